@@ -0,0 +1,39 @@
+//! Builds the rarity Merkle tree of a collection from a CSV of `mint,multiplier_bps`
+//! lines and prints its root followed by every mint's multiplier and proof.
+//!
+//! `cargo run --example rarity_tree -- rarity.csv`
+
+use farm::merkle::RarityTree;
+use solana_program::hash::Hash;
+use std::{env, fs, process};
+
+fn main() {
+    let path = env::args().nth(1).unwrap_or_else(|| {
+        eprintln!("usage: rarity_tree <csv>");
+        process::exit(2);
+    });
+    let csv = fs::read_to_string(&path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        process::exit(1);
+    });
+    let tree = RarityTree::from_csv(&csv).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        process::exit(1);
+    });
+
+    println!("root {}", Hash::new_from_array(tree.root()));
+    for entry in tree.entries() {
+        let proof = tree.proof(&entry.mint).unwrap();
+        let hashes = proof
+            .proof
+            .iter()
+            .map(|hash| Hash::new_from_array(*hash).to_string())
+            .collect::<Vec<_>>();
+        println!(
+            "{} {} {}",
+            entry.mint,
+            proof.multiplier_bps,
+            hashes.join(",")
+        );
+    }
+}
@@ -39,6 +39,57 @@ pub enum FarmError {
 
     #[error("Numeric overflow // underflow ")]
     NumericOverflow,
+
+    #[error("Signer is not the platform owner ")]
+    InvalidAuthority,
+
+    #[error("Metadata account is not the Metaplex metadata of the mint ")]
+    InvalidMetadataAccount,
+
+    #[error("NFT is not a verified member of the platform collection ")]
+    CollectionNotAllowed,
+
+    #[error("Mint is not a non-fungible token ")]
+    NotAnNft,
+
+    #[error("Account is not writable ")]
+    AccountNotWritable,
+
+    #[error("Token account has a delegate or close authority ")]
+    TokenAccountHasAuthority,
+
+    #[error("Mint freeze authority is neither the platform PDA nor the master edition ")]
+    CannotFreeze,
+
+    #[error("NFT is still in its lock period ")]
+    StakeLocked,
+
+    #[error("Lock tier is not offered by the platform ")]
+    InvalidLockTier,
+
+    #[error("Rarity proof does not match the platform's rarity root ")]
+    InvalidRarityProof,
+
+    #[error("Withdrawal would leave less than the rewards owed to stakers ")]
+    RewardsOwed,
+
+    #[error("Token account is not the platform's reward vault ")]
+    InvalidRewardVault,
+
+    #[error("Platform is paused ")]
+    PlatformPaused,
+
+    #[error("Platform is not paused ")]
+    PlatformNotPaused,
+
+    #[error("NFTs are still staked ")]
+    StillStaked,
+
+    #[error("Reward vault still holds tokens ")]
+    RewardVaultNotEmpty,
+
+    #[error("Account passed more than once ")]
+    DuplicateAccount,
 }
 impl From<FarmError> for ProgramError {
     fn from(e: FarmError) -> Self {
@@ -1,25 +1,244 @@
 #![allow(clippy::too_many_arguments)]
 
+use crate::{
+    merkle::MAX_PROOF_LEN,
+    metadata::{find_edition_address, find_metadata_address, mpl_token_metadata},
+    state::{
+        find_escrow_address, find_rarity_address, find_stake_record_address,
+        find_user_state_address, find_vault_address, CollectionRule, CustodyMode, LockConfig,
+        LockTier, RewardMode, MAX_ALLOWED_CREATORS, MAX_LOCK_TIERS,
+    },
+};
+use arrayref::array_ref;
+use num_traits::FromPrimitive;
 use solana_program::{
     instruction::{AccountMeta, Instruction},
-    msg,
     program_error::ProgramError,
     pubkey::Pubkey,
+    system_program,
 };
 use std::mem;
 
+/// Maximum number of mints a single `SetRarity` can cover, so that a full batch with its
+/// rarity accounts still fits in one transaction.
+pub const MAX_RARITY_ENTRIES: usize = 12;
+
+/// Maximum number of NFTs a single `StakeMany` can stake, on a `CustodyMode::Authority`
+/// platform. A full batch serializes to 1196 bytes, one more NFT would not fit the 1232
+/// bytes of a transaction. Other platforms take fewer, see `max_stake_many`.
+pub const MAX_STAKE_MANY: usize = 5;
+
+/// Maximum number of NFTs a single `UnstakeMany` can unstake, on a `CustodyMode::Authority`
+/// platform. A full batch serializes to 1228 bytes. Other platforms take fewer, see
+/// `max_unstake_many`.
+pub const MAX_UNSTAKE_MANY: usize = 8;
+
+/// Maximum number of NFTs a single `StakeMany` can stake on a platform with
+/// `custody_mode`. Escrow and frozen custody pass an escrow or master edition with each
+/// NFT, which leaves room for 4 of them at 1163 bytes.
+pub fn max_stake_many(custody_mode: CustodyMode) -> usize {
+    match custody_mode {
+        CustodyMode::Authority => MAX_STAKE_MANY,
+        CustodyMode::Escrow | CustodyMode::Frozen => 4,
+    }
+}
+
+/// Maximum number of NFTs a single `UnstakeMany` can unstake on a platform with
+/// `custody_mode`, 6 at 1228 bytes under escrow and frozen custody, see `max_stake_many`.
+pub fn max_unstake_many(custody_mode: CustodyMode) -> usize {
+    match custody_mode {
+        CustodyMode::Authority => MAX_UNSTAKE_MANY,
+        CustodyMode::Escrow | CustodyMode::Frozen => 6,
+    }
+}
+
+/// Maximum number of stake records a single `HarvestAll` can harvest. A full batch
+/// serializes to 1129 bytes, which leaves room in the 1232 bytes of a transaction for a
+/// `SetComputeUnitLimit` instruction next to it, and 13 records would not.
+pub const MAX_HARVEST_ALL: usize = 12;
+
+/// One mint's rarity multiplier in a `SetRarity` batch.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RarityEntry {
+    pub mint: Pubkey,
+    /// Reward multiplier in basis points, 10_000 for 1x.
+    pub multiplier_bps: u16,
+}
+
+/// A mint's rarity multiplier with the Merkle proof of it against the platform's rarity
+/// root, see `merkle`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RarityProof {
+    /// Reward multiplier in basis points, 10_000 for 1x.
+    pub multiplier_bps: u16,
+    /// Sibling hashes from the leaf up, at most `MAX_PROOF_LEN`.
+    pub proof: Vec<[u8; 32]>,
+}
+
+/// What `QueryPendingRewards` returns, packed as two little endian `u64`s.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PendingRewards {
+    /// Everything the queried records earned and were not paid yet.
+    pub pending: u64,
+    /// The part of `pending` a `Harvest` would pay right now, leaving out records still in
+    /// a lock period and everything while the platform is paused.
+    pub claimable: u64,
+}
+
+impl PendingRewards {
+    pub const LEN: usize = 16;
+
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        let (pending, rest) = NftInstruction::unpack_u64(input)?;
+        let (claimable, _) = NftInstruction::unpack_u64(rest)?;
+        Ok(Self { pending, claimable })
+    }
+
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::LEN);
+        buf.extend_from_slice(&self.pending.to_le_bytes());
+        buf.extend_from_slice(&self.claimable.to_le_bytes());
+        buf
+    }
+}
+
+/// Instructions of the farm program.
+///
+/// Each variant is encoded as a one byte tag followed by its fields in little endian.
+/// Tags are part of the on-chain interface and never change once assigned; tag `1` is
+/// reserved and must not be handed out to a new instruction.
 #[repr(C)]
 #[derive(Clone, Debug, PartialEq)]
 pub enum NftInstruction {
     // Init
-    InitializePlatform { amount: u64 },
+    /// Tag `0`.
+    ///
+    /// `reward_rate` is in reward base units per second, so it has to be scaled by the
+    /// reward mint's decimals by the caller. Under `RewardMode::PerNft` every staked NFT
+    /// earns it; under `RewardMode::Emission` it is the pool's total emission, shared
+    /// between whatever is staked at the time. `custody_mode` picks how staked NFTs are
+    /// held, see `CustodyMode`, and `lock` when they may leave again. `collection_rule` picks whether
+    /// stakeable NFTs need a verified membership of `collection` or one of `creators`
+    /// (at most `MAX_ALLOWED_CREATORS`) as a verified creator.
+    InitializePlatform {
+        amount: u64,
+        reward_rate: u64,
+        reward_mode: RewardMode,
+        custody_mode: CustodyMode,
+        lock: LockConfig,
+        collection_rule: CollectionRule,
+        collection: Pubkey,
+        creators: Vec<Pubkey>,
+    },
 
-    StakeNft,
+    /// Tag `2`, optionally followed by the lock tier byte. A missing byte picks tier 0,
+    /// which neither locks the NFT nor boosts its rewards.
+    ///
+    /// The tier may be followed by a rarity proof: the multiplier, a count byte and that
+    /// many sibling hashes. A proven multiplier is used instead of the mint's rarity
+    /// account and stays cached on the stake record.
+    StakeNft {
+        lock_tier: u8,
+        rarity: Option<RarityProof>,
+    },
 
     //Unstake
+    /// Tag `3`.
     UnStakeNft,
 
+    /// Tag `4`.
     Harvest,
+
+    // Admin
+    /// Tag `5`.
+    UpdateRewardRate { reward_rate: u64 },
+
+    /// Tag `6`. Replaces the platform's lock tiers, at most `MAX_LOCK_TIERS`, encoded as a
+    /// count byte followed by each tier's duration and multiplier. Stakes already made
+    /// keep the tier they were made with.
+    SetLockTiers { tiers: Vec<LockTier> },
+
+    /// Tag `7`. Sets the rarity multiplier of up to `MAX_RARITY_ENTRIES` mints, encoded as a
+    /// count byte followed by each mint and multiplier. Expects one rarity account per
+    /// entry, in the same order. NFTs already staked pick up the new multiplier at their
+    /// next harvest.
+    SetRarity { entries: Vec<RarityEntry> },
+
+    /// Tag `8`. Sets the Merkle root rarity proofs passed to `StakeNft` are checked against.
+    /// An all-zero root disables proofs.
+    SetRarityRoot { root: [u8; 32] },
+
+    /// Tag `9`. Moves `amount` reward tokens from any signer into the reward vault.
+    TopUpRewards { amount: u64 },
+
+    /// Tag `10`. Moves `amount` reward tokens from the vault back to the platform owner, as
+    /// long as what stays behind covers the rewards owed to stakers.
+    WithdrawRewards { amount: u64 },
+
+    /// Tag `11`, followed by `1` to pause the platform or `0` to resume it. Rewards stop
+    /// accruing while it is paused.
+    SetPaused { paused: bool },
+
+    /// Tag `12`. Returns a staked NFT while the platform is paused without touching its
+    /// rewards, which are forfeited. Lock periods don't apply.
+    EmergencyUnstake,
+
+    /// Tag `13`, followed by the proposed owner. Only takes effect once that key signs
+    /// `AcceptOwner`; proposing `Pubkey::default()` cancels a pending proposal.
+    ProposeOwner { new_owner: Pubkey },
+
+    /// Tag `14`. Signed by the pending owner to take over the platform.
+    AcceptOwner,
+
+    /// Tag `15`. Leaves the platform without an owner for good, freezing its configuration.
+    RenounceOwnership,
+
+    /// Tag `16`. Creates the signer's user state on the platform. `StakeNft` still does so
+    /// on first use, so this is only needed to set it up ahead of time.
+    InitializeUser,
+
+    /// Tag `17`. Closes the signer's user state once nothing is staked through it, returning
+    /// its rent. Also works after the platform itself was closed.
+    CloseUserState,
+
+    /// Tag `18`. Closes the platform and its reward vault, returning their rent to the owner.
+    /// Only allowed once nothing is staked and the vault is empty.
+    ClosePlatform,
+
+    /// Tag `19`, followed by `1` to close user states automatically when their last NFT is
+    /// unstaked or `0` to keep them.
+    SetAutoCloseUserState { enabled: bool },
+
+    /// Tag `20`, followed by the number of NFTs, 1 to `max_stake_many` of the platform's
+    /// custody mode, and the lock tier they all get.
+    ///
+    /// Takes the user, user state, platform, vault PDA, token program, system program and
+    /// token metadata program, then for each NFT its token account, mint, stake record,
+    /// metadata and rarity account, followed by its escrow under `CustodyMode::Escrow` or
+    /// its master edition under `CustodyMode::Frozen`. Rarity always comes from the rarity
+    /// accounts. Any NFT failing fails the whole batch.
+    StakeMany { count: u8, lock_tier: u8 },
+
+    /// Tag `21`, followed by the number of NFTs, 1 to `max_unstake_many` of the platform's
+    /// custody mode.
+    ///
+    /// Takes the user, user state, platform, vault PDA, token program, system program, the
+    /// user's reward account, the reward vault and the token metadata program, then for
+    /// each NFT its token account, mint and stake record, followed by its escrow or master
+    /// edition as for `StakeMany`. The rewards of all of them are paid in one transfer.
+    UnstakeMany { count: u8 },
+
+    /// Tag `22`, followed by the number of stake records, 1 to `MAX_HARVEST_ALL`.
+    ///
+    /// Takes the user, platform, the user's reward account, the reward vault, vault PDA
+    /// and token program, then each stake record with the rarity account of its mint, as
+    /// for `Harvest`. Their rewards are paid in one transfer.
+    HarvestAll { count: u8 },
+
+    /// Tag `23`. Takes the platform followed by any number of its stake records, each
+    /// passed once, and returns their `PendingRewards` through the return data. Runs the
+    /// same settlement as `Harvest` but writes nothing, so clients can simulate it.
+    QueryPendingRewards,
 }
 
 impl NftInstruction {
@@ -29,12 +248,164 @@ impl NftInstruction {
             .ok_or(ProgramError::InvalidAccountData)?;
 
         Ok(match tag {
-            0 => Self::InitializePlatform {
-                amount: Self::unpack_amount(rest)?,
-            },
-            2 => Self::StakeNft,
+            0 => {
+                let (amount, rest) = Self::unpack_u64(rest)?;
+                let (reward_rate, rest) = Self::unpack_u64(rest)?;
+                let (&reward_mode, rest) =
+                    rest.split_first().ok_or(ProgramError::InvalidAccountData)?;
+                let reward_mode =
+                    RewardMode::from_u8(reward_mode).ok_or(ProgramError::InvalidAccountData)?;
+                let (&custody_mode, rest) =
+                    rest.split_first().ok_or(ProgramError::InvalidAccountData)?;
+                let custody_mode =
+                    CustodyMode::from_u8(custody_mode).ok_or(ProgramError::InvalidAccountData)?;
+                let (hard_lock_duration, rest) = Self::unpack_u64(rest)?;
+                let (soft_lock_duration, rest) = Self::unpack_u64(rest)?;
+                let (early_unstake_penalty_bps, rest) = Self::unpack_u16(rest)?;
+                let lock = LockConfig {
+                    hard_lock_duration,
+                    soft_lock_duration,
+                    early_unstake_penalty_bps,
+                };
+                let (&collection_rule, rest) =
+                    rest.split_first().ok_or(ProgramError::InvalidAccountData)?;
+                let collection_rule = CollectionRule::from_u8(collection_rule)
+                    .ok_or(ProgramError::InvalidAccountData)?;
+                let (collection, rest) = Self::unpack_pubkey(rest)?;
+                let (&creator_count, mut rest) =
+                    rest.split_first().ok_or(ProgramError::InvalidAccountData)?;
+                if creator_count as usize > MAX_ALLOWED_CREATORS {
+                    return Err(ProgramError::InvalidAccountData);
+                }
+                let mut creators = Vec::with_capacity(creator_count as usize);
+                for _ in 0..creator_count {
+                    let (creator, next) = Self::unpack_pubkey(rest)?;
+                    creators.push(creator);
+                    rest = next;
+                }
+                Self::InitializePlatform {
+                    amount,
+                    reward_rate,
+                    reward_mode,
+                    custody_mode,
+                    lock,
+                    collection_rule,
+                    collection,
+                    creators,
+                }
+            }
+            2 => {
+                let (lock_tier, rest) = match rest.split_first() {
+                    Some((&lock_tier, rest)) => (lock_tier, rest),
+                    None => (0, rest),
+                };
+                let rarity = if rest.is_empty() {
+                    None
+                } else {
+                    let (multiplier_bps, rest) = Self::unpack_u16(rest)?;
+                    let (&count, mut rest) =
+                        rest.split_first().ok_or(ProgramError::InvalidAccountData)?;
+                    if count as usize > MAX_PROOF_LEN {
+                        return Err(ProgramError::InvalidAccountData);
+                    }
+                    let mut proof = Vec::with_capacity(count as usize);
+                    for _ in 0..count {
+                        let (hash, next) = Self::unpack_hash(rest)?;
+                        proof.push(hash);
+                        rest = next;
+                    }
+                    Some(RarityProof {
+                        multiplier_bps,
+                        proof,
+                    })
+                };
+                Self::StakeNft { lock_tier, rarity }
+            }
             3 => Self::UnStakeNft,
             4 => Self::Harvest,
+            5 => Self::UpdateRewardRate {
+                reward_rate: Self::unpack_u64(rest)?.0,
+            },
+            6 => {
+                let (&count, mut rest) =
+                    rest.split_first().ok_or(ProgramError::InvalidAccountData)?;
+                if count as usize > MAX_LOCK_TIERS {
+                    return Err(ProgramError::InvalidAccountData);
+                }
+                let mut tiers = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    let (duration, next) = Self::unpack_u64(rest)?;
+                    let (multiplier_bps, next) = Self::unpack_u16(next)?;
+                    tiers.push(LockTier {
+                        duration,
+                        multiplier_bps,
+                    });
+                    rest = next;
+                }
+                Self::SetLockTiers { tiers }
+            }
+            7 => {
+                let (&count, mut rest) =
+                    rest.split_first().ok_or(ProgramError::InvalidAccountData)?;
+                if count as usize > MAX_RARITY_ENTRIES {
+                    return Err(ProgramError::InvalidAccountData);
+                }
+                let mut entries = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    let (mint, next) = Self::unpack_pubkey(rest)?;
+                    let (multiplier_bps, next) = Self::unpack_u16(next)?;
+                    entries.push(RarityEntry {
+                        mint,
+                        multiplier_bps,
+                    });
+                    rest = next;
+                }
+                Self::SetRarity { entries }
+            }
+            8 => Self::SetRarityRoot {
+                root: Self::unpack_hash(rest)?.0,
+            },
+            9 => Self::TopUpRewards {
+                amount: Self::unpack_u64(rest)?.0,
+            },
+            10 => Self::WithdrawRewards {
+                amount: Self::unpack_u64(rest)?.0,
+            },
+            11 => Self::SetPaused {
+                paused: match rest.first() {
+                    Some(0) => false,
+                    Some(1) => true,
+                    _ => return Err(ProgramError::InvalidAccountData),
+                },
+            },
+            12 => Self::EmergencyUnstake,
+            13 => Self::ProposeOwner {
+                new_owner: Self::unpack_pubkey(rest)?.0,
+            },
+            14 => Self::AcceptOwner,
+            15 => Self::RenounceOwnership,
+            16 => Self::InitializeUser,
+            17 => Self::CloseUserState,
+            18 => Self::ClosePlatform,
+            19 => Self::SetAutoCloseUserState {
+                enabled: match rest.first() {
+                    Some(0) => false,
+                    Some(1) => true,
+                    _ => return Err(ProgramError::InvalidAccountData),
+                },
+            },
+            20 => {
+                let count = Self::unpack_batch_count(rest, MAX_STAKE_MANY)?;
+                let lock_tier = *rest.get(1).ok_or(ProgramError::InvalidAccountData)?;
+                Self::StakeMany { count, lock_tier }
+            }
+            21 => Self::UnstakeMany {
+                count: Self::unpack_batch_count(rest, MAX_UNSTAKE_MANY)?,
+            },
+            22 => Self::HarvestAll {
+                count: Self::unpack_batch_count(rest, MAX_HARVEST_ALL)?,
+            },
+            23 => Self::QueryPendingRewards,
 
             _ => return Err(ProgramError::InvalidAccountData),
         })
@@ -42,23 +413,1054 @@ impl NftInstruction {
 
     pub fn pack(&self) -> Vec<u8> {
         let mut buf = Vec::with_capacity(mem::size_of::<Self>());
-        match &*self {
-            Self::InitializePlatform { amount } => {
+        match self {
+            Self::InitializePlatform {
+                amount,
+                reward_rate,
+                reward_mode,
+                custody_mode,
+                lock,
+                collection_rule,
+                collection,
+                creators,
+            } => {
                 buf.push(0);
                 buf.extend_from_slice(&amount.to_le_bytes());
+                buf.extend_from_slice(&reward_rate.to_le_bytes());
+                buf.push(*reward_mode as u8);
+                buf.push(*custody_mode as u8);
+                buf.extend_from_slice(&lock.hard_lock_duration.to_le_bytes());
+                buf.extend_from_slice(&lock.soft_lock_duration.to_le_bytes());
+                buf.extend_from_slice(&lock.early_unstake_penalty_bps.to_le_bytes());
+                buf.push(*collection_rule as u8);
+                buf.extend_from_slice(collection.as_ref());
+                buf.push(creators.len() as u8);
+                for creator in creators {
+                    buf.extend_from_slice(creator.as_ref());
+                }
             }
-
-            _ => todo!(),
+            Self::StakeNft { lock_tier, rarity } => {
+                buf.push(2);
+                buf.push(*lock_tier);
+                if let Some(rarity) = rarity {
+                    buf.extend_from_slice(&rarity.multiplier_bps.to_le_bytes());
+                    buf.push(rarity.proof.len() as u8);
+                    for hash in &rarity.proof {
+                        buf.extend_from_slice(hash);
+                    }
+                }
+            }
+            Self::UnStakeNft => buf.push(3),
+            Self::Harvest => buf.push(4),
+            Self::UpdateRewardRate { reward_rate } => {
+                buf.push(5);
+                buf.extend_from_slice(&reward_rate.to_le_bytes());
+            }
+            Self::SetLockTiers { tiers } => {
+                buf.push(6);
+                buf.push(tiers.len() as u8);
+                for tier in tiers {
+                    buf.extend_from_slice(&tier.duration.to_le_bytes());
+                    buf.extend_from_slice(&tier.multiplier_bps.to_le_bytes());
+                }
+            }
+            Self::SetRarity { entries } => {
+                buf.push(7);
+                buf.push(entries.len() as u8);
+                for entry in entries {
+                    buf.extend_from_slice(entry.mint.as_ref());
+                    buf.extend_from_slice(&entry.multiplier_bps.to_le_bytes());
+                }
+            }
+            Self::SetRarityRoot { root } => {
+                buf.push(8);
+                buf.extend_from_slice(root);
+            }
+            Self::TopUpRewards { amount } => {
+                buf.push(9);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::WithdrawRewards { amount } => {
+                buf.push(10);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::SetPaused { paused } => {
+                buf.push(11);
+                buf.push(*paused as u8);
+            }
+            Self::EmergencyUnstake => buf.push(12),
+            Self::ProposeOwner { new_owner } => {
+                buf.push(13);
+                buf.extend_from_slice(new_owner.as_ref());
+            }
+            Self::AcceptOwner => buf.push(14),
+            Self::RenounceOwnership => buf.push(15),
+            Self::InitializeUser => buf.push(16),
+            Self::CloseUserState => buf.push(17),
+            Self::ClosePlatform => buf.push(18),
+            Self::SetAutoCloseUserState { enabled } => {
+                buf.push(19);
+                buf.push(*enabled as u8);
+            }
+            Self::StakeMany { count, lock_tier } => {
+                buf.push(20);
+                buf.push(*count);
+                buf.push(*lock_tier);
+            }
+            Self::UnstakeMany { count } => {
+                buf.push(21);
+                buf.push(*count);
+            }
+            Self::HarvestAll { count } => {
+                buf.push(22);
+                buf.push(*count);
+            }
+            Self::QueryPendingRewards => buf.push(23),
         }
         buf
     }
 
-    fn unpack_amount(input: &[u8]) -> Result<u64, ProgramError> {
-        let amount = input
+    fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
+        let value = input
             .get(..8)
             .and_then(|slice| slice.try_into().ok())
             .map(u64::from_le_bytes)
             .ok_or(ProgramError::InvalidAccountData)?;
-        Ok(amount)
+        Ok((value, &input[8..]))
+    }
+
+    fn unpack_u16(input: &[u8]) -> Result<(u16, &[u8]), ProgramError> {
+        let value = input
+            .get(..2)
+            .and_then(|slice| slice.try_into().ok())
+            .map(u16::from_le_bytes)
+            .ok_or(ProgramError::InvalidAccountData)?;
+        Ok((value, &input[2..]))
+    }
+
+    fn unpack_batch_count(input: &[u8], max: usize) -> Result<u8, ProgramError> {
+        match input.first() {
+            Some(&count) if count > 0 && count as usize <= max => Ok(count),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    fn unpack_pubkey(input: &[u8]) -> Result<(Pubkey, &[u8]), ProgramError> {
+        if input.len() < 32 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let (key, rest) = input.split_at(32);
+        Ok((Pubkey::new(key), rest))
+    }
+
+    fn unpack_hash(input: &[u8]) -> Result<([u8; 32], &[u8]), ProgramError> {
+        if input.len() < 32 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let (hash, rest) = input.split_at(32);
+        Ok((*array_ref![hash, 0, 32], rest))
+    }
+}
+
+/// Creates an `InitializePlatform` instruction. `platform_state` is a new keypair account
+/// and `reward_vault` a token account of the reward mint owned by the platform PDA.
+pub fn initialize_platform(
+    program_id: &Pubkey,
+    platform_state: &Pubkey,
+    owner: &Pubkey,
+    owner_reward_account: &Pubkey,
+    reward_vault: &Pubkey,
+    amount: u64,
+    reward_rate: u64,
+    reward_mode: RewardMode,
+    custody_mode: CustodyMode,
+    lock: LockConfig,
+    collection_rule: CollectionRule,
+    collection: &Pubkey,
+    creators: &[Pubkey],
+) -> Instruction {
+    let data = NftInstruction::InitializePlatform {
+        amount,
+        reward_rate,
+        reward_mode,
+        custody_mode,
+        lock,
+        collection_rule,
+        collection: *collection,
+        creators: creators.to_vec(),
+    }
+    .pack();
+    let (vault, _) = find_vault_address(program_id, platform_state);
+
+    let accounts = vec![
+        AccountMeta::new(*platform_state, true),
+        AccountMeta::new(*owner, true),
+        AccountMeta::new(*owner_reward_account, false),
+        AccountMeta::new(*reward_vault, false),
+        AccountMeta::new_readonly(vault, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    }
+}
+
+/// Creates an `InitializeUser` instruction for `user`, who pays for the user state.
+pub fn initialize_user(program_id: &Pubkey, user: &Pubkey, platform_state: &Pubkey) -> Instruction {
+    let (user_state, _) = find_user_state_address(program_id, platform_state, user);
+
+    let accounts = vec![
+        AccountMeta::new(*user, true),
+        AccountMeta::new(user_state, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(*platform_state, false),
+    ];
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::InitializeUser.pack(),
+    }
+}
+
+/// Creates a `CloseUserState` instruction returning the rent of `user`'s user state.
+pub fn close_user_state(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    platform_state: &Pubkey,
+) -> Instruction {
+    let (user_state, _) = find_user_state_address(program_id, platform_state, user);
+
+    let accounts = vec![
+        AccountMeta::new(*user, true),
+        AccountMeta::new(user_state, false),
+        AccountMeta::new_readonly(*platform_state, false),
+    ];
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::CloseUserState.pack(),
+    }
+}
+
+/// Creates a `StakeNft` instruction for the NFT of `mint` held in `token_account`, locked
+/// in `lock_tier`. A `rarity` proof, e.g. from `merkle::RarityTree::proof`, takes the
+/// place of the mint's rarity account.
+///
+/// The trailing escrow, edition and token metadata program accounts are only used by
+/// `CustodyMode::Escrow` and `CustodyMode::Frozen` platforms respectively.
+pub fn stake_nft(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    platform_state: &Pubkey,
+    token_account: &Pubkey,
+    mint: &Pubkey,
+    lock_tier: u8,
+    rarity: Option<RarityProof>,
+) -> Instruction {
+    let (user_state, _) = find_user_state_address(program_id, platform_state, user);
+    let (stake_record, _) = find_stake_record_address(program_id, platform_state, mint);
+    let (metadata, _) = find_metadata_address(mint);
+    let (vault, _) = find_vault_address(program_id, platform_state);
+    let (escrow, _) = find_escrow_address(program_id, platform_state, mint);
+    let (edition, _) = find_edition_address(mint);
+    let (rarity_account, _) = find_rarity_address(program_id, platform_state, mint);
+
+    let accounts = vec![
+        AccountMeta::new(*user, true),
+        AccountMeta::new(user_state, false),
+        AccountMeta::new(stake_record, false),
+        AccountMeta::new(*platform_state, false),
+        AccountMeta::new(*token_account, false),
+        AccountMeta::new_readonly(*mint, false),
+        AccountMeta::new_readonly(metadata, false),
+        AccountMeta::new_readonly(vault, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(rarity_account, false),
+        AccountMeta::new(escrow, false),
+        AccountMeta::new_readonly(edition, false),
+        AccountMeta::new_readonly(mpl_token_metadata::id(), false),
+    ];
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::StakeNft { lock_tier, rarity }.pack(),
+    }
+}
+
+/// Creates an `UnStakeNft` instruction returning the NFT of `mint` to `token_account`, the
+/// account it was staked from.
+pub fn unstake_nft(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    platform_state: &Pubkey,
+    token_account: &Pubkey,
+    mint: &Pubkey,
+    user_reward_account: &Pubkey,
+    reward_vault: &Pubkey,
+) -> Instruction {
+    let (user_state, _) = find_user_state_address(program_id, platform_state, user);
+    let (stake_record, _) = find_stake_record_address(program_id, platform_state, mint);
+    let (vault, _) = find_vault_address(program_id, platform_state);
+    let (escrow, _) = find_escrow_address(program_id, platform_state, mint);
+    let (edition, _) = find_edition_address(mint);
+
+    let accounts = vec![
+        AccountMeta::new(*user, true),
+        AccountMeta::new(user_state, false),
+        AccountMeta::new(stake_record, false),
+        AccountMeta::new(*platform_state, false),
+        AccountMeta::new(*token_account, false),
+        AccountMeta::new_readonly(*mint, false),
+        AccountMeta::new_readonly(vault, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new(*user_reward_account, false),
+        AccountMeta::new(*reward_vault, false),
+        AccountMeta::new(escrow, false),
+        AccountMeta::new_readonly(edition, false),
+        AccountMeta::new_readonly(mpl_token_metadata::id(), false),
+    ];
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::UnStakeNft.pack(),
+    }
+}
+
+/// The account following an NFT of a `StakeMany` or `UnstakeMany` batch on a platform
+/// with `custody_mode`, if any.
+fn custody_account(
+    program_id: &Pubkey,
+    platform_state: &Pubkey,
+    mint: &Pubkey,
+    custody_mode: CustodyMode,
+) -> Option<AccountMeta> {
+    match custody_mode {
+        CustodyMode::Authority => None,
+        CustodyMode::Escrow => Some(AccountMeta::new(
+            find_escrow_address(program_id, platform_state, mint).0,
+            false,
+        )),
+        CustodyMode::Frozen => Some(AccountMeta::new_readonly(
+            find_edition_address(mint).0,
+            false,
+        )),
+    }
+}
+
+/// Creates a `StakeMany` instruction staking every `(token_account, mint)` of `nfts` in
+/// `lock_tier`. `custody_mode` has to be the platform's, and `nfts` at most
+/// `max_stake_many` of it.
+pub fn stake_many(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    platform_state: &Pubkey,
+    nfts: &[(Pubkey, Pubkey)],
+    lock_tier: u8,
+    custody_mode: CustodyMode,
+) -> Instruction {
+    let (user_state, _) = find_user_state_address(program_id, platform_state, user);
+    let (vault, _) = find_vault_address(program_id, platform_state);
+
+    let mut accounts = vec![
+        AccountMeta::new(*user, true),
+        AccountMeta::new(user_state, false),
+        AccountMeta::new(*platform_state, false),
+        AccountMeta::new_readonly(vault, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(mpl_token_metadata::id(), false),
+    ];
+    for (token_account, mint) in nfts {
+        let (stake_record, _) = find_stake_record_address(program_id, platform_state, mint);
+        let (metadata, _) = find_metadata_address(mint);
+        let (rarity, _) = find_rarity_address(program_id, platform_state, mint);
+        accounts.extend([
+            AccountMeta::new(*token_account, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(stake_record, false),
+            AccountMeta::new_readonly(metadata, false),
+            AccountMeta::new_readonly(rarity, false),
+        ]);
+        accounts.extend(custody_account(
+            program_id,
+            platform_state,
+            mint,
+            custody_mode,
+        ));
+    }
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::StakeMany {
+            count: nfts.len() as u8,
+            lock_tier,
+        }
+        .pack(),
+    }
+}
+
+/// Creates an `UnstakeMany` instruction returning every `(token_account, mint)` of `nfts`.
+/// `custody_mode` has to be the platform's, and `nfts` at most `max_unstake_many` of it.
+pub fn unstake_many(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    platform_state: &Pubkey,
+    nfts: &[(Pubkey, Pubkey)],
+    user_reward_account: &Pubkey,
+    reward_vault: &Pubkey,
+    custody_mode: CustodyMode,
+) -> Instruction {
+    let (user_state, _) = find_user_state_address(program_id, platform_state, user);
+    let (vault, _) = find_vault_address(program_id, platform_state);
+
+    let mut accounts = vec![
+        AccountMeta::new(*user, true),
+        AccountMeta::new(user_state, false),
+        AccountMeta::new(*platform_state, false),
+        AccountMeta::new_readonly(vault, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new(*user_reward_account, false),
+        AccountMeta::new(*reward_vault, false),
+        AccountMeta::new_readonly(mpl_token_metadata::id(), false),
+    ];
+    for (token_account, mint) in nfts {
+        let (stake_record, _) = find_stake_record_address(program_id, platform_state, mint);
+        accounts.extend([
+            AccountMeta::new(*token_account, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(stake_record, false),
+        ]);
+        accounts.extend(custody_account(
+            program_id,
+            platform_state,
+            mint,
+            custody_mode,
+        ));
+    }
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::UnstakeMany {
+            count: nfts.len() as u8,
+        }
+        .pack(),
+    }
+}
+
+/// Creates an `EmergencyUnstake` instruction returning the NFT of `mint` to
+/// `token_account` while the platform is paused.
+pub fn emergency_unstake(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    platform_state: &Pubkey,
+    token_account: &Pubkey,
+    mint: &Pubkey,
+) -> Instruction {
+    let (user_state, _) = find_user_state_address(program_id, platform_state, user);
+    let (stake_record, _) = find_stake_record_address(program_id, platform_state, mint);
+    let (vault, _) = find_vault_address(program_id, platform_state);
+    let (escrow, _) = find_escrow_address(program_id, platform_state, mint);
+    let (edition, _) = find_edition_address(mint);
+
+    let accounts = vec![
+        AccountMeta::new(*user, true),
+        AccountMeta::new(user_state, false),
+        AccountMeta::new(stake_record, false),
+        AccountMeta::new(*platform_state, false),
+        AccountMeta::new(*token_account, false),
+        AccountMeta::new_readonly(*mint, false),
+        AccountMeta::new_readonly(vault, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new(escrow, false),
+        AccountMeta::new_readonly(edition, false),
+        AccountMeta::new_readonly(mpl_token_metadata::id(), false),
+    ];
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::EmergencyUnstake.pack(),
+    }
+}
+
+/// Creates a `HarvestAll` instruction paying the rewards of every one of `mints` to
+/// `user_reward_account`.
+pub fn harvest_all(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    platform_state: &Pubkey,
+    mints: &[Pubkey],
+    user_reward_account: &Pubkey,
+    reward_vault: &Pubkey,
+) -> Instruction {
+    let (vault, _) = find_vault_address(program_id, platform_state);
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*user, true),
+        AccountMeta::new(*platform_state, false),
+        AccountMeta::new(*user_reward_account, false),
+        AccountMeta::new(*reward_vault, false),
+        AccountMeta::new_readonly(vault, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+    for mint in mints {
+        let (stake_record, _) = find_stake_record_address(program_id, platform_state, mint);
+        let (rarity, _) = find_rarity_address(program_id, platform_state, mint);
+        accounts.extend([
+            AccountMeta::new(stake_record, false),
+            AccountMeta::new_readonly(rarity, false),
+        ]);
+    }
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::HarvestAll {
+            count: mints.len() as u8,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `QueryPendingRewards` instruction for the stake records of `mints`, meant to
+/// be simulated.
+pub fn query_pending_rewards(
+    program_id: &Pubkey,
+    platform_state: &Pubkey,
+    mints: &[Pubkey],
+) -> Instruction {
+    let mut accounts = vec![AccountMeta::new_readonly(*platform_state, false)];
+    accounts.extend(mints.iter().map(|mint| {
+        let (stake_record, _) = find_stake_record_address(program_id, platform_state, mint);
+        AccountMeta::new_readonly(stake_record, false)
+    }));
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::QueryPendingRewards.pack(),
+    }
+}
+
+/// Creates a `Harvest` instruction paying the rewards of the staked NFT of `mint`.
+pub fn harvest(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    platform_state: &Pubkey,
+    mint: &Pubkey,
+    user_reward_account: &Pubkey,
+    reward_vault: &Pubkey,
+) -> Instruction {
+    let (stake_record, _) = find_stake_record_address(program_id, platform_state, mint);
+    let (vault, _) = find_vault_address(program_id, platform_state);
+    let (rarity, _) = find_rarity_address(program_id, platform_state, mint);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*user, true),
+        AccountMeta::new(stake_record, false),
+        AccountMeta::new(*platform_state, false),
+        AccountMeta::new(*user_reward_account, false),
+        AccountMeta::new(*reward_vault, false),
+        AccountMeta::new_readonly(vault, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(rarity, false),
+    ];
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::Harvest.pack(),
+    }
+}
+
+/// Creates an `UpdateRewardRate` instruction signed by the platform owner.
+pub fn update_reward_rate(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    platform_state: &Pubkey,
+    reward_rate: u64,
+) -> Instruction {
+    let accounts = vec![
+        AccountMeta::new_readonly(*owner, true),
+        AccountMeta::new(*platform_state, false),
+    ];
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::UpdateRewardRate { reward_rate }.pack(),
+    }
+}
+
+/// Creates a `SetLockTiers` instruction signed by the platform owner.
+pub fn set_lock_tiers(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    platform_state: &Pubkey,
+    tiers: &[LockTier],
+) -> Instruction {
+    let accounts = vec![
+        AccountMeta::new_readonly(*owner, true),
+        AccountMeta::new(*platform_state, false),
+    ];
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::SetLockTiers {
+            tiers: tiers.to_vec(),
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `TopUpRewards` instruction moving `amount` from `source`, a reward token
+/// account of `depositor`, into the reward vault.
+pub fn top_up_rewards(
+    program_id: &Pubkey,
+    depositor: &Pubkey,
+    source: &Pubkey,
+    platform_state: &Pubkey,
+    reward_vault: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let (vault, _) = find_vault_address(program_id, platform_state);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*depositor, true),
+        AccountMeta::new(*source, false),
+        AccountMeta::new(*platform_state, false),
+        AccountMeta::new(*reward_vault, false),
+        AccountMeta::new_readonly(vault, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::TopUpRewards { amount }.pack(),
+    }
+}
+
+/// Creates a `WithdrawRewards` instruction signed by the platform owner, paying `amount`
+/// out of the reward vault into `destination`.
+pub fn withdraw_rewards(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    platform_state: &Pubkey,
+    reward_vault: &Pubkey,
+    destination: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let (vault, _) = find_vault_address(program_id, platform_state);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*owner, true),
+        AccountMeta::new(*platform_state, false),
+        AccountMeta::new(*reward_vault, false),
+        AccountMeta::new(*destination, false),
+        AccountMeta::new_readonly(vault, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::WithdrawRewards { amount }.pack(),
+    }
+}
+
+/// Creates a `ClosePlatform` instruction signed by the platform owner, who gets the rent.
+pub fn close_platform(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    platform_state: &Pubkey,
+    reward_vault: &Pubkey,
+) -> Instruction {
+    let (vault, _) = find_vault_address(program_id, platform_state);
+
+    let accounts = vec![
+        AccountMeta::new(*owner, true),
+        AccountMeta::new(*platform_state, false),
+        AccountMeta::new(*reward_vault, false),
+        AccountMeta::new_readonly(vault, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::ClosePlatform.pack(),
+    }
+}
+
+/// Creates a `SetAutoCloseUserState` instruction signed by the platform owner.
+pub fn set_auto_close_user_state(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    platform_state: &Pubkey,
+    enabled: bool,
+) -> Instruction {
+    let accounts = vec![
+        AccountMeta::new_readonly(*owner, true),
+        AccountMeta::new(*platform_state, false),
+    ];
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::SetAutoCloseUserState { enabled }.pack(),
+    }
+}
+
+/// Creates a `SetPaused` instruction signed by the platform owner.
+pub fn set_paused(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    platform_state: &Pubkey,
+    paused: bool,
+) -> Instruction {
+    let accounts = vec![
+        AccountMeta::new_readonly(*owner, true),
+        AccountMeta::new(*platform_state, false),
+    ];
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::SetPaused { paused }.pack(),
+    }
+}
+
+/// Creates a `ProposeOwner` instruction signed by the current platform owner.
+pub fn propose_owner(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    platform_state: &Pubkey,
+    new_owner: &Pubkey,
+) -> Instruction {
+    let accounts = vec![
+        AccountMeta::new_readonly(*owner, true),
+        AccountMeta::new(*platform_state, false),
+    ];
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::ProposeOwner {
+            new_owner: *new_owner,
+        }
+        .pack(),
+    }
+}
+
+/// Creates an `AcceptOwner` instruction signed by the pending owner.
+pub fn accept_owner(
+    program_id: &Pubkey,
+    new_owner: &Pubkey,
+    platform_state: &Pubkey,
+) -> Instruction {
+    let accounts = vec![
+        AccountMeta::new_readonly(*new_owner, true),
+        AccountMeta::new(*platform_state, false),
+    ];
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::AcceptOwner.pack(),
+    }
+}
+
+/// Creates a `RenounceOwnership` instruction signed by the platform owner.
+pub fn renounce_ownership(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    platform_state: &Pubkey,
+) -> Instruction {
+    let accounts = vec![
+        AccountMeta::new_readonly(*owner, true),
+        AccountMeta::new(*platform_state, false),
+    ];
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::RenounceOwnership.pack(),
+    }
+}
+
+/// Creates a `SetRarityRoot` instruction signed by the platform owner.
+pub fn set_rarity_root(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    platform_state: &Pubkey,
+    root: [u8; 32],
+) -> Instruction {
+    let accounts = vec![
+        AccountMeta::new_readonly(*owner, true),
+        AccountMeta::new(*platform_state, false),
+    ];
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::SetRarityRoot { root }.pack(),
+    }
+}
+
+/// Creates a `SetRarity` instruction signed by the platform owner, who pays for the rarity
+/// accounts created by it.
+pub fn set_rarity(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    platform_state: &Pubkey,
+    entries: &[RarityEntry],
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*owner, true),
+        AccountMeta::new_readonly(*platform_state, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    for entry in entries {
+        let (rarity, _) = find_rarity_address(program_id, platform_state, &entry.mint);
+        accounts.push(AccountMeta::new(rarity, false));
+    }
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: NftInstruction::SetRarity {
+            entries: entries.to_vec(),
+        }
+        .pack(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::{collection::vec, prelude::*};
+
+    fn pubkey() -> impl Strategy<Value = Pubkey> {
+        any::<[u8; 32]>().prop_map(Pubkey::new_from_array)
+    }
+
+    fn collection_rule() -> impl Strategy<Value = CollectionRule> {
+        prop_oneof![
+            Just(CollectionRule::VerifiedCollection),
+            Just(CollectionRule::VerifiedCreator),
+        ]
+    }
+
+    fn reward_mode() -> impl Strategy<Value = RewardMode> {
+        prop_oneof![Just(RewardMode::PerNft), Just(RewardMode::Emission)]
+    }
+
+    fn custody_mode() -> impl Strategy<Value = CustodyMode> {
+        prop_oneof![
+            Just(CustodyMode::Authority),
+            Just(CustodyMode::Escrow),
+            Just(CustodyMode::Frozen),
+        ]
+    }
+
+    fn lock_config() -> impl Strategy<Value = LockConfig> {
+        (any::<u64>(), any::<u64>(), any::<u16>()).prop_map(
+            |(hard_lock_duration, soft_lock_duration, early_unstake_penalty_bps)| LockConfig {
+                hard_lock_duration,
+                soft_lock_duration,
+                early_unstake_penalty_bps,
+            },
+        )
+    }
+
+    fn instruction() -> impl Strategy<Value = NftInstruction> {
+        prop_oneof![
+            (
+                (
+                    any::<u64>(),
+                    any::<u64>(),
+                    reward_mode(),
+                    custody_mode(),
+                    lock_config()
+                ),
+                (
+                    collection_rule(),
+                    pubkey(),
+                    vec(pubkey(), 0..=MAX_ALLOWED_CREATORS)
+                ),
+            )
+                .prop_map(
+                    |(
+                        (amount, reward_rate, reward_mode, custody_mode, lock),
+                        (collection_rule, collection, creators),
+                    )| {
+                        NftInstruction::InitializePlatform {
+                            amount,
+                            reward_rate,
+                            reward_mode,
+                            custody_mode,
+                            lock,
+                            collection_rule,
+                            collection,
+                            creators,
+                        }
+                    }
+                ),
+            (
+                any::<u8>(),
+                proptest::option::of(
+                    (any::<u16>(), vec(any::<[u8; 32]>(), 0..=MAX_PROOF_LEN)).prop_map(
+                        |(multiplier_bps, proof)| RarityProof {
+                            multiplier_bps,
+                            proof
+                        }
+                    )
+                )
+            )
+                .prop_map(|(lock_tier, rarity)| NftInstruction::StakeNft { lock_tier, rarity }),
+            Just(NftInstruction::UnStakeNft),
+            Just(NftInstruction::Harvest),
+            any::<u64>().prop_map(|reward_rate| NftInstruction::UpdateRewardRate { reward_rate }),
+            vec(
+                (any::<u64>(), any::<u16>()).prop_map(|(duration, multiplier_bps)| LockTier {
+                    duration,
+                    multiplier_bps
+                }),
+                0..=MAX_LOCK_TIERS
+            )
+            .prop_map(|tiers| NftInstruction::SetLockTiers { tiers }),
+            vec(
+                (pubkey(), any::<u16>()).prop_map(|(mint, multiplier_bps)| RarityEntry {
+                    mint,
+                    multiplier_bps
+                }),
+                0..=MAX_RARITY_ENTRIES
+            )
+            .prop_map(|entries| NftInstruction::SetRarity { entries }),
+            any::<[u8; 32]>().prop_map(|root| NftInstruction::SetRarityRoot { root }),
+            any::<u64>().prop_map(|amount| NftInstruction::TopUpRewards { amount }),
+            any::<u64>().prop_map(|amount| NftInstruction::WithdrawRewards { amount }),
+            any::<bool>().prop_map(|paused| NftInstruction::SetPaused { paused }),
+            Just(NftInstruction::EmergencyUnstake),
+            pubkey().prop_map(|new_owner| NftInstruction::ProposeOwner { new_owner }),
+            Just(NftInstruction::AcceptOwner),
+            Just(NftInstruction::RenounceOwnership),
+            Just(NftInstruction::InitializeUser),
+            Just(NftInstruction::CloseUserState),
+            Just(NftInstruction::ClosePlatform),
+            any::<bool>().prop_map(|enabled| NftInstruction::SetAutoCloseUserState { enabled }),
+            (1..=MAX_STAKE_MANY as u8, any::<u8>())
+                .prop_map(|(count, lock_tier)| NftInstruction::StakeMany { count, lock_tier }),
+            (1..=MAX_UNSTAKE_MANY as u8).prop_map(|count| NftInstruction::UnstakeMany { count }),
+            (1..=MAX_HARVEST_ALL as u8).prop_map(|count| NftInstruction::HarvestAll { count }),
+            Just(NftInstruction::QueryPendingRewards),
+        ]
+    }
+
+    /// The tag every variant is expected to encode to. The match is exhaustive, so a new
+    /// variant doesn't compile until it has a tag here and a case in `instruction()`.
+    fn expected_tag(instruction: &NftInstruction) -> u8 {
+        match instruction {
+            NftInstruction::InitializePlatform { .. } => 0,
+            NftInstruction::StakeNft { .. } => 2,
+            NftInstruction::UnStakeNft => 3,
+            NftInstruction::Harvest => 4,
+            NftInstruction::UpdateRewardRate { .. } => 5,
+            NftInstruction::SetLockTiers { .. } => 6,
+            NftInstruction::SetRarity { .. } => 7,
+            NftInstruction::SetRarityRoot { .. } => 8,
+            NftInstruction::TopUpRewards { .. } => 9,
+            NftInstruction::WithdrawRewards { .. } => 10,
+            NftInstruction::SetPaused { .. } => 11,
+            NftInstruction::EmergencyUnstake => 12,
+            NftInstruction::ProposeOwner { .. } => 13,
+            NftInstruction::AcceptOwner => 14,
+            NftInstruction::RenounceOwnership => 15,
+            NftInstruction::InitializeUser => 16,
+            NftInstruction::CloseUserState => 17,
+            NftInstruction::ClosePlatform => 18,
+            NftInstruction::SetAutoCloseUserState { .. } => 19,
+            NftInstruction::StakeMany { .. } => 20,
+            NftInstruction::UnstakeMany { .. } => 21,
+            NftInstruction::HarvestAll { .. } => 22,
+            NftInstruction::QueryPendingRewards => 23,
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_pack_unpack_round_trip(instruction in instruction()) {
+            let packed = instruction.pack();
+            prop_assert_eq!(packed[0], expected_tag(&instruction));
+            prop_assert_eq!(NftInstruction::unpack(&packed).unwrap(), instruction);
+        }
+
+        #[test]
+        fn test_unpack_never_panics(input in vec(any::<u8>(), 0..512)) {
+            if let Ok(instruction) = NftInstruction::unpack(&input) {
+                // the encoding may add optional trailing fields the input left out
+                let packed = instruction.pack();
+                let len = packed.len().min(input.len());
+                prop_assert_eq!(&input[..len], &packed[..len]);
+                prop_assert_eq!(NftInstruction::unpack(&packed).unwrap(), instruction);
+            }
+        }
+    }
+
+    #[test]
+    fn test_reserved_and_unknown_tags_are_rejected() {
+        assert_eq!(
+            NftInstruction::unpack(&[1]),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            NftInstruction::unpack(&[]),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            NftInstruction::unpack(&[u8::MAX]),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_truncated_data_is_rejected() {
+        let packed = NftInstruction::InitializePlatform {
+            amount: 1,
+            reward_rate: 2,
+            reward_mode: RewardMode::Emission,
+            custody_mode: CustodyMode::Escrow,
+            lock: LockConfig {
+                hard_lock_duration: 3,
+                soft_lock_duration: 4,
+                early_unstake_penalty_bps: 5,
+            },
+            collection_rule: CollectionRule::VerifiedCreator,
+            collection: Pubkey::new_unique(),
+            creators: vec![Pubkey::new_unique(); 2],
+        }
+        .pack();
+        for len in 1..packed.len() {
+            assert_eq!(
+                NftInstruction::unpack(&packed[..len]),
+                Err(ProgramError::InvalidAccountData)
+            );
+        }
     }
 }
@@ -1,5 +1,9 @@
 pub mod entrypoint;
 pub mod error;
 pub mod instruction;
+pub mod math;
+pub mod merkle;
+pub mod metadata;
 pub mod processor;
 pub mod state;
+pub mod validation;
@@ -0,0 +1,293 @@
+//! Fixed-point reward math
+
+use crate::error::FarmError;
+
+/// Scale applied to fractional per-second rates and reward accumulators.
+pub const PRECISION: u128 = 1_000_000_000_000;
+
+pub const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Basis points making up 100%.
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Returns `a * b / c` rounded down, computed in u128 so the product can't overflow.
+pub fn checked_mul_div(a: u128, b: u128, c: u128) -> Result<u128, FarmError> {
+    a.checked_mul(b)
+        .and_then(|product| product.checked_div(c))
+        .ok_or(FarmError::NumericOverflow)
+}
+
+/// Converts a `PRECISION`-scaled value back into whole base units, rounding down.
+pub fn to_amount(scaled: u128) -> Result<u64, FarmError> {
+    let amount = scaled
+        .checked_div(PRECISION)
+        .ok_or(FarmError::NumericOverflow)?;
+    u64::try_from(amount).map_err(|_| FarmError::NumericOverflow)
+}
+
+/// `bps` basis points of `amount`, rounded down.
+pub fn apply_bps(amount: u64, bps: u64) -> Result<u64, FarmError> {
+    let value = checked_mul_div(amount as u128, bps as u128, BPS_DENOMINATOR as u128)?;
+    u64::try_from(value).map_err(|_| FarmError::NumericOverflow)
+}
+
+/// Reward per NFT accrued at `rate` base units a second over `elapsed` seconds,
+/// scaled by `PRECISION` for the platform accumulator.
+pub fn accrued_per_nft(rate: u64, elapsed: u64) -> Result<u128, FarmError> {
+    (rate as u128)
+        .checked_mul(elapsed as u128)
+        .and_then(|accrued| accrued.checked_mul(PRECISION))
+        .ok_or(FarmError::NumericOverflow)
+}
+
+/// Share of `emission` base units a second over `elapsed` seconds that goes to a 1x NFT
+/// when the staked NFTs weigh `total_weight_bps` together, scaled by `PRECISION` for the
+/// platform accumulator.
+pub fn accrued_per_weight(
+    emission: u64,
+    elapsed: u64,
+    total_weight_bps: u64,
+) -> Result<u128, FarmError> {
+    checked_mul_div(
+        accrued_per_nft(emission, elapsed)?,
+        BPS_DENOMINATOR as u128,
+        total_weight_bps as u128,
+    )
+}
+
+/// Base units owed for `unsettled` weighted accruals that no stake record has settled yet,
+/// in units of `BPS_DENOMINATOR * PRECISION`. Rounds up, so a vault holding it can always
+/// pay every record's rounded-down share.
+pub fn unsettled_amount(unsettled: u128) -> Result<u64, FarmError> {
+    let scale = PRECISION * BPS_DENOMINATOR as u128;
+    u64::try_from(unsettled.div_ceil(scale)).map_err(|_| FarmError::NumericOverflow)
+}
+
+/// Base units owed to a stake weighing `weight_bps` (10_000 for 1x) that last settled at
+/// `paid` when the accumulator is at `acc`.
+pub fn pending_weighted_reward(acc: u128, paid: u128, weight_bps: u64) -> Result<u64, FarmError> {
+    let base = acc.checked_sub(paid).ok_or(FarmError::NumericOverflow)?;
+    to_amount(checked_mul_div(
+        base,
+        weight_bps as u128,
+        BPS_DENOMINATOR as u128,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEN_YEARS: u64 = 10 * 365 * SECONDS_PER_DAY + 2 * SECONDS_PER_DAY;
+
+    /// Long-division reference: pays `amount` into a running numerator every second
+    /// and only ever converts whole periods into reward.
+    struct Reference {
+        amount: u64,
+        period: u64,
+        elapsed: u64,
+        paid: u64,
+        remainder: u64,
+    }
+
+    impl Reference {
+        fn new(amount: u64, period: u64) -> Self {
+            Reference {
+                amount,
+                period,
+                elapsed: 0,
+                paid: 0,
+                remainder: 0,
+            }
+        }
+
+        fn advance_to(&mut self, elapsed: u64) -> u64 {
+            let seconds = elapsed - self.elapsed;
+            let numerator = self.remainder as u128 + self.amount as u128 * seconds as u128;
+            self.paid += (numerator / self.period as u128) as u64;
+            self.remainder = (numerator % self.period as u128) as u64;
+            self.elapsed = elapsed;
+            self.paid
+        }
+    }
+
+    /// Pays a stake that settled at `paid` for what has `accrued` since in one update, the
+    /// way `settle_stake_record` does after `update_reward_per_nft`.
+    fn settle(paid: u128, accrued: u128, weight_bps: u64) -> u64 {
+        pending_weighted_reward(paid + accrued, paid, weight_bps).unwrap()
+    }
+
+    /// Every elapsed second over the first two days, then an odd stride up to ten years.
+    fn sweep() -> impl Iterator<Item = u64> {
+        let stride = std::iter::successors(Some(2 * SECONDS_PER_DAY), |elapsed| {
+            // step by an odd stride so every residue of the period gets visited
+            Some(elapsed + 3_607).filter(|&elapsed| elapsed <= TEN_YEARS)
+        });
+        (1..2 * SECONDS_PER_DAY).chain(stride)
+    }
+
+    fn assert_per_nft_matches_reference(rate: u64, weight_bps: u64) {
+        let paid = accrued_per_nft(rate, 12_345).unwrap();
+        let mut reference = Reference::new(rate * weight_bps, BPS_DENOMINATOR);
+        for elapsed in sweep() {
+            assert_eq!(
+                settle(paid, accrued_per_nft(rate, elapsed).unwrap(), weight_bps),
+                reference.advance_to(elapsed),
+                "rate {} weight {} elapsed {}",
+                rate,
+                weight_bps,
+                elapsed
+            );
+        }
+    }
+
+    /// Emission shares round twice, into the accumulator and out of it, so they may come
+    /// in up to one base unit under the reference but never over it.
+    fn assert_emission_within_dust(emission: u64, weight_bps: u64, total_weight_bps: u64) {
+        let paid = accrued_per_weight(emission, 12_345, total_weight_bps).unwrap();
+        let mut reference = Reference::new(emission * weight_bps, total_weight_bps);
+        for elapsed in sweep() {
+            let accrued = accrued_per_weight(emission, elapsed, total_weight_bps).unwrap();
+            let paid_out = settle(paid, accrued, weight_bps);
+            let owed = reference.advance_to(elapsed);
+            assert!(
+                paid_out <= owed && owed - paid_out <= 1,
+                "emission {} weight {}/{} elapsed {}: paid {} of {}",
+                emission,
+                weight_bps,
+                total_weight_bps,
+                elapsed,
+                paid_out,
+                owed
+            );
+        }
+    }
+
+    #[test]
+    fn test_per_nft_reward_matches_reference_from_one_second_to_ten_years() {
+        for rate in [1, 10, 1_000, 86_401, 1_000_000_000] {
+            for weight_bps in [1, BPS_DENOMINATOR, 12_345, 20_000] {
+                assert_per_nft_matches_reference(rate, weight_bps);
+            }
+        }
+    }
+
+    #[test]
+    fn test_emission_reward_is_within_dust_of_reference() {
+        for emission in [1, 100, 86_401, 1_000_000_000] {
+            assert_emission_within_dust(emission, BPS_DENOMINATOR, BPS_DENOMINATOR);
+            assert_emission_within_dust(emission, BPS_DENOMINATOR, 3 * BPS_DENOMINATOR);
+            assert_emission_within_dust(emission, 15_000, 40_001);
+        }
+    }
+
+    #[test]
+    fn test_small_weights_do_not_truncate_to_zero() {
+        // 1 base unit a second at a 1 bps weight only pays out once 10_000 seconds accrued
+        let paid = accrued_per_nft(1, 12_345).unwrap();
+        assert_eq!(settle(paid, accrued_per_nft(1, 9_999).unwrap(), 1), 0);
+        assert_eq!(settle(paid, accrued_per_nft(1, 10_000).unwrap(), 1), 1);
+        assert_eq!(
+            settle(paid, accrued_per_nft(1, TEN_YEARS).unwrap(), 1),
+            TEN_YEARS / 10_000
+        );
+    }
+
+    #[test]
+    fn test_accumulator_settles_each_rate_separately() {
+        let mut acc = accrued_per_nft(5, 100).unwrap();
+        let paid = acc;
+        acc += accrued_per_nft(7, 30).unwrap();
+        assert_eq!(
+            pending_weighted_reward(acc, 0, BPS_DENOMINATOR).unwrap(),
+            5 * 100 + 7 * 30
+        );
+        assert_eq!(
+            pending_weighted_reward(acc, paid, BPS_DENOMINATOR).unwrap(),
+            7 * 30
+        );
+        assert_eq!(
+            pending_weighted_reward(paid, acc, BPS_DENOMINATOR),
+            Err(FarmError::NumericOverflow)
+        );
+    }
+
+    #[test]
+    fn test_emission_is_split_between_shares() {
+        let acc = accrued_per_weight(100, 86_400, 3 * BPS_DENOMINATOR).unwrap();
+        assert_eq!(
+            pending_weighted_reward(acc, 0, BPS_DENOMINATOR).unwrap(),
+            2_880_000
+        );
+        // rounding only ever loses dust, never pays out more than was emitted
+        let acc = accrued_per_weight(10, 1, 3 * BPS_DENOMINATOR).unwrap();
+        assert_eq!(
+            pending_weighted_reward(acc * 3, 0, BPS_DENOMINATOR).unwrap(),
+            9
+        );
+        assert!(acc * 3 <= 10 * PRECISION);
+        assert_eq!(accrued_per_weight(1, 1, 0), Err(FarmError::NumericOverflow));
+    }
+
+    #[test]
+    fn test_emission_is_split_by_weight() {
+        // a 1x and a 3x stake share 400 a second
+        let acc = accrued_per_weight(400, 10, 4 * BPS_DENOMINATOR).unwrap();
+        assert_eq!(
+            pending_weighted_reward(acc, 0, BPS_DENOMINATOR).unwrap(),
+            1_000
+        );
+        assert_eq!(
+            pending_weighted_reward(acc, 0, 3 * BPS_DENOMINATOR).unwrap(),
+            3_000
+        );
+        // the weight is applied before rounding to whole base units
+        let acc = accrued_per_nft(1, 1).unwrap();
+        assert_eq!(pending_weighted_reward(acc, 0, 15_000).unwrap(), 1);
+        assert_eq!(pending_weighted_reward(acc * 2, 0, 15_000).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_unsettled_amount_covers_every_share() {
+        // three 1x stakes sharing 10 a second for a second
+        let acc = accrued_per_weight(10, 1, 3 * BPS_DENOMINATOR).unwrap();
+        let unsettled = acc * 3 * BPS_DENOMINATOR as u128;
+        let shares = 3 * pending_weighted_reward(acc, 0, BPS_DENOMINATOR).unwrap();
+        assert_eq!(shares, 9);
+        assert_eq!(unsettled_amount(unsettled).unwrap(), 10);
+        assert_eq!(unsettled_amount(0).unwrap(), 0);
+        assert_eq!(
+            unsettled_amount(PRECISION * BPS_DENOMINATOR as u128).unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_apply_bps() {
+        assert_eq!(apply_bps(1_000, 2_500).unwrap(), 250);
+        assert_eq!(apply_bps(999, 2_500).unwrap(), 249);
+        assert_eq!(apply_bps(u64::MAX, BPS_DENOMINATOR).unwrap(), u64::MAX);
+        assert_eq!(apply_bps(7, 0).unwrap(), 0);
+        assert_eq!(
+            apply_bps(u64::MAX, 2 * BPS_DENOMINATOR),
+            Err(FarmError::NumericOverflow)
+        );
+    }
+
+    #[test]
+    fn test_overflow_is_reported() {
+        assert_eq!(
+            accrued_per_nft(u64::MAX, u64::MAX),
+            Err(FarmError::NumericOverflow)
+        );
+        assert_eq!(
+            pending_weighted_reward(u128::MAX, 0, 2),
+            Err(FarmError::NumericOverflow)
+        );
+        assert_eq!(to_amount(u128::MAX), Err(FarmError::NumericOverflow));
+        assert_eq!(
+            checked_mul_div(u128::MAX, 2, 1),
+            Err(FarmError::NumericOverflow)
+        );
+    }
+}
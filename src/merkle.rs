@@ -0,0 +1,251 @@
+//! Merkle proofs of (mint, multiplier) rarity leaves
+
+use solana_program::{hash::hashv, pubkey::Pubkey};
+
+/// Longest proof `StakeNft` accepts, enough for a tree of a million mints.
+pub const MAX_PROOF_LEN: usize = 20;
+
+// Leaves and inner nodes are hashed under different prefixes, so an inner node can't be
+// passed off as a leaf.
+const LEAF_PREFIX: &[u8] = &[0];
+const NODE_PREFIX: &[u8] = &[1];
+
+/// Leaf committing `mint` to a reward multiplier of `multiplier_bps`.
+pub fn leaf_hash(mint: &Pubkey, multiplier_bps: u16) -> [u8; 32] {
+    hashv(&[LEAF_PREFIX, mint.as_ref(), &multiplier_bps.to_le_bytes()]).to_bytes()
+}
+
+/// Parent of two nodes. The pair is sorted first, so proofs don't need to say which side
+/// each sibling is on.
+fn node_hash(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (left, right) = if a <= b { (a, b) } else { (b, a) };
+    hashv(&[NODE_PREFIX, left, right]).to_bytes()
+}
+
+/// Whether `proof` leads from `leaf` up to `root`.
+pub fn verify(root: &[u8; 32], leaf: [u8; 32], proof: &[[u8; 32]]) -> bool {
+    proof
+        .iter()
+        .fold(leaf, |node, sibling| node_hash(&node, sibling))
+        == *root
+}
+
+#[cfg(not(target_arch = "bpf"))]
+pub use builder::*;
+
+/// Off-chain construction of rarity trees, e.g. from a CSV export of the collection.
+#[cfg(not(target_arch = "bpf"))]
+mod builder {
+    use super::*;
+    use crate::instruction::{RarityEntry, RarityProof};
+    use std::{collections::HashSet, str::FromStr};
+    use thiserror::Error;
+
+    #[derive(Clone, Debug, Eq, Error, PartialEq)]
+    pub enum TreeError {
+        #[error("line {0}: expected `mint,multiplier_bps` ")]
+        InvalidLine(usize),
+
+        #[error("line {0}: multiplier must be positive ")]
+        ZeroMultiplier(usize),
+
+        #[error("mint {0} is listed twice ")]
+        DuplicateMint(Pubkey),
+
+        #[error("tree has no leaves ")]
+        Empty,
+
+        #[error("tree is too deep for a proof of at most MAX_PROOF_LEN hashes ")]
+        TooDeep,
+    }
+
+    /// Merkle tree over the rarity of every listed mint.
+    pub struct RarityTree {
+        entries: Vec<RarityEntry>,
+        /// Every level of the tree, leaves first. An odd node out is carried up unchanged.
+        layers: Vec<Vec<[u8; 32]>>,
+    }
+
+    impl RarityTree {
+        pub fn new(entries: Vec<RarityEntry>) -> Result<Self, TreeError> {
+            if entries.is_empty() {
+                return Err(TreeError::Empty);
+            }
+            let mut seen = HashSet::new();
+            for entry in &entries {
+                if !seen.insert(entry.mint) {
+                    return Err(TreeError::DuplicateMint(entry.mint));
+                }
+            }
+
+            let mut layers = vec![entries
+                .iter()
+                .map(|entry| leaf_hash(&entry.mint, entry.multiplier_bps))
+                .collect::<Vec<_>>()];
+            while layers.last().unwrap().len() > 1 {
+                let next = layers
+                    .last()
+                    .unwrap()
+                    .chunks(2)
+                    .map(|pair| match pair {
+                        [a, b] => node_hash(a, b),
+                        [a] => *a,
+                        _ => unreachable!(),
+                    })
+                    .collect();
+                layers.push(next);
+            }
+            if layers.len() - 1 > MAX_PROOF_LEN {
+                return Err(TreeError::TooDeep);
+            }
+
+            Ok(RarityTree { entries, layers })
+        }
+
+        /// Reads one `mint,multiplier_bps` pair per line. Blank lines and a
+        /// `mint,multiplier_bps` header are skipped.
+        pub fn from_csv(csv: &str) -> Result<Self, TreeError> {
+            let mut entries = Vec::new();
+            for (index, line) in csv.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || (index == 0 && line == "mint,multiplier_bps") {
+                    continue;
+                }
+                let number = index + 1;
+                let (mint, multiplier_bps) =
+                    line.split_once(',').ok_or(TreeError::InvalidLine(number))?;
+                let mint =
+                    Pubkey::from_str(mint.trim()).map_err(|_| TreeError::InvalidLine(number))?;
+                let multiplier_bps = multiplier_bps
+                    .trim()
+                    .parse::<u16>()
+                    .map_err(|_| TreeError::InvalidLine(number))?;
+                if multiplier_bps == 0 {
+                    return Err(TreeError::ZeroMultiplier(number));
+                }
+                entries.push(RarityEntry {
+                    mint,
+                    multiplier_bps,
+                });
+            }
+            Self::new(entries)
+        }
+
+        pub fn root(&self) -> [u8; 32] {
+            self.layers.last().unwrap()[0]
+        }
+
+        pub fn entries(&self) -> &[RarityEntry] {
+            &self.entries
+        }
+
+        /// The multiplier of `mint` with its proof, to be passed to `StakeNft`.
+        pub fn proof(&self, mint: &Pubkey) -> Option<RarityProof> {
+            let leaf = self.entries.iter().position(|entry| entry.mint == *mint)?;
+            let mut index = leaf;
+            let mut proof = Vec::with_capacity(self.layers.len() - 1);
+            for layer in &self.layers[..self.layers.len() - 1] {
+                // a node without a sibling is carried up, so it adds nothing to the proof
+                if let Some(sibling) = layer.get(index ^ 1) {
+                    proof.push(*sibling);
+                }
+                index /= 2;
+            }
+            Some(RarityProof {
+                multiplier_bps: self.entries[leaf].multiplier_bps,
+                proof,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::RarityEntry;
+
+    fn entries(count: usize) -> Vec<RarityEntry> {
+        (0..count)
+            .map(|index| RarityEntry {
+                mint: Pubkey::new_unique(),
+                multiplier_bps: 10_000 + index as u16,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_every_proof_verifies() {
+        for count in 1..=9 {
+            let tree = RarityTree::new(entries(count)).unwrap();
+            for entry in tree.entries() {
+                let proof = tree.proof(&entry.mint).unwrap();
+                assert_eq!(proof.multiplier_bps, entry.multiplier_bps);
+                assert!(verify(
+                    &tree.root(),
+                    leaf_hash(&entry.mint, proof.multiplier_bps),
+                    &proof.proof
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_proof_binds_multiplier_and_mint() {
+        let tree = RarityTree::new(entries(5)).unwrap();
+        let entry = tree.entries()[2];
+        let proof = tree.proof(&entry.mint).unwrap().proof;
+        assert!(!verify(
+            &tree.root(),
+            leaf_hash(&entry.mint, entry.multiplier_bps + 1),
+            &proof
+        ));
+        assert!(!verify(
+            &tree.root(),
+            leaf_hash(&tree.entries()[3].mint, entry.multiplier_bps),
+            &proof
+        ));
+        assert!(tree.proof(&Pubkey::new_unique()).is_none());
+    }
+
+    #[test]
+    fn test_from_csv() {
+        let a = Pubkey::new_unique();
+        let b = Pubkey::new_unique();
+        let csv = format!("mint,multiplier_bps\n{}, 15000\n\n{},30000\n", a, b);
+        let tree = RarityTree::from_csv(&csv).unwrap();
+        assert_eq!(
+            tree.entries(),
+            &[
+                RarityEntry {
+                    mint: a,
+                    multiplier_bps: 15_000
+                },
+                RarityEntry {
+                    mint: b,
+                    multiplier_bps: 30_000
+                },
+            ]
+        );
+
+        assert_eq!(
+            RarityTree::from_csv(&format!("{},x", a)).err(),
+            Some(TreeError::InvalidLine(1))
+        );
+        assert_eq!(
+            RarityTree::from_csv(&format!("{},1\nnope,1", a)).err(),
+            Some(TreeError::InvalidLine(2))
+        );
+        assert_eq!(
+            RarityTree::from_csv(&format!("{},0", a)).err(),
+            Some(TreeError::ZeroMultiplier(1))
+        );
+        assert_eq!(
+            RarityTree::from_csv(&format!("{},1\n{},2", a, a)).err(),
+            Some(TreeError::DuplicateMint(a))
+        );
+        assert_eq!(
+            RarityTree::from_csv("mint,multiplier_bps\n").err(),
+            Some(TreeError::Empty)
+        );
+    }
+}
@@ -0,0 +1,381 @@
+//! Minimal reader for Metaplex token metadata accounts
+
+use crate::error::FarmError;
+use solana_program::{
+    account_info::AccountInfo,
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+pub mod mpl_token_metadata {
+    solana_program::declare_id!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+}
+
+/// `Key::MetadataV1` discriminator of the token metadata program.
+const METADATA_V1_KEY: u8 = 4;
+
+/// `MetadataInstruction::FreezeDelegatedAccount` and `ThawDelegatedAccount` tags.
+const FREEZE_DELEGATED_ACCOUNT: u8 = 26;
+const THAW_DELEGATED_ACCOUNT: u8 = 27;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Creator {
+    pub address: Pubkey,
+    pub verified: bool,
+    pub share: u8,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Collection {
+    pub verified: bool,
+    pub key: Pubkey,
+}
+
+/// The parts of a metadata account the farm cares about.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Metadata {
+    pub update_authority: Pubkey,
+    pub mint: Pubkey,
+    pub creators: Vec<Creator>,
+    pub collection: Option<Collection>,
+}
+
+pub fn find_metadata_address(mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            "metadata".as_bytes(),
+            mpl_token_metadata::id().as_ref(),
+            mint.as_ref(),
+        ],
+        &mpl_token_metadata::id(),
+    )
+}
+
+/// Master edition of `mint`, which is the freeze authority of every Metaplex NFT mint.
+pub fn find_edition_address(mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            "metadata".as_bytes(),
+            mpl_token_metadata::id().as_ref(),
+            mint.as_ref(),
+            "edition".as_bytes(),
+        ],
+        &mpl_token_metadata::id(),
+    )
+}
+
+/// Freezes `token_account` on behalf of its approved `delegate`, using the edition's
+/// freeze authority over `mint`.
+pub fn freeze_delegated_account(
+    delegate: &Pubkey,
+    token_account: &Pubkey,
+    edition: &Pubkey,
+    mint: &Pubkey,
+) -> Instruction {
+    delegated_freeze_instruction(
+        FREEZE_DELEGATED_ACCOUNT,
+        delegate,
+        token_account,
+        edition,
+        mint,
+    )
+}
+
+/// Thaws a token account frozen by `freeze_delegated_account`.
+pub fn thaw_delegated_account(
+    delegate: &Pubkey,
+    token_account: &Pubkey,
+    edition: &Pubkey,
+    mint: &Pubkey,
+) -> Instruction {
+    delegated_freeze_instruction(
+        THAW_DELEGATED_ACCOUNT,
+        delegate,
+        token_account,
+        edition,
+        mint,
+    )
+}
+
+fn delegated_freeze_instruction(
+    tag: u8,
+    delegate: &Pubkey,
+    token_account: &Pubkey,
+    edition: &Pubkey,
+    mint: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: mpl_token_metadata::id(),
+        accounts: vec![
+            AccountMeta::new(*delegate, true),
+            AccountMeta::new(*token_account, false),
+            AccountMeta::new_readonly(*edition, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: vec![tag],
+    }
+}
+
+/// Loads the metadata of `mint`, checking the account is its canonical metadata PDA.
+pub fn load_metadata(
+    metadata_account: &AccountInfo,
+    mint: &Pubkey,
+) -> Result<Metadata, ProgramError> {
+    if *metadata_account.owner != mpl_token_metadata::id()
+        || find_metadata_address(mint).0 != *metadata_account.key
+    {
+        return Err(FarmError::InvalidMetadataAccount.into());
+    }
+    let metadata = Metadata::unpack(&metadata_account.try_borrow_data()?)?;
+    if metadata.mint != *mint {
+        return Err(FarmError::InvalidMetadataAccount.into());
+    }
+    Ok(metadata)
+}
+
+impl Metadata {
+    /// Decodes the borsh layout of `MetadataV1`. Trailing optional fields are absent on
+    /// accounts written by older program versions and read as `None`.
+    pub fn unpack(src: &[u8]) -> Result<Self, FarmError> {
+        let mut reader = Reader { data: src };
+        if reader.read_u8()? != METADATA_V1_KEY {
+            return Err(FarmError::InvalidMetadataAccount);
+        }
+        let update_authority = reader.read_pubkey()?;
+        let mint = reader.read_pubkey()?;
+        // name, symbol, uri
+        for _ in 0..3 {
+            reader.skip_string()?;
+        }
+        let _seller_fee_basis_points = reader.read_bytes(2)?;
+        let mut creators = vec![];
+        if reader.read_bool()? {
+            let count = reader.read_u32()?;
+            for _ in 0..count {
+                creators.push(Creator {
+                    address: reader.read_pubkey()?,
+                    verified: reader.read_bool()?,
+                    share: reader.read_u8()?,
+                });
+            }
+        }
+        let _primary_sale_happened = reader.read_bool()?;
+        let _is_mutable = reader.read_bool()?;
+
+        let mut collection = None;
+        if !reader.is_empty() {
+            // edition_nonce, token_standard
+            for _ in 0..2 {
+                if reader.read_bool()? {
+                    reader.read_u8()?;
+                }
+            }
+            if reader.read_bool()? {
+                collection = Some(Collection {
+                    verified: reader.read_bool()?,
+                    key: reader.read_pubkey()?,
+                });
+            }
+        }
+
+        Ok(Metadata {
+            update_authority,
+            mint,
+            creators,
+            collection,
+        })
+    }
+
+    /// Whether the metadata carries a verified membership of `collection`.
+    pub fn in_verified_collection(&self, collection: &Pubkey) -> bool {
+        matches!(&self.collection, Some(c) if c.verified && c.key == *collection)
+    }
+
+    /// Whether any of `allowed` appears among the metadata's verified creators.
+    pub fn has_verified_creator(&self, allowed: &[Pubkey]) -> bool {
+        self.creators
+            .iter()
+            .any(|creator| creator.verified && allowed.contains(&creator.address))
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], FarmError> {
+        if self.data.len() < len {
+            return Err(FarmError::InvalidMetadataAccount);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, FarmError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_bool(&mut self) -> Result<bool, FarmError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(FarmError::InvalidMetadataAccount),
+        }
+    }
+
+    fn read_u32(&mut self) -> Result<u32, FarmError> {
+        let bytes = self.read_bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn read_pubkey(&mut self) -> Result<Pubkey, FarmError> {
+        Ok(Pubkey::new(self.read_bytes(32)?))
+    }
+
+    fn skip_string(&mut self) -> Result<(), FarmError> {
+        let len = self.read_u32()? as usize;
+        self.read_bytes(len)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_string(buf: &mut Vec<u8>, value: &str) {
+        buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        buf.extend_from_slice(value.as_bytes());
+    }
+
+    fn metadata_bytes(
+        mint: &Pubkey,
+        creators: &[Creator],
+        collection: Option<&Collection>,
+    ) -> Vec<u8> {
+        let mut buf = vec![METADATA_V1_KEY];
+        buf.extend_from_slice(Pubkey::new_unique().as_ref());
+        buf.extend_from_slice(mint.as_ref());
+        push_string(&mut buf, "Rappid #1\0\0\0");
+        push_string(&mut buf, "RPD");
+        push_string(&mut buf, "https://example.com/1.json");
+        buf.extend_from_slice(&500u16.to_le_bytes());
+        buf.push(1);
+        buf.extend_from_slice(&(creators.len() as u32).to_le_bytes());
+        for creator in creators {
+            buf.extend_from_slice(creator.address.as_ref());
+            buf.push(creator.verified as u8);
+            buf.push(creator.share);
+        }
+        // primary_sale_happened, is_mutable, edition_nonce: Some(255), token_standard: None
+        buf.extend_from_slice(&[1, 1, 1, 255, 0]);
+        match collection {
+            Some(collection) => {
+                buf.push(1);
+                buf.push(collection.verified as u8);
+                buf.extend_from_slice(collection.key.as_ref());
+            }
+            None => buf.push(0),
+        }
+        // uses: None, then zero padding up to the account size
+        buf.push(0);
+        buf.resize(679, 0);
+        buf
+    }
+
+    #[test]
+    fn test_unpack_verified_collection() {
+        let mint = Pubkey::new_unique();
+        let creator = Creator {
+            address: Pubkey::new_unique(),
+            verified: true,
+            share: 100,
+        };
+        let collection = Collection {
+            verified: true,
+            key: Pubkey::new_unique(),
+        };
+        let metadata = Metadata::unpack(&metadata_bytes(
+            &mint,
+            std::slice::from_ref(&creator),
+            Some(&collection),
+        ))
+        .unwrap();
+        assert_eq!(metadata.mint, mint);
+        assert_eq!(metadata.creators, vec![creator]);
+        assert!(metadata.in_verified_collection(&collection.key));
+        assert!(!metadata.in_verified_collection(&Pubkey::new_unique()));
+    }
+
+    #[test]
+    fn test_unverified_collection_is_rejected() {
+        let collection = Collection {
+            verified: false,
+            key: Pubkey::new_unique(),
+        };
+        let metadata = Metadata::unpack(&metadata_bytes(
+            &Pubkey::new_unique(),
+            &[],
+            Some(&collection),
+        ))
+        .unwrap();
+        assert!(!metadata.in_verified_collection(&collection.key));
+    }
+
+    #[test]
+    fn test_verified_creator_allowlist() {
+        let first = Creator {
+            address: Pubkey::new_unique(),
+            verified: true,
+            share: 0,
+        };
+        let unverified = Creator {
+            address: Pubkey::new_unique(),
+            verified: false,
+            share: 100,
+        };
+        let metadata = Metadata::unpack(&metadata_bytes(
+            &Pubkey::new_unique(),
+            &[first.clone(), unverified.clone()],
+            None,
+        ))
+        .unwrap();
+        assert!(metadata.has_verified_creator(&[Pubkey::new_unique(), first.address]));
+        assert!(!metadata.has_verified_creator(&[unverified.address]));
+        assert!(!metadata.has_verified_creator(&[]));
+    }
+
+    #[test]
+    fn test_legacy_layout_without_collection() {
+        let mint = Pubkey::new_unique();
+        let mut data = metadata_bytes(&mint, &[], None);
+        // cut right after is_mutable, as written by v1.0 of the metadata program
+        let legacy_len = 1 + 32 + 32 + 16 + 7 + 30 + 2 + 1 + 4 + 2;
+        data.truncate(legacy_len);
+        let metadata = Metadata::unpack(&data).unwrap();
+        assert_eq!(metadata.mint, mint);
+        assert_eq!(metadata.collection, None);
+    }
+
+    #[test]
+    fn test_wrong_key_and_truncated_data() {
+        let mut data = metadata_bytes(&Pubkey::new_unique(), &[], None);
+        assert_eq!(
+            Metadata::unpack(&data[..40]),
+            Err(FarmError::InvalidMetadataAccount)
+        );
+        data[0] = 6;
+        assert_eq!(
+            Metadata::unpack(&data),
+            Err(FarmError::InvalidMetadataAccount)
+        );
+    }
+}
@@ -1,25 +1,43 @@
-use num_traits::CheckedDiv;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
     msg,
-    program::{invoke, invoke_signed},
+    program::{invoke, invoke_signed, set_return_data},
     program_error::ProgramError,
+    program_option::COption,
     program_pack::{IsInitialized, Pack},
     pubkey::Pubkey,
-    system_instruction::create_account,
+    system_instruction::{self, allocate, assign, create_account},
     sysvar::{clock::Clock, rent::Rent, Sysvar},
 };
 
 use crate::{
     error::FarmError,
-    instruction::NftInstruction,
-    state::{PlatForm, UserState},
+    instruction::{
+        max_stake_many, max_unstake_many, NftInstruction, PendingRewards, RarityEntry, RarityProof,
+        MAX_RARITY_ENTRIES,
+    },
+    math, merkle,
+    metadata::{
+        freeze_delegated_account, load_metadata, mpl_token_metadata, thaw_delegated_account,
+    },
+    state::{
+        create_escrow_address, create_rarity_address, create_stake_record_address,
+        create_user_state_address, create_vault_address, find_escrow_address, find_rarity_address,
+        find_stake_record_address, find_user_state_address, find_vault_address, CollectionRule,
+        CustodyMode, LockConfig, LockTier, PlatForm, Rarity, RewardMode, StakeRecord, UserState,
+        DEFAULT_MULTIPLIER_BPS, ESCROW_SEED, MAX_ALLOWED_CREATORS, MAX_LOCK_TIERS, RARITY_SEED,
+        STAKE_RECORD_SEED, USER_STATE_SEED, VAULT_SEED,
+    },
+    validation::{
+        assert_program_owned, assert_signer, assert_system_program, assert_token_program,
+        assert_writable, load_token_account, load_writable_token_account,
+    },
+};
+use spl_token::{
+    instruction::transfer,
+    state::{Account as TokenAccount, Mint},
 };
-use spl_associated_token_account;
-use spl_token::{instruction::transfer, state::Account as TokenAccount};
-use std::cell::RefCell;
-use std::str::FromStr;
 pub struct Processor;
 impl Processor {
     pub fn process(
@@ -29,29 +47,133 @@ impl Processor {
     ) -> ProgramResult {
         let instruction = NftInstruction::unpack(instruction_data)?;
         match instruction {
-            NftInstruction::InitializePlatform { amount } => {
+            NftInstruction::InitializePlatform {
+                amount,
+                reward_rate,
+                reward_mode,
+                custody_mode,
+                lock,
+                collection_rule,
+                collection,
+                creators,
+            } => {
                 msg!("Instruction:INIT PLATFORM");
-                return Self::process_init_platform(accounts, program_id, amount);
+                Self::process_init_platform(
+                    accounts,
+                    program_id,
+                    amount,
+                    reward_rate,
+                    reward_mode,
+                    custody_mode,
+                    lock,
+                    collection_rule,
+                    collection,
+                    &creators,
+                )
             }
-            NftInstruction::StakeNft {} => {
-                msg!("Instruction:STAKE NFT!!!!!");
-                return Self::process_stake_nft(accounts, program_id);
+            NftInstruction::StakeNft { lock_tier, rarity } => {
+                msg!("Instruction: STAKE NFT");
+                Self::process_stake_nft(accounts, program_id, lock_tier, rarity)
             }
-            NftInstruction::UnStakeNft {} => {
+            NftInstruction::UnStakeNft => {
                 msg!("Instruction: UNSTAKE NFT");
-                return Self::process_unstake_nft(accounts, program_id);
+                Self::process_unstake_nft(accounts, program_id)
             }
-            NftInstruction::Harvest {} => {
+            NftInstruction::Harvest => {
                 msg!("Instruction: CLAIM REWARD");
-                return Self::process_harvest_reward(accounts, program_id);
+                Self::process_harvest_reward(accounts, program_id)
+            }
+            NftInstruction::UpdateRewardRate { reward_rate } => {
+                msg!("Instruction: UPDATE REWARD RATE");
+                Self::process_update_reward_rate(accounts, program_id, reward_rate)
+            }
+            NftInstruction::SetLockTiers { tiers } => {
+                msg!("Instruction: SET LOCK TIERS");
+                Self::process_set_lock_tiers(accounts, program_id, &tiers)
+            }
+            NftInstruction::SetRarity { entries } => {
+                msg!("Instruction: SET RARITY");
+                Self::process_set_rarity(accounts, program_id, &entries)
+            }
+            NftInstruction::SetRarityRoot { root } => {
+                msg!("Instruction: SET RARITY ROOT");
+                Self::process_set_rarity_root(accounts, program_id, root)
+            }
+            NftInstruction::TopUpRewards { amount } => {
+                msg!("Instruction: TOP UP REWARDS");
+                Self::process_top_up_rewards(accounts, program_id, amount)
+            }
+            NftInstruction::WithdrawRewards { amount } => {
+                msg!("Instruction: WITHDRAW REWARDS");
+                Self::process_withdraw_rewards(accounts, program_id, amount)
+            }
+            NftInstruction::SetPaused { paused } => {
+                msg!("Instruction: SET PAUSED");
+                Self::process_set_paused(accounts, program_id, paused)
+            }
+            NftInstruction::EmergencyUnstake => {
+                msg!("Instruction: EMERGENCY UNSTAKE");
+                Self::process_emergency_unstake(accounts, program_id)
+            }
+            NftInstruction::ProposeOwner { new_owner } => {
+                msg!("Instruction: PROPOSE OWNER");
+                Self::process_propose_owner(accounts, program_id, new_owner)
+            }
+            NftInstruction::AcceptOwner => {
+                msg!("Instruction: ACCEPT OWNER");
+                Self::process_accept_owner(accounts, program_id)
+            }
+            NftInstruction::RenounceOwnership => {
+                msg!("Instruction: RENOUNCE OWNERSHIP");
+                Self::process_renounce_ownership(accounts, program_id)
+            }
+            NftInstruction::InitializeUser => {
+                msg!("Instruction: INITIALIZE USER");
+                Self::process_user_init(accounts, program_id)
+            }
+            NftInstruction::CloseUserState => {
+                msg!("Instruction: CLOSE USER STATE");
+                Self::process_close_user_state(accounts, program_id)
+            }
+            NftInstruction::ClosePlatform => {
+                msg!("Instruction: CLOSE PLATFORM");
+                Self::process_close_platform(accounts, program_id)
+            }
+            NftInstruction::SetAutoCloseUserState { enabled } => {
+                msg!("Instruction: SET AUTO CLOSE USER STATE");
+                Self::process_set_auto_close_user_state(accounts, program_id, enabled)
+            }
+            NftInstruction::StakeMany { count, lock_tier } => {
+                msg!("Instruction: STAKE MANY");
+                Self::process_stake_many(accounts, program_id, lock_tier, count)
+            }
+            NftInstruction::UnstakeMany { count } => {
+                msg!("Instruction: UNSTAKE MANY");
+                Self::process_unstake_many(accounts, program_id, count)
+            }
+            NftInstruction::HarvestAll { count } => {
+                msg!("Instruction: HARVEST ALL");
+                Self::process_harvest_all(accounts, program_id, count)
+            }
+            NftInstruction::QueryPendingRewards => {
+                msg!("Instruction: QUERY PENDING REWARDS");
+                Self::process_query_pending_rewards(accounts, program_id)
             }
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn process_init_platform(
         accounts: &[AccountInfo],
         program_id: &Pubkey,
         alloc_point_new: u64,
+        reward_rate: u64,
+        reward_mode: RewardMode,
+        custody_mode: CustodyMode,
+        lock: LockConfig,
+        collection_rule: CollectionRule,
+        collection: Pubkey,
+        creators: &[Pubkey],
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let platform_state_account = next_account_info(account_info_iter)?;
@@ -62,6 +184,29 @@ impl Processor {
         let system_program_id = next_account_info(account_info_iter)?;
         let token_program = next_account_info(account_info_iter)?;
 
+        assert_signer(platform_state_account)?;
+        assert_writable(platform_state_account)?;
+        assert_signer(owner_account)?;
+        assert_writable(owner_account)?;
+        load_writable_token_account(admin_reward_token_account)?;
+        let vault = load_writable_token_account(pda_reward_token_account)?;
+        assert_system_program(system_program_id)?;
+        assert_token_program(token_program)?;
+
+        //pda to store staked tokens
+        let (pda, nonce) = find_vault_address(program_id, platform_state_account.key);
+
+        msg!("pda {}", pda);
+
+        if pda != *pda_account.key {
+            msg!("wrong pda");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if vault.owner != pda {
+            msg!("reward vault is not owned by the pda");
+            return Err(FarmError::InvalidTokenAccountOwner.into());
+        }
+
         invoke(
             &create_account(
                 owner_account.key,
@@ -78,18 +223,8 @@ impl Processor {
         )?;
         msg!("Platfom_state_account {}", platform_state_account.key);
 
-        //pda to store staked tokens
-        let pda_prefix = "rappid-paltform";
-
-        let pda_seed = &[pda_prefix.as_bytes(), platform_state_account.key.as_ref()];
-
-        let (pda, nonce) = Pubkey::find_program_address(pda_seed, program_id);
-
-        msg!("pda {}", pda);
-
         let mut platform_data =
             PlatForm::unpack_unchecked(&platform_state_account.try_borrow_data()?)?;
-        msg!("after unpack");
 
         if platform_data.is_initialized() {
             return Err(ProgramError::AccountAlreadyInitialized);
@@ -98,6 +233,25 @@ impl Processor {
         platform_data.is_initialized = true;
         platform_data.owner = *owner_account.key;
         platform_data.total_staked_nft = 0;
+        platform_data.total_weight_bps = 0;
+        platform_data.reward_vault = *pda_reward_token_account.key;
+        platform_data.total_deposited = alloc_point_new;
+        platform_data.reward_rate = reward_rate;
+        platform_data.reward_mode = reward_mode;
+        platform_data.custody_mode = custody_mode;
+        platform_data.lock = lock;
+        platform_data.acc_reward_per_nft = 0;
+        platform_data.last_update_time = Clock::get()?.unix_timestamp as u64;
+        if creators.len() > MAX_ALLOWED_CREATORS
+            || lock.early_unstake_penalty_bps as u64 > math::BPS_DENOMINATOR
+        {
+            return Err(ProgramError::InvalidArgument);
+        }
+        platform_data.collection_rule = collection_rule;
+        platform_data.collection = collection;
+        platform_data.creator_count = creators.len() as u8;
+        platform_data.creators[..creators.len()].copy_from_slice(creators);
+        platform_data.vault_bump = nonce;
 
         let transfer_token = transfer(
             token_program.key,
@@ -105,9 +259,9 @@ impl Processor {
             pda_reward_token_account.key,
             owner_account.key,
             &[],
-            alloc_point_new.clone(),
+            alloc_point_new,
         )?;
-        msg!("Calling the token program to transfer LP tokens pdatoken account...");
+        msg!("depositing {} into the reward vault", alloc_point_new);
         invoke(
             &transfer_token,
             &[
@@ -127,15 +281,23 @@ impl Processor {
     }
 
     pub fn process_user_init(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
-        msg!("entry ********************************");
         let account_info_iter = &mut accounts.iter();
         let user = next_account_info(account_info_iter)?;
         let user_state_account = next_account_info(account_info_iter)?;
         let system_program = next_account_info(account_info_iter)?;
-        let mint = next_account_info(account_info_iter)?;
+        let platform_state = next_account_info(account_info_iter)?;
+
+        assert_signer(user)?;
+        assert_writable(user)?;
+        assert_writable(user_state_account)?;
+        assert_system_program(system_program)?;
+        assert_program_owned(platform_state, program_id)?;
+        if user_state_account.owner == program_id {
+            return Err(FarmError::AlreadyInUse.into());
+        }
 
-        let pda_seed = &[(user.key).as_ref(), (mint.key).as_ref()];
-        let (pda, nonce) = Pubkey::find_program_address(pda_seed, program_id);
+        let user_prefix = USER_STATE_SEED;
+        let (pda, nonce) = find_user_state_address(program_id, platform_state.key, user.key);
         msg!("pda {}", pda);
 
         if pda != *user_state_account.key {
@@ -143,135 +305,485 @@ impl Processor {
             return Err(ProgramError::InvalidAccountData);
         }
 
-        invoke_signed(
-            &create_account(
-                user.key,
-                user_state_account.key,
-                Rent::default().minimum_balance(UserState::LEN),
-                UserState::LEN as u64,
-                program_id,
-            ),
+        Self::create_pda_account(
+            user,
+            user_state_account,
+            UserState::LEN,
+            program_id,
+            system_program,
             &[
-                user.clone(),
-                user_state_account.clone(),
-                system_program.clone(),
+                user_prefix.as_bytes(),
+                platform_state.key.as_ref(),
+                user.key.as_ref(),
+                &[nonce],
             ],
-            &[&[&(user.key).as_ref(), &(mint.key).as_ref()[..], &[nonce]]],
         )?;
 
         let mut user_data = UserState::unpack_unchecked(&user_state_account.try_borrow_data()?)?;
 
         if user_data.is_initialized() {
-            return Err(ProgramError::AccountAlreadyInitialized);
+            return Err(FarmError::AlreadyInUse.into());
         }
 
-        msg!("after unpacks ******************");
-
         user_data.is_initialized = true;
         user_data.user = *user.key;
         user_data.total_staked_nft = 0;
-        user_data.last_staked_time = 0;
+        user_data.bump = nonce;
 
         UserState::pack(user_data, &mut user_state_account.try_borrow_mut_data()?)?;
 
         Ok(())
     }
 
-    pub fn process_stake_nft(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    pub fn process_stake_nft(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        lock_tier: u8,
+        rarity: Option<RarityProof>,
+    ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
 
-        msg!("entered ******************");
         let user = next_account_info(account_info_iter)?;
         let user_state_account = next_account_info(account_info_iter)?;
+        let stake_record_account = next_account_info(account_info_iter)?;
         let platform_state = next_account_info(account_info_iter)?;
 
         let token_account = next_account_info(account_info_iter)?;
         let mint_key = next_account_info(account_info_iter)?;
+        let metadata_account = next_account_info(account_info_iter)?;
 
         let pda_account = next_account_info(account_info_iter)?;
         let token_program = next_account_info(account_info_iter)?;
 
         let system_program = next_account_info(account_info_iter)?;
+        let rarity_account = next_account_info(account_info_iter)?;
+
+        let (mut platform_state_info, mut user_data, now) = Self::begin_stake(
+            user,
+            user_state_account,
+            platform_state,
+            pda_account,
+            token_program,
+            system_program,
+            program_id,
+        )?;
+        let custody = Self::trailing_custody_accounts(&platform_state_info, account_info_iter)?;
+
+        Self::stake_one(
+            &mut platform_state_info,
+            &mut user_data,
+            lock_tier,
+            rarity,
+            user,
+            platform_state,
+            pda_account,
+            token_program,
+            system_program,
+            token_account,
+            mint_key,
+            metadata_account,
+            stake_record_account,
+            rarity_account,
+            &mut custody.iter(),
+            program_id,
+            now,
+        )?;
 
-        let user_pda_seed = &[(user.key).as_ref(), (mint_key.key).as_ref()];
-        let (user_state, nonce) = Pubkey::find_program_address(user_pda_seed, program_id);
-        msg!("pda {}", user_state_account.key);
+        UserState::pack(user_data, &mut user_state_account.try_borrow_mut_data()?)?;
+        PlatForm::pack(
+            platform_state_info,
+            &mut platform_state.try_borrow_mut_data()?,
+        )
+    }
 
-        if user_state != *user_state_account.key {
-            msg!("user_state_acc wrong");
-            return Err(ProgramError::InvalidAccountData);
+    /// Stakes `count` NFTs of the signer in one go, see `NftInstruction::StakeMany` for the
+    /// account layout. A failure on any of them fails the whole instruction.
+    pub fn process_stake_many(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        lock_tier: u8,
+        count: u8,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let user = next_account_info(account_info_iter)?;
+        let user_state_account = next_account_info(account_info_iter)?;
+        let platform_state = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+        let system_program = next_account_info(account_info_iter)?;
+        let metadata_program = next_account_info(account_info_iter)?;
+
+        let (mut platform_state_info, mut user_data, now) = Self::begin_stake(
+            user,
+            user_state_account,
+            platform_state,
+            pda_account,
+            token_program,
+            system_program,
+            program_id,
+        )?;
+        if count as usize > max_stake_many(platform_state_info.custody_mode) {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        for _ in 0..count {
+            let token_account = next_account_info(account_info_iter)?;
+            let mint_key = next_account_info(account_info_iter)?;
+            let stake_record_account = next_account_info(account_info_iter)?;
+            let metadata_account = next_account_info(account_info_iter)?;
+            let rarity_account = next_account_info(account_info_iter)?;
+            let custody = Self::next_custody_accounts(
+                &platform_state_info,
+                account_info_iter,
+                metadata_program,
+            )?;
+
+            Self::stake_one(
+                &mut platform_state_info,
+                &mut user_data,
+                lock_tier,
+                None,
+                user,
+                platform_state,
+                pda_account,
+                token_program,
+                system_program,
+                token_account,
+                mint_key,
+                metadata_account,
+                stake_record_account,
+                rarity_account,
+                &mut custody.iter(),
+                program_id,
+                now,
+            )?;
         }
+        msg!("staked {}", count);
+
+        UserState::pack(user_data, &mut user_state_account.try_borrow_mut_data()?)?;
+        PlatForm::pack(
+            platform_state_info,
+            &mut platform_state.try_borrow_mut_data()?,
+        )?;
+
+        Ok(())
+    }
 
+    /// Checks the accounts every stake shares and loads the platform and user state,
+    /// creating the latter on the user's first stake. Rewards are brought up to now, so the
+    /// stakes that follow can change the platform's weight.
+    fn begin_stake<'a>(
+        user: &AccountInfo<'a>,
+        user_state_account: &AccountInfo<'a>,
+        platform_state: &AccountInfo<'a>,
+        pda_account: &AccountInfo<'a>,
+        token_program: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        program_id: &Pubkey,
+    ) -> Result<(PlatForm, UserState, u64), ProgramError> {
+        assert_signer(user)?;
+        assert_writable(user)?;
+        assert_writable(user_state_account)?;
+        assert_program_owned(platform_state, program_id)?;
+        assert_writable(platform_state)?;
+        assert_token_program(token_program)?;
+        assert_system_program(system_program)?;
+
+        // first stake on the platform, unless the user state was set up by `InitializeUser`
         if user_state_account.owner != program_id {
             let user_init_accounts = &[
                 user.clone(),
                 user_state_account.clone(),
                 system_program.clone(),
-                mint_key.clone(),
+                platform_state.clone(),
             ];
 
-            Self::process_user_init(user_init_accounts, program_id);
+            Self::process_user_init(user_init_accounts, program_id)?;
         };
 
-        let mut user_data = UserState::unpack_unchecked(&user_state_account.try_borrow_data()?)?;
-
-        //pda to store staked tokens
-        let pda_prefix = "rappid-paltform";
-        let pda_seed = &[pda_prefix.as_bytes(), (platform_state.key).as_ref()];
-
-        let (pda, nonce) = Pubkey::find_program_address(pda_seed, program_id);
-        msg!("pda {}", pda_account.key);
+        let user_data =
+            Self::unpack_user_state(user_state_account, platform_state, user, program_id)?;
 
-        if pda != *pda_account.key {
-            msg!("wrong pda");
-            return Err(ProgramError::InvalidAccountData);
+        let mut platform_state_info = PlatForm::unpack(&platform_state.try_borrow_data()?)?;
+        if platform_state_info.paused {
+            return Err(FarmError::PlatformPaused.into());
         }
-        let owner_change_ix = spl_token::instruction::set_authority(
-            token_program.key,
-            token_account.key,
-            Some(&pda),
-            spl_token::instruction::AuthorityType::AccountOwner,
-            user.key,
-            &[],
-        )?;
 
-        invoke(
-            &owner_change_ix,
-            &[token_account.clone(), user.clone(), token_program.clone()],
+        //pda to store staked tokens
+        Self::assert_vault_pda(
+            pda_account,
+            platform_state,
+            platform_state_info.vault_bump,
+            program_id,
         )?;
+        msg!("pda {}", pda_account.key);
 
         //set up clock
         let system_clock = Clock::get()?;
+        let now = system_clock.unix_timestamp as u64;
+        msg!("current time{}", now);
 
-        let mut platform_state_info =
-            PlatForm::unpack_unchecked(&platform_state.try_borrow_data()?)?;
+        Self::update_reward_per_nft(&mut platform_state_info, now)?;
+
+        Ok((platform_state_info, user_data, now))
+    }
+
+    /// Takes custody of one NFT and opens its stake record. `custody_accounts` holds the
+    /// escrow under `CustodyMode::Escrow`, or what `set_frozen` needs under
+    /// `CustodyMode::Frozen`.
+    #[allow(clippy::too_many_arguments)]
+    fn stake_one<'a, 'b>(
+        platform_state_info: &mut PlatForm,
+        user_data: &mut UserState,
+        lock_tier: u8,
+        rarity: Option<RarityProof>,
+        user: &AccountInfo<'a>,
+        platform_state: &AccountInfo<'a>,
+        pda_account: &AccountInfo<'a>,
+        token_program: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        token_account: &AccountInfo<'a>,
+        mint_key: &AccountInfo<'a>,
+        metadata_account: &AccountInfo<'a>,
+        stake_record_account: &AccountInfo<'a>,
+        rarity_account: &AccountInfo<'a>,
+        custody_accounts: &mut std::slice::Iter<'b, AccountInfo<'a>>,
+        program_id: &Pubkey,
+        now: u64,
+    ) -> ProgramResult {
+        assert_writable(stake_record_account)?;
+        assert_writable(token_account)?;
+        if *mint_key.owner != spl_token::id() {
+            return Err(FarmError::InvalidMint.into());
+        }
+
+        let token_account_info = load_token_account(token_account)?;
+        if token_account_info.mint != *mint_key.key {
+            return Err(FarmError::InvalidMint.into());
+        }
+
+        let mint_info = Mint::unpack(&mint_key.try_borrow_data()?)?;
+        if mint_info.supply != 1 || mint_info.decimals != 0 {
+            return Err(FarmError::NotAnNft.into());
+        }
+        if token_account_info.amount != 1 {
+            return Err(ProgramError::InsufficientFunds);
+        }
+
+        let tier = platform_state_info
+            .lock_tier(lock_tier)
+            .ok_or(FarmError::InvalidLockTier)?;
+        let rarity_proven = rarity.is_some();
+        let (rarity_bps, rarity_bump) = match rarity {
+            Some(rarity) => {
+                let leaf = merkle::leaf_hash(mint_key.key, rarity.multiplier_bps);
+                if platform_state_info.rarity_root == [0; 32]
+                    || rarity.multiplier_bps == 0
+                    || !merkle::verify(&platform_state_info.rarity_root, leaf, &rarity.proof)
+                {
+                    return Err(FarmError::InvalidRarityProof.into());
+                }
+                (rarity.multiplier_bps, 0)
+            }
+            None => Self::find_rarity(rarity_account, platform_state, mint_key.key, program_id)?,
+        };
+
+        let metadata = load_metadata(metadata_account, mint_key.key)?;
+        let allowed = match platform_state_info.collection_rule {
+            CollectionRule::VerifiedCollection => {
+                metadata.in_verified_collection(&platform_state_info.collection)
+            }
+            CollectionRule::VerifiedCreator => {
+                metadata.has_verified_creator(platform_state_info.allowed_creators())
+            }
+        };
+        if !allowed {
+            msg!("collection not allowed");
+            return Err(FarmError::CollectionNotAllowed.into());
+        }
+
+        let record_prefix = STAKE_RECORD_SEED;
+        let (stake_record, record_nonce) =
+            find_stake_record_address(program_id, platform_state.key, mint_key.key);
+
+        if stake_record != *stake_record_account.key {
+            msg!("stake_record_acc wrong");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if stake_record_account.owner == program_id {
+            return Err(FarmError::AlreadyInUse.into());
+        }
+
+        Self::create_pda_account(
+            user,
+            stake_record_account,
+            StakeRecord::LEN,
+            program_id,
+            system_program,
+            &[
+                record_prefix.as_bytes(),
+                platform_state.key.as_ref(),
+                mint_key.key.as_ref(),
+                &[record_nonce],
+            ],
+        )?;
 
-        msg!("unpacked!!!!!!!!!!!!!!");
+        let mut escrow_bump = 0;
+        match platform_state_info.custody_mode {
+            CustodyMode::Authority => {
+                // SetAuthority clears the delegate but would leave a close authority with
+                // the previous owner, who could then drain the account out of the vault
+                if token_account_info.close_authority.is_some() {
+                    return Err(FarmError::TokenAccountHasAuthority.into());
+                }
+                let owner_change_ix = spl_token::instruction::set_authority(
+                    token_program.key,
+                    token_account.key,
+                    Some(pda_account.key),
+                    spl_token::instruction::AuthorityType::AccountOwner,
+                    user.key,
+                    &[],
+                )?;
+
+                invoke(
+                    &owner_change_ix,
+                    &[token_account.clone(), user.clone(), token_program.clone()],
+                )?;
+            }
+            CustodyMode::Escrow => {
+                let escrow_account = next_account_info(custody_accounts)?;
+                escrow_bump = Self::open_escrow(
+                    user,
+                    token_account,
+                    escrow_account,
+                    mint_key,
+                    platform_state,
+                    pda_account,
+                    token_program,
+                    system_program,
+                    program_id,
+                )?;
+            }
+            CustodyMode::Frozen => {
+                if token_account_info.owner != *user.key {
+                    return Err(FarmError::InvalidTokenAccountOwner.into());
+                }
+                invoke(
+                    &spl_token::instruction::approve(
+                        token_program.key,
+                        token_account.key,
+                        pda_account.key,
+                        user.key,
+                        &[],
+                        1,
+                    )?,
+                    &[
+                        token_account.clone(),
+                        pda_account.clone(),
+                        user.clone(),
+                        token_program.clone(),
+                    ],
+                )?;
+                Self::set_frozen(
+                    true,
+                    token_account,
+                    mint_key,
+                    platform_state,
+                    pda_account,
+                    token_program,
+                    custody_accounts,
+                    platform_state_info.vault_bump,
+                )?;
+            }
+        }
 
-        user_data.last_staked_time = system_clock.unix_timestamp.clone() as u64;
-        msg!("current time{}",user_data.last_staked_time);
+        let record_data = StakeRecord {
+            is_initialized: true,
+            platform: *platform_state.key,
+            staker: *user.key,
+            mint: *mint_key.key,
+            stake_time: now,
+            last_claim_time: now,
+            accrued_reward: 0,
+            reward_per_nft_paid: platform_state_info.acc_reward_per_nft,
+            bump: record_nonce,
+            escrow_bump,
+            rarity_bump,
+            lock_tier,
+            unlock_time: now.saturating_add(
+                platform_state_info
+                    .lock
+                    .hard_lock_duration
+                    .max(tier.duration),
+            ),
+            weight_bps: math::apply_bps(tier.multiplier_bps as u64, rarity_bps as u64)?,
+            tier_multiplier_bps: tier.multiplier_bps,
+            rarity_bps,
+            rarity_proven,
+        };
 
+        user_data.total_staked_nft = user_data
+            .total_staked_nft
+            .checked_add(1)
+            .ok_or(FarmError::NumericOverflow)?;
         platform_state_info.total_staked_nft = platform_state_info
             .total_staked_nft
             .checked_add(1)
             .ok_or(ProgramError::AccountDataTooSmall)?;
+        platform_state_info.total_weight_bps = platform_state_info
+            .total_weight_bps
+            .checked_add(record_data.weight_bps)
+            .ok_or(FarmError::NumericOverflow)?;
+
+        StakeRecord::pack(
+            record_data,
+            &mut stake_record_account.try_borrow_mut_data()?,
+        )
+    }
 
-        UserState::pack(user_data, &mut user_state_account.try_borrow_mut_data()?)?;
-        PlatForm::pack(
-            platform_state_info,
-            &mut platform_state.try_borrow_mut_data()?,
-        )?;
-        msg!(" packed!!!!1111");
+    /// The custody account following an NFT in a `StakeMany` or `UnstakeMany` batch, with
+    /// the token metadata program when `set_frozen` may need it.
+    fn next_custody_accounts<'a, 'b>(
+        platform: &PlatForm,
+        account_info_iter: &mut std::slice::Iter<'b, AccountInfo<'a>>,
+        metadata_program: &AccountInfo<'a>,
+    ) -> Result<Vec<AccountInfo<'a>>, ProgramError> {
+        Ok(match platform.custody_mode {
+            CustodyMode::Authority => vec![],
+            CustodyMode::Escrow => vec![next_account_info(account_info_iter)?.clone()],
+            CustodyMode::Frozen => vec![
+                next_account_info(account_info_iter)?.clone(),
+                metadata_program.clone(),
+            ],
+        })
+    }
 
-        Ok(())
+    /// The custody accounts trailing a `StakeNft`, `UnStakeNft` or `EmergencyUnstake`: the
+    /// escrow, then the master edition and token metadata program, each only used by the
+    /// custody mode it belongs to.
+    fn trailing_custody_accounts<'a, 'b>(
+        platform: &PlatForm,
+        account_info_iter: &mut std::slice::Iter<'b, AccountInfo<'a>>,
+    ) -> Result<Vec<AccountInfo<'a>>, ProgramError> {
+        Ok(match platform.custody_mode {
+            CustodyMode::Authority => vec![],
+            CustodyMode::Escrow => vec![next_account_info(account_info_iter)?.clone()],
+            CustodyMode::Frozen => {
+                next_account_info(account_info_iter)?;
+                vec![
+                    next_account_info(account_info_iter)?.clone(),
+                    next_account_info(account_info_iter)?.clone(),
+                ]
+            }
+        })
     }
 
     pub fn process_unstake_nft(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
 
-        msg!("entered ******************");
         let user = next_account_info(account_info_iter)?;
         let user_state_account = next_account_info(account_info_iter)?;
+        let stake_record_account = next_account_info(account_info_iter)?;
         let platform_state = next_account_info(account_info_iter)?;
 
         let pda_token_account = next_account_info(account_info_iter)?;
@@ -283,112 +795,295 @@ impl Processor {
         let user_reward_account = next_account_info(account_info_iter)?;
         let pda_reward_token_account = next_account_info(account_info_iter)?;
 
+        let (mut platform_state_info, mut user_data, now) = Self::begin_unstake(
+            user,
+            user_state_account,
+            platform_state,
+            pda_account,
+            token_program,
+            system_program,
+            user_reward_account,
+            pda_reward_token_account,
+            program_id,
+        )?;
+        let custody = Self::trailing_custody_accounts(&platform_state_info, account_info_iter)?;
+        msg!("user {}", user_state_account.key);
 
-        let user_pda_seed = &[(user.key).as_ref(), (mint_key.key).as_ref()];
+        let reward = Self::unstake_one(
+            &mut platform_state_info,
+            &mut user_data,
+            user,
+            platform_state,
+            pda_account,
+            token_program,
+            pda_token_account,
+            mint_key,
+            stake_record_account,
+            &mut custody.iter(),
+            program_id,
+            now,
+        )?;
 
-        
-        let (user_state, nonce) = Pubkey::find_program_address(user_pda_seed, program_id);
-        msg!("pda {}", user_state);
+        Self::finish_unstake(
+            platform_state_info,
+            user_data,
+            reward,
+            user,
+            user_state_account,
+            platform_state,
+            pda_account,
+            token_program,
+            user_reward_account,
+            pda_reward_token_account,
+        )
+    }
 
-        if user_state != *user_state_account.key {
-            msg!("user_state_acc wrong");
-            return Err(ProgramError::InvalidAccountData);
+    /// Unstakes `count` NFTs of the signer in one go and pays their rewards in a single
+    /// transfer, see `NftInstruction::UnstakeMany` for the account layout. A failure on any
+    /// of them fails the whole instruction.
+    pub fn process_unstake_many(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        count: u8,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let user = next_account_info(account_info_iter)?;
+        let user_state_account = next_account_info(account_info_iter)?;
+        let platform_state = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+        let system_program = next_account_info(account_info_iter)?;
+        let user_reward_account = next_account_info(account_info_iter)?;
+        let pda_reward_token_account = next_account_info(account_info_iter)?;
+        let metadata_program = next_account_info(account_info_iter)?;
+
+        let (mut platform_state_info, mut user_data, now) = Self::begin_unstake(
+            user,
+            user_state_account,
+            platform_state,
+            pda_account,
+            token_program,
+            system_program,
+            user_reward_account,
+            pda_reward_token_account,
+            program_id,
+        )?;
+        if count as usize > max_unstake_many(platform_state_info.custody_mode) {
+            return Err(ProgramError::InvalidArgument);
         }
 
-        //pda to store staked tokens
-        let pda_prefix = "rappid-paltform";
-        let pda_seed = &[pda_prefix.as_bytes(), (platform_state.key).as_ref()];
+        let mut reward = 0u64;
+        for _ in 0..count {
+            let pda_token_account = next_account_info(account_info_iter)?;
+            let mint_key = next_account_info(account_info_iter)?;
+            let stake_record_account = next_account_info(account_info_iter)?;
+            let custody = Self::next_custody_accounts(
+                &platform_state_info,
+                account_info_iter,
+                metadata_program,
+            )?;
 
-        let (pda, nonce) = Pubkey::find_program_address(pda_seed, program_id);
-        msg!("user {}", user_state_account.key);
+            let paid = Self::unstake_one(
+                &mut platform_state_info,
+                &mut user_data,
+                user,
+                platform_state,
+                pda_account,
+                token_program,
+                pda_token_account,
+                mint_key,
+                stake_record_account,
+                &mut custody.iter(),
+                program_id,
+                now,
+            )?;
+            reward = reward.checked_add(paid).ok_or(FarmError::NumericOverflow)?;
+        }
+        msg!("unstaked {}", count);
 
-        msg!("pda {}", pda);
+        Self::finish_unstake(
+            platform_state_info,
+            user_data,
+            reward,
+            user,
+            user_state_account,
+            platform_state,
+            pda_account,
+            token_program,
+            user_reward_account,
+            pda_reward_token_account,
+        )
+    }
 
-        if pda != *pda_account.key {
-            msg!("error with farm pda");
-            return Err(ProgramError::InvalidAccountData);
-        }
+    /// Checks the accounts every unstake shares and loads the platform and user state.
+    #[allow(clippy::too_many_arguments)]
+    fn begin_unstake(
+        user: &AccountInfo,
+        user_state_account: &AccountInfo,
+        platform_state: &AccountInfo,
+        pda_account: &AccountInfo,
+        token_program: &AccountInfo,
+        system_program: &AccountInfo,
+        user_reward_account: &AccountInfo,
+        pda_reward_token_account: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> Result<(PlatForm, UserState, u64), ProgramError> {
+        assert_signer(user)?;
+        assert_writable(user)?;
+        assert_program_owned(user_state_account, program_id)?;
+        assert_writable(user_state_account)?;
+        assert_program_owned(platform_state, program_id)?;
+        assert_writable(platform_state)?;
+        assert_token_program(token_program)?;
+        assert_system_program(system_program)?;
+        load_writable_token_account(user_reward_account)?;
+        load_writable_token_account(pda_reward_token_account)?;
+
+        let user_data =
+            Self::unpack_user_state(user_state_account, platform_state, user, program_id)?;
+        let platform_state_info = PlatForm::unpack(&platform_state.try_borrow_data()?)?;
+
+        Self::assert_reward_vault(&platform_state_info, pda_reward_token_account)?;
 
-        let transfer_nft = spl_token::instruction::set_authority(
-            token_program.key,
-            pda_token_account.key,
-            Some(user.key),
-            spl_token::instruction::AuthorityType::AccountOwner,
-            &pda,
-            &[],
-        )?;
-        invoke_signed(
-            &transfer_nft,
-            &[
-                pda_token_account.clone(),
-                pda_account.clone(),
-                token_program.clone(),
-            ],
-            &[&[
-                pda_prefix.as_bytes(),
-                &(platform_state.key).as_ref()[..],
-                &[nonce],
-            ]],
+        //pda to store staked tokens
+        Self::assert_vault_pda(
+            pda_account,
+            platform_state,
+            platform_state_info.vault_bump,
+            program_id,
         )?;
 
         //set up clock
         let system_clock = Clock::get()?;
-        msg!("111");
+        let now = system_clock.unix_timestamp as u64;
+
+        Ok((platform_state_info, user_data, now))
+    }
+
+    /// Returns one NFT, settles its record and closes it. Returns the reward to pay for it,
+    /// after any early unstake penalty. `custody_accounts` is as for `stake_one`.
+    #[allow(clippy::too_many_arguments)]
+    fn unstake_one<'a, 'b>(
+        platform_state_info: &mut PlatForm,
+        user_data: &mut UserState,
+        user: &AccountInfo<'a>,
+        platform_state: &AccountInfo<'a>,
+        pda_account: &AccountInfo<'a>,
+        token_program: &AccountInfo<'a>,
+        pda_token_account: &AccountInfo<'a>,
+        mint_key: &AccountInfo<'a>,
+        stake_record_account: &AccountInfo<'a>,
+        custody_accounts: &mut std::slice::Iter<'b, AccountInfo<'a>>,
+        program_id: &Pubkey,
+        now: u64,
+    ) -> Result<u64, ProgramError> {
+        assert_writable(stake_record_account)?;
+        let pda_token = load_writable_token_account(pda_token_account)?;
+
+        let mut record_data =
+            Self::unpack_stake_record(stake_record_account, platform_state, user, program_id)?;
+        if record_data.mint != *mint_key.key || pda_token.mint != record_data.mint {
+            return Err(FarmError::InvalidMint.into());
+        }
+
+        if now < record_data.unlock_time {
+            msg!("locked until {}", record_data.unlock_time);
+            return Err(FarmError::StakeLocked.into());
+        }
 
-        let user_data = UserState::unpack_unchecked(&user_state_account.try_borrow_data()?)?;
+        Self::return_nft(
+            platform_state_info,
+            &record_data,
+            user,
+            pda_token_account,
+            &pda_token,
+            mint_key,
+            platform_state,
+            pda_account,
+            token_program,
+            custody_accounts,
+            program_id,
+        )?;
 
-        msg!("222");
+        msg! {"lst updated {}",record_data.stake_time};
 
-        let mut platform_state_info =
-            PlatForm::unpack_unchecked(&platform_state.try_borrow_data()?)?;
+        Self::settle_stake_record(platform_state_info, &mut record_data, now)?;
+
+        let mut reward = record_data.accrued_reward;
+        if platform_state_info
+            .lock
+            .is_soft_locked(record_data.stake_time, now)
+        {
+            // the penalty is simply never paid out, so it stays in the reward vault
+            let penalty = math::apply_bps(
+                reward,
+                platform_state_info.lock.early_unstake_penalty_bps as u64,
+            )?;
+            msg!("early unstake penalty {}", penalty);
+            reward -= penalty;
+        }
+        Self::release_reward(platform_state_info, record_data.accrued_reward, reward)?;
 
+        user_data.total_staked_nft = user_data
+            .total_staked_nft
+            .checked_sub(1)
+            .ok_or(FarmError::NumericOverflow)?;
         platform_state_info.total_staked_nft = platform_state_info
             .total_staked_nft
             .checked_sub(1)
             .ok_or(ProgramError::AccountDataTooSmall)?;
+        platform_state_info.total_weight_bps = platform_state_info
+            .total_weight_bps
+            .checked_sub(record_data.weight_bps)
+            .ok_or(FarmError::NumericOverflow)?;
+
+        Self::close_account(stake_record_account, user)?;
+
+        Ok(reward)
+    }
+
+    /// Pays out what the unstaked NFTs earned and writes back the platform and user state.
+    #[allow(clippy::too_many_arguments)]
+    fn finish_unstake<'a>(
+        platform_state_info: PlatForm,
+        user_data: UserState,
+        reward: u64,
+        user: &AccountInfo<'a>,
+        user_state_account: &AccountInfo<'a>,
+        platform_state: &AccountInfo<'a>,
+        pda_account: &AccountInfo<'a>,
+        token_program: &AccountInfo<'a>,
+        user_reward_account: &AccountInfo<'a>,
+        pda_reward_token_account: &AccountInfo<'a>,
+    ) -> ProgramResult {
+        if reward > 0 {
+            Self::transfer_reward(
+                token_program,
+                pda_reward_token_account,
+                user_reward_account,
+                pda_account,
+                platform_state,
+                platform_state_info.vault_bump,
+                reward,
+            )?;
+        }
 
         msg!(
             "total value staked {}",
             platform_state_info.total_staked_nft
         );
-        msg!{"lst updated {}",user_data.last_staked_time};
-
-        let total_staked_duration =
-        system_clock.unix_timestamp.clone() as u64 - user_data.last_staked_time;
-        msg!("total_duration{}",total_staked_duration);
-
-
-
-        if total_staked_duration > 86400 {
-            let harvest_accounts = &[
-                user.clone(),
-                user_state_account.clone(),
-                platform_state.clone(),
-                user_reward_account.clone(),
-                pda_reward_token_account.clone(),
-                pda_account.clone(),
-                token_program.clone(),
-                system_program.clone(),
-                mint_key.clone(),
-            ];
 
-            Self::process_harvest_reward(harvest_accounts, program_id);
-        }
-
-        UserState::pack(user_data, &mut user_state_account.try_borrow_mut_data()?)?;
+        Self::store_user_state(user_data, user_state_account, user, &platform_state_info)?;
         PlatForm::pack(
             platform_state_info,
             &mut platform_state.try_borrow_mut_data()?,
-        )?;
-
-        Ok(())
+        )
     }
 
     pub fn process_harvest_reward(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
-        msg!("claim reward!!!!!!");
         let user = next_account_info(account_info_iter)?;
-        let user_state_account = next_account_info(account_info_iter)?;
+        let stake_record_account = next_account_info(account_info_iter)?;
         let platform_state = next_account_info(account_info_iter)?;
 
         let user_reward_account = next_account_info(account_info_iter)?;
@@ -396,65 +1091,1470 @@ impl Processor {
         let pda_account = next_account_info(account_info_iter)?;
 
         let token_program = next_account_info(account_info_iter)?;
+        let rarity_account = next_account_info(account_info_iter)?;
+
+        let (mut platform_state_info, now) = Self::begin_harvest(
+            user,
+            platform_state,
+            user_reward_account,
+            pda_reward_token_account,
+            pda_account,
+            token_program,
+            program_id,
+        )?;
 
-        //pda with rewarder auth
-        msg!("1111111");
+        let total_reward = Self::harvest_one(
+            &mut platform_state_info,
+            user,
+            platform_state,
+            stake_record_account,
+            rarity_account,
+            program_id,
+            now,
+        )?;
 
+        Self::finish_harvest(
+            platform_state_info,
+            total_reward,
+            platform_state,
+            user_reward_account,
+            pda_reward_token_account,
+            pda_account,
+            token_program,
+        )
+    }
 
-        let pda_prefix = "rappid-paltform";
-        let pda_seed = &[pda_prefix.as_bytes(), (platform_state.key).as_ref()];
+    /// Harvests `count` stake records of the signer and pays them in a single transfer, see
+    /// `NftInstruction::HarvestAll` for the account layout.
+    pub fn process_harvest_all(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        count: u8,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let user = next_account_info(account_info_iter)?;
+        let platform_state = next_account_info(account_info_iter)?;
+        let user_reward_account = next_account_info(account_info_iter)?;
+        let pda_reward_token_account = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
 
-        let (pda, nonce) = Pubkey::find_program_address(pda_seed, program_id);
+        let (mut platform_state_info, now) = Self::begin_harvest(
+            user,
+            platform_state,
+            user_reward_account,
+            pda_reward_token_account,
+            pda_account,
+            token_program,
+            program_id,
+        )?;
 
-        let system_clock = Clock::get()?;
+        let mut total_reward = 0u64;
+        for _ in 0..count {
+            let stake_record_account = next_account_info(account_info_iter)?;
+            let rarity_account = next_account_info(account_info_iter)?;
+            let reward = Self::harvest_one(
+                &mut platform_state_info,
+                user,
+                platform_state,
+                stake_record_account,
+                rarity_account,
+                program_id,
+                now,
+            )?;
+            total_reward = total_reward
+                .checked_add(reward)
+                .ok_or(FarmError::NumericOverflow)?;
+        }
+        msg!("harvested {} records", count);
 
-        let mut user_data = UserState::unpack_unchecked(&user_state_account.try_borrow_data()?)?;
+        Self::finish_harvest(
+            platform_state_info,
+            total_reward,
+            platform_state,
+            user_reward_account,
+            pda_reward_token_account,
+            pda_account,
+            token_program,
+        )
+    }
+
+    pub fn process_query_pending_rewards(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let platform_state = next_account_info(account_info_iter)?;
+        assert_program_owned(platform_state, program_id)?;
+
+        // settles copies of the platform and records exactly like a harvest would, and
+        // drops them afterwards
+        let mut platform_state_info = PlatForm::unpack(&platform_state.try_borrow_data()?)?;
+        let system_clock = Clock::get()?;
+        let now = system_clock.unix_timestamp as u64;
+
+        let mut rewards = PendingRewards::default();
+        let mut queried: Vec<&Pubkey> = Vec::new();
+        for stake_record_account in account_info_iter {
+            assert_program_owned(stake_record_account, program_id)?;
+            // a record counted twice would report its rewards twice
+            if queried.contains(&stake_record_account.key) {
+                msg!("stake record {} passed twice", stake_record_account.key);
+                return Err(FarmError::DuplicateAccount.into());
+            }
+            queried.push(stake_record_account.key);
+            let mut record_data = StakeRecord::unpack(&stake_record_account.try_borrow_data()?)?;
+            if record_data.platform != *platform_state.key {
+                msg!("stake record of another platform");
+                return Err(ProgramError::InvalidAccountData);
+            }
+            Self::settle_stake_record(&mut platform_state_info, &mut record_data, now)?;
+
+            rewards.pending = rewards
+                .pending
+                .checked_add(record_data.accrued_reward)
+                .ok_or(FarmError::NumericOverflow)?;
+            if !platform_state_info.paused
+                && now >= record_data.unlock_time
+                && !platform_state_info
+                    .lock
+                    .is_soft_locked(record_data.stake_time, now)
+            {
+                rewards.claimable = rewards
+                    .claimable
+                    .checked_add(record_data.accrued_reward)
+                    .ok_or(FarmError::NumericOverflow)?;
+            }
+        }
+        msg!(
+            "pending {} claimable {}",
+            rewards.pending,
+            rewards.claimable
+        );
+
+        set_return_data(&rewards.pack());
+        Ok(())
+    }
+
+    /// Checks the accounts every harvest shares and loads the platform.
+    fn begin_harvest(
+        user: &AccountInfo,
+        platform_state: &AccountInfo,
+        user_reward_account: &AccountInfo,
+        pda_reward_token_account: &AccountInfo,
+        pda_account: &AccountInfo,
+        token_program: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> Result<(PlatForm, u64), ProgramError> {
+        assert_signer(user)?;
+        assert_program_owned(platform_state, program_id)?;
+        assert_writable(platform_state)?;
+        load_writable_token_account(user_reward_account)?;
+        load_writable_token_account(pda_reward_token_account)?;
+        assert_token_program(token_program)?;
+
+        //pda with rewarder auth
+        let platform_state_info = PlatForm::unpack(&platform_state.try_borrow_data()?)?;
+        if platform_state_info.paused {
+            return Err(FarmError::PlatformPaused.into());
+        }
+        Self::assert_vault_pda(
+            pda_account,
+            platform_state,
+            platform_state_info.vault_bump,
+            program_id,
+        )?;
+        Self::assert_reward_vault(&platform_state_info, pda_reward_token_account)?;
+
+        let system_clock = Clock::get()?;
+        let now = system_clock.unix_timestamp as u64;
+
+        Ok((platform_state_info, now))
+    }
+
+    /// Settles one stake record and picks up a changed rarity. Returns what it can pay out
+    /// now, which is booked as paid, or 0 while a lock keeps its rewards on the record.
+    fn harvest_one(
+        platform_state_info: &mut PlatForm,
+        user: &AccountInfo,
+        platform_state: &AccountInfo,
+        stake_record_account: &AccountInfo,
+        rarity_account: &AccountInfo,
+        program_id: &Pubkey,
+        now: u64,
+    ) -> Result<u64, ProgramError> {
+        assert_writable(stake_record_account)?;
+        let mut record_data =
+            Self::unpack_stake_record(stake_record_account, platform_state, user, program_id)?;
+
+        Self::settle_stake_record(platform_state_info, &mut record_data, now)?;
+        let total_reward = record_data.accrued_reward;
+
+        // everything up to now was settled at the old weight, a rarity set since the last
+        // harvest only applies from here on
+        let rarity_bps = if record_data.rarity_proven {
+            record_data.rarity_bps
+        } else {
+            Self::load_rarity(
+                rarity_account,
+                platform_state,
+                &record_data.mint,
+                record_data.rarity_bump,
+                program_id,
+            )?
+        };
+        if rarity_bps != record_data.rarity_bps {
+            let weight_bps =
+                math::apply_bps(record_data.tier_multiplier_bps as u64, rarity_bps as u64)?;
+            platform_state_info.total_weight_bps = platform_state_info
+                .total_weight_bps
+                .checked_sub(record_data.weight_bps)
+                .and_then(|total| total.checked_add(weight_bps))
+                .ok_or(FarmError::NumericOverflow)?;
+            msg!("weight {} -> {}", record_data.weight_bps, weight_bps);
+            record_data.weight_bps = weight_bps;
+            record_data.rarity_bps = rarity_bps;
+        }
+
+        msg!("total reward {}", total_reward);
+
+        // rewards earned inside a lock period stay on the record until it expires, so
+        // harvesting can't be used to dodge the early unstake penalty
+        let paid = if now >= record_data.unlock_time
+            && !platform_state_info
+                .lock
+                .is_soft_locked(record_data.stake_time, now)
+        {
+            Self::claim_stake_record(platform_state_info, &mut record_data, now)?
+        } else {
+            0
+        };
+
+        StakeRecord::pack(
+            record_data,
+            &mut stake_record_account.try_borrow_mut_data()?,
+        )?;
+
+        Ok(paid)
+    }
+
+    /// Pays out what the harvested records released and writes back the platform.
+    fn finish_harvest<'a>(
+        platform_state_info: PlatForm,
+        total_reward: u64,
+        platform_state: &AccountInfo<'a>,
+        user_reward_account: &AccountInfo<'a>,
+        pda_reward_token_account: &AccountInfo<'a>,
+        pda_account: &AccountInfo<'a>,
+        token_program: &AccountInfo<'a>,
+    ) -> ProgramResult {
+        if total_reward > 0 {
+            Self::transfer_reward(
+                token_program,
+                pda_reward_token_account,
+                user_reward_account,
+                pda_account,
+                platform_state,
+                platform_state_info.vault_bump,
+                total_reward,
+            )?;
+        }
+
+        PlatForm::pack(
+            platform_state_info,
+            &mut platform_state.try_borrow_mut_data()?,
+        )
+    }
+
+    pub fn process_update_reward_rate(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        reward_rate: u64,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let owner_account = next_account_info(account_info_iter)?;
+        let platform_state = next_account_info(account_info_iter)?;
+
+        let mut platform_state_info =
+            Self::unpack_platform_for_owner(owner_account, platform_state, program_id)?;
+        assert_writable(platform_state)?;
 
-        let total_staked_duration =
-        system_clock.unix_timestamp.clone() as u64 - user_data.last_staked_time;
-        let reward_per_day:u64 = 10;
-        let rewra_per_sec = ((reward_per_day as f32) / 86400.00)*100000.00;
+        // settle everything accrued so far at the old rate before switching
+        let system_clock = Clock::get()?;
+        Self::update_reward_per_nft(&mut platform_state_info, system_clock.unix_timestamp as u64)?;
+        msg!(
+            "reward rate {} -> {}",
+            platform_state_info.reward_rate,
+            reward_rate
+        );
+        platform_state_info.reward_rate = reward_rate;
 
-        
+        PlatForm::pack(
+            platform_state_info,
+            &mut platform_state.try_borrow_mut_data()?,
+        )?;
 
-        msg!("rewra_per_sec {}",rewra_per_sec);
-        msg!("total staked duration {}",total_staked_duration);
+        Ok(())
+    }
 
+    pub fn process_set_lock_tiers(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        tiers: &[LockTier],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let owner_account = next_account_info(account_info_iter)?;
+        let platform_state = next_account_info(account_info_iter)?;
 
-        let total_reward = ((total_staked_duration * rewra_per_sec as u64) / 1000);
-        msg!("total reward {}",total_reward);
+        let mut platform_state_info =
+            Self::unpack_platform_for_owner(owner_account, platform_state, program_id)?;
+        assert_writable(platform_state)?;
+
+        // a zero multiplier would stake an NFT that can never earn anything
+        if tiers.len() > MAX_LOCK_TIERS || tiers.iter().any(|tier| tier.multiplier_bps == 0) {
+            return Err(ProgramError::InvalidArgument);
+        }
+        platform_state_info.lock_tier_count = tiers.len() as u8;
+        platform_state_info.lock_tiers = [LockTier::default(); MAX_LOCK_TIERS];
+        platform_state_info.lock_tiers[..tiers.len()].copy_from_slice(tiers);
+        msg!("{} lock tiers", tiers.len());
+
+        PlatForm::pack(
+            platform_state_info,
+            &mut platform_state.try_borrow_mut_data()?,
+        )?;
 
-        if total_staked_duration > 86400 {
-            let transfer_token = transfer(
+        Ok(())
+    }
+
+    pub fn process_set_paused(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        paused: bool,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let owner_account = next_account_info(account_info_iter)?;
+        let platform_state = next_account_info(account_info_iter)?;
+
+        let mut platform_state_info =
+            Self::unpack_platform_for_owner(owner_account, platform_state, program_id)?;
+        assert_writable(platform_state)?;
+
+        // everything up to now accrues under the old state
+        let system_clock = Clock::get()?;
+        Self::update_reward_per_nft(&mut platform_state_info, system_clock.unix_timestamp as u64)?;
+        platform_state_info.paused = paused;
+        msg!("paused {}", paused);
+
+        PlatForm::pack(
+            platform_state_info,
+            &mut platform_state.try_borrow_mut_data()?,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn process_close_user_state(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let user = next_account_info(account_info_iter)?;
+        let user_state_account = next_account_info(account_info_iter)?;
+        let platform_state = next_account_info(account_info_iter)?;
+
+        assert_signer(user)?;
+        assert_writable(user)?;
+        assert_writable(user_state_account)?;
+
+        // the platform is only needed for the address, so this still works once it is closed
+        let user_data =
+            Self::unpack_user_state(user_state_account, platform_state, user, program_id)?;
+        if user_data.total_staked_nft != 0 {
+            return Err(FarmError::StillStaked.into());
+        }
+
+        Self::close_account(user_state_account, user)
+    }
+
+    pub fn process_close_platform(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let owner_account = next_account_info(account_info_iter)?;
+        let platform_state = next_account_info(account_info_iter)?;
+        let pda_reward_token_account = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+
+        let platform_state_info =
+            Self::unpack_platform_for_owner(owner_account, platform_state, program_id)?;
+        assert_writable(owner_account)?;
+        assert_writable(platform_state)?;
+        let vault = load_writable_token_account(pda_reward_token_account)?;
+        assert_token_program(token_program)?;
+        let nonce = platform_state_info.vault_bump;
+        Self::assert_vault_pda(pda_account, platform_state, nonce, program_id)?;
+        Self::assert_reward_vault(&platform_state_info, pda_reward_token_account)?;
+
+        if platform_state_info.total_staked_nft != 0 {
+            return Err(FarmError::StillStaked.into());
+        }
+        if vault.amount != 0 {
+            return Err(FarmError::RewardVaultNotEmpty.into());
+        }
+
+        let signer_seeds: &[&[u8]] =
+            &[VAULT_SEED.as_bytes(), platform_state.key.as_ref(), &[nonce]];
+        invoke_signed(
+            &spl_token::instruction::close_account(
                 token_program.key,
                 pda_reward_token_account.key,
-                user_reward_account.key,
-                &pda,
+                owner_account.key,
+                pda_account.key,
                 &[],
-                total_reward.clone(),
-            )?;
-            msg!("Calling the token program to transfer LP tokens pdatoken account...");
-            invoke_signed(
-                &transfer_token,
+            )?,
+            &[
+                pda_reward_token_account.clone(),
+                owner_account.clone(),
+                pda_account.clone(),
+                token_program.clone(),
+            ],
+            &[signer_seeds],
+        )?;
+
+        Self::close_account(platform_state, owner_account)
+    }
+
+    pub fn process_set_auto_close_user_state(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        enabled: bool,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let owner_account = next_account_info(account_info_iter)?;
+        let platform_state = next_account_info(account_info_iter)?;
+
+        let mut platform_state_info =
+            Self::unpack_platform_for_owner(owner_account, platform_state, program_id)?;
+        assert_writable(platform_state)?;
+
+        platform_state_info.close_empty_user_states = enabled;
+
+        PlatForm::pack(
+            platform_state_info,
+            &mut platform_state.try_borrow_mut_data()?,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn process_propose_owner(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        new_owner: Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let owner_account = next_account_info(account_info_iter)?;
+        let platform_state = next_account_info(account_info_iter)?;
+
+        let mut platform_state_info =
+            Self::unpack_platform_for_owner(owner_account, platform_state, program_id)?;
+        assert_writable(platform_state)?;
+
+        platform_state_info.pending_owner = new_owner;
+        msg!("pending owner {}", new_owner);
+
+        PlatForm::pack(
+            platform_state_info,
+            &mut platform_state.try_borrow_mut_data()?,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn process_accept_owner(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let new_owner_account = next_account_info(account_info_iter)?;
+        let platform_state = next_account_info(account_info_iter)?;
+
+        assert_signer(new_owner_account)?;
+        assert_program_owned(platform_state, program_id)?;
+        assert_writable(platform_state)?;
+
+        let mut platform_state_info = PlatForm::unpack(&platform_state.try_borrow_data()?)?;
+        if platform_state_info.pending_owner == Pubkey::default()
+            || platform_state_info.pending_owner != *new_owner_account.key
+        {
+            return Err(FarmError::InvalidAuthority.into());
+        }
+        platform_state_info.owner = platform_state_info.pending_owner;
+        platform_state_info.pending_owner = Pubkey::default();
+        msg!("owner {}", platform_state_info.owner);
+
+        PlatForm::pack(
+            platform_state_info,
+            &mut platform_state.try_borrow_mut_data()?,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn process_renounce_ownership(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let owner_account = next_account_info(account_info_iter)?;
+        let platform_state = next_account_info(account_info_iter)?;
+
+        let mut platform_state_info =
+            Self::unpack_platform_for_owner(owner_account, platform_state, program_id)?;
+        assert_writable(platform_state)?;
+
+        platform_state_info.owner = Pubkey::default();
+        platform_state_info.pending_owner = Pubkey::default();
+
+        PlatForm::pack(
+            platform_state_info,
+            &mut platform_state.try_borrow_mut_data()?,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn process_emergency_unstake(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let user = next_account_info(account_info_iter)?;
+        let user_state_account = next_account_info(account_info_iter)?;
+        let stake_record_account = next_account_info(account_info_iter)?;
+        let platform_state = next_account_info(account_info_iter)?;
+        let pda_token_account = next_account_info(account_info_iter)?;
+        let mint_key = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+        let system_program = next_account_info(account_info_iter)?;
+
+        assert_signer(user)?;
+        assert_writable(user)?;
+        assert_program_owned(user_state_account, program_id)?;
+        assert_writable(user_state_account)?;
+        assert_writable(stake_record_account)?;
+        assert_program_owned(platform_state, program_id)?;
+        assert_writable(platform_state)?;
+        let pda_token = load_writable_token_account(pda_token_account)?;
+        assert_token_program(token_program)?;
+        assert_system_program(system_program)?;
+
+        let mut user_data =
+            Self::unpack_user_state(user_state_account, platform_state, user, program_id)?;
+        let mut platform_state_info = PlatForm::unpack(&platform_state.try_borrow_data()?)?;
+        if !platform_state_info.paused {
+            return Err(FarmError::PlatformNotPaused.into());
+        }
+        let custody = Self::trailing_custody_accounts(&platform_state_info, account_info_iter)?;
+        let record_data =
+            Self::unpack_stake_record(stake_record_account, platform_state, user, program_id)?;
+        if record_data.mint != *mint_key.key || pda_token.mint != record_data.mint {
+            return Err(FarmError::InvalidMint.into());
+        }
+        Self::assert_vault_pda(
+            pda_account,
+            platform_state,
+            platform_state_info.vault_bump,
+            program_id,
+        )?;
+
+        Self::return_nft(
+            &platform_state_info,
+            &record_data,
+            user,
+            pda_token_account,
+            &pda_token,
+            mint_key,
+            platform_state,
+            pda_account,
+            token_program,
+            &mut custody.iter(),
+            program_id,
+        )?;
+
+        // the record is dropped without settling, so nothing of the reward math it may be
+        // paused for runs here. Saturating keeps a broken counter from trapping the NFT.
+        let unsettled = platform_state_info
+            .acc_reward_per_nft
+            .saturating_sub(record_data.reward_per_nft_paid)
+            .saturating_mul(record_data.weight_bps as u128);
+        platform_state_info.unsettled_reward = platform_state_info
+            .unsettled_reward
+            .saturating_sub(unsettled);
+        platform_state_info.total_accrued = platform_state_info
+            .total_accrued
+            .saturating_sub(record_data.accrued_reward);
+        platform_state_info.total_weight_bps = platform_state_info
+            .total_weight_bps
+            .saturating_sub(record_data.weight_bps);
+        platform_state_info.total_staked_nft =
+            platform_state_info.total_staked_nft.saturating_sub(1);
+        user_data.total_staked_nft = user_data.total_staked_nft.saturating_sub(1);
+        msg!("forfeited {}", record_data.accrued_reward);
+
+        Self::store_user_state(user_data, user_state_account, user, &platform_state_info)?;
+        PlatForm::pack(
+            platform_state_info,
+            &mut platform_state.try_borrow_mut_data()?,
+        )?;
+        Self::close_account(stake_record_account, user)?;
+
+        Ok(())
+    }
+
+    pub fn process_top_up_rewards(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        amount: u64,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let depositor = next_account_info(account_info_iter)?;
+        let source = next_account_info(account_info_iter)?;
+        let platform_state = next_account_info(account_info_iter)?;
+        let pda_reward_token_account = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+
+        assert_signer(depositor)?;
+        load_writable_token_account(source)?;
+        assert_program_owned(platform_state, program_id)?;
+        assert_writable(platform_state)?;
+        load_writable_token_account(pda_reward_token_account)?;
+        assert_token_program(token_program)?;
+
+        let mut platform_state_info = PlatForm::unpack(&platform_state.try_borrow_data()?)?;
+        Self::assert_vault_pda(
+            pda_account,
+            platform_state,
+            platform_state_info.vault_bump,
+            program_id,
+        )?;
+        Self::assert_reward_vault(&platform_state_info, pda_reward_token_account)?;
+
+        invoke(
+            &transfer(
+                token_program.key,
+                source.key,
+                pda_reward_token_account.key,
+                depositor.key,
+                &[],
+                amount,
+            )?,
+            &[
+                source.clone(),
+                pda_reward_token_account.clone(),
+                depositor.clone(),
+                token_program.clone(),
+            ],
+        )?;
+        platform_state_info.total_deposited = platform_state_info
+            .total_deposited
+            .checked_add(amount)
+            .ok_or(FarmError::NumericOverflow)?;
+        msg!("deposited {}", platform_state_info.total_deposited);
+
+        PlatForm::pack(
+            platform_state_info,
+            &mut platform_state.try_borrow_mut_data()?,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn process_withdraw_rewards(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        amount: u64,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let owner_account = next_account_info(account_info_iter)?;
+        let platform_state = next_account_info(account_info_iter)?;
+        let pda_reward_token_account = next_account_info(account_info_iter)?;
+        let destination = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+
+        let mut platform_state_info =
+            Self::unpack_platform_for_owner(owner_account, platform_state, program_id)?;
+        assert_writable(platform_state)?;
+        let vault = load_writable_token_account(pda_reward_token_account)?;
+        load_writable_token_account(destination)?;
+        assert_token_program(token_program)?;
+        let nonce = platform_state_info.vault_bump;
+        Self::assert_vault_pda(pda_account, platform_state, nonce, program_id)?;
+        Self::assert_reward_vault(&platform_state_info, pda_reward_token_account)?;
+
+        // bring what is owed up to now before checking what is left over
+        let system_clock = Clock::get()?;
+        Self::update_reward_per_nft(&mut platform_state_info, system_clock.unix_timestamp as u64)?;
+        let owed = platform_state_info.total_owed()?;
+        msg!("vault {} owed {}", vault.amount, owed);
+        let needed = owed.checked_add(amount).ok_or(FarmError::RewardsOwed)?;
+        if vault.amount < needed {
+            return Err(FarmError::RewardsOwed.into());
+        }
+
+        Self::transfer_reward(
+            token_program,
+            pda_reward_token_account,
+            destination,
+            pda_account,
+            platform_state,
+            nonce,
+            amount,
+        )?;
+        platform_state_info.total_withdrawn = platform_state_info
+            .total_withdrawn
+            .checked_add(amount)
+            .ok_or(FarmError::NumericOverflow)?;
+
+        PlatForm::pack(
+            platform_state_info,
+            &mut platform_state.try_borrow_mut_data()?,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn process_set_rarity_root(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        root: [u8; 32],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let owner_account = next_account_info(account_info_iter)?;
+        let platform_state = next_account_info(account_info_iter)?;
+
+        let mut platform_state_info =
+            Self::unpack_platform_for_owner(owner_account, platform_state, program_id)?;
+        assert_writable(platform_state)?;
+
+        // stakes already made keep the multiplier they proved
+        platform_state_info.rarity_root = root;
+
+        PlatForm::pack(
+            platform_state_info,
+            &mut platform_state.try_borrow_mut_data()?,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn process_set_rarity(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        entries: &[RarityEntry],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let owner_account = next_account_info(account_info_iter)?;
+        let platform_state = next_account_info(account_info_iter)?;
+        let system_program = next_account_info(account_info_iter)?;
+
+        Self::unpack_platform_for_owner(owner_account, platform_state, program_id)?;
+        assert_writable(owner_account)?;
+        assert_system_program(system_program)?;
+
+        if entries.len() > MAX_RARITY_ENTRIES
+            || entries.iter().any(|entry| entry.multiplier_bps == 0)
+        {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        for entry in entries {
+            let rarity_account = next_account_info(account_info_iter)?;
+            assert_writable(rarity_account)?;
+
+            let mut rarity = if rarity_account.owner == program_id {
+                let rarity = Rarity::unpack(&rarity_account.try_borrow_data()?)?;
+                let address = create_rarity_address(
+                    program_id,
+                    platform_state.key,
+                    &entry.mint,
+                    rarity.bump,
+                )?;
+                if address != *rarity_account.key {
+                    return Err(ProgramError::InvalidAccountData);
+                }
+                rarity
+            } else {
+                let (address, bump) =
+                    find_rarity_address(program_id, platform_state.key, &entry.mint);
+                if address != *rarity_account.key {
+                    return Err(ProgramError::InvalidAccountData);
+                }
+                Self::create_pda_account(
+                    owner_account,
+                    rarity_account,
+                    Rarity::LEN,
+                    program_id,
+                    system_program,
+                    &[
+                        RARITY_SEED.as_bytes(),
+                        platform_state.key.as_ref(),
+                        entry.mint.as_ref(),
+                        &[bump],
+                    ],
+                )?;
+                Rarity {
+                    is_initialized: true,
+                    platform: *platform_state.key,
+                    mint: entry.mint,
+                    multiplier_bps: DEFAULT_MULTIPLIER_BPS,
+                    bump,
+                }
+            };
+            msg!("rarity of {} is {}", entry.mint, entry.multiplier_bps);
+            rarity.multiplier_bps = entry.multiplier_bps;
+            Rarity::pack(rarity, &mut rarity_account.try_borrow_mut_data()?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Loads the platform for an owner-only instruction signed by `owner_account`.
+    fn unpack_platform_for_owner(
+        owner_account: &AccountInfo,
+        platform_state: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> Result<PlatForm, ProgramError> {
+        assert_signer(owner_account)?;
+        assert_program_owned(platform_state, program_id)?;
+
+        let platform_state_info = PlatForm::unpack(&platform_state.try_borrow_data()?)?;
+        // a renounced platform has no owner left to sign for it
+        if platform_state_info.owner == Pubkey::default()
+            || platform_state_info.owner != *owner_account.key
+        {
+            return Err(FarmError::InvalidAuthority.into());
+        }
+        Ok(platform_state_info)
+    }
+
+    /// Rarity multiplier of `mint` when it is staked, 1x when the platform owner never set
+    /// one, and the bump of its rarity account to store on the stake record. The account has
+    /// to be the mint's rarity PDA either way, so a set multiplier can't be skipped.
+    ///
+    /// An unset rarity account has no stored bump, and any other bump would let a staker pass
+    /// an empty address instead of the set one, so this is the one place its bump is searched.
+    fn find_rarity(
+        rarity_account: &AccountInfo,
+        platform_state: &AccountInfo,
+        mint: &Pubkey,
+        program_id: &Pubkey,
+    ) -> Result<(u16, u8), ProgramError> {
+        if rarity_account.owner != program_id {
+            let (address, bump) = find_rarity_address(program_id, platform_state.key, mint);
+            if address != *rarity_account.key {
+                msg!("rarity_acc wrong");
+                return Err(ProgramError::InvalidAccountData);
+            }
+            return Ok((DEFAULT_MULTIPLIER_BPS, bump));
+        }
+
+        let rarity = Rarity::unpack(&rarity_account.try_borrow_data()?)?;
+        let multiplier_bps = Self::load_rarity(
+            rarity_account,
+            platform_state,
+            mint,
+            rarity.bump,
+            program_id,
+        )?;
+        Ok((multiplier_bps, rarity.bump))
+    }
+
+    /// Rarity multiplier of `mint` from its rarity account at `bump`, as found at stake time.
+    fn load_rarity(
+        rarity_account: &AccountInfo,
+        platform_state: &AccountInfo,
+        mint: &Pubkey,
+        bump: u8,
+        program_id: &Pubkey,
+    ) -> Result<u16, ProgramError> {
+        let address = create_rarity_address(program_id, platform_state.key, mint, bump)?;
+        if address != *rarity_account.key {
+            msg!("rarity_acc wrong");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if rarity_account.owner != program_id {
+            return Ok(DEFAULT_MULTIPLIER_BPS);
+        }
+        Ok(Rarity::unpack(&rarity_account.try_borrow_data()?)?.multiplier_bps)
+    }
+
+    /// Loads a stake record, checking it is the platform + mint PDA and belongs to `user`.
+    fn unpack_stake_record(
+        stake_record_account: &AccountInfo,
+        platform_state: &AccountInfo,
+        user: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> Result<StakeRecord, ProgramError> {
+        assert_program_owned(stake_record_account, program_id)?;
+        let record_data = StakeRecord::unpack(&stake_record_account.try_borrow_data()?)?;
+
+        let stake_record = create_stake_record_address(
+            program_id,
+            platform_state.key,
+            &record_data.mint,
+            record_data.bump,
+        )?;
+        if stake_record != *stake_record_account.key || record_data.platform != *platform_state.key
+        {
+            msg!("stake_record_acc wrong");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if record_data.staker != *user.key {
+            return Err(FarmError::InvalidAuthority.into());
+        }
+
+        Ok(record_data)
+    }
+
+    /// Hands the NFT of `record` back to `user` from wherever the platform's custody mode
+    /// keeps it. `custody_accounts` is as for `stake_one`.
+    #[allow(clippy::too_many_arguments)]
+    fn return_nft<'a, 'b>(
+        platform: &PlatForm,
+        record: &StakeRecord,
+        user: &AccountInfo<'a>,
+        pda_token_account: &AccountInfo<'a>,
+        pda_token: &TokenAccount,
+        mint_key: &AccountInfo<'a>,
+        platform_state: &AccountInfo<'a>,
+        pda_account: &AccountInfo<'a>,
+        token_program: &AccountInfo<'a>,
+        custody_accounts: &mut std::slice::Iter<'b, AccountInfo<'a>>,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let pda_prefix = VAULT_SEED;
+        let nonce = platform.vault_bump;
+        match platform.custody_mode {
+            CustodyMode::Authority => {
+                let transfer_nft = spl_token::instruction::set_authority(
+                    token_program.key,
+                    pda_token_account.key,
+                    Some(user.key),
+                    spl_token::instruction::AuthorityType::AccountOwner,
+                    pda_account.key,
+                    &[],
+                )?;
+                invoke_signed(
+                    &transfer_nft,
+                    &[
+                        pda_token_account.clone(),
+                        pda_account.clone(),
+                        token_program.clone(),
+                    ],
+                    &[&[pda_prefix.as_bytes(), platform_state.key.as_ref(), &[nonce]]],
+                )?;
+            }
+            CustodyMode::Escrow => {
+                // the NFT goes back to a token account of the user, rent of the escrow too
+                if pda_token.owner != *user.key {
+                    return Err(FarmError::InvalidTokenAccountOwner.into());
+                }
+                let escrow_account = next_account_info(custody_accounts)?;
+                Self::close_escrow(
+                    user,
+                    pda_token_account,
+                    escrow_account,
+                    record,
+                    platform_state,
+                    pda_account,
+                    token_program,
+                    program_id,
+                    nonce,
+                )?;
+            }
+            CustodyMode::Frozen => {
+                if pda_token.owner != *user.key
+                    || pda_token.delegate != COption::Some(*pda_account.key)
+                {
+                    return Err(FarmError::InvalidTokenAccountOwner.into());
+                }
+                Self::set_frozen(
+                    false,
+                    pda_token_account,
+                    mint_key,
+                    platform_state,
+                    pda_account,
+                    token_program,
+                    custody_accounts,
+                    nonce,
+                )?;
+                invoke(
+                    &spl_token::instruction::revoke(
+                        token_program.key,
+                        pda_token_account.key,
+                        user.key,
+                        &[],
+                    )?,
+                    &[
+                        pda_token_account.clone(),
+                        user.clone(),
+                        token_program.clone(),
+                    ],
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Creates the escrow token account of the mint, owned by the platform PDA, and moves
+    /// the NFT into it. Returns the escrow bump to store on the stake record.
+    #[allow(clippy::too_many_arguments)]
+    fn open_escrow<'a>(
+        user: &AccountInfo<'a>,
+        token_account: &AccountInfo<'a>,
+        escrow_account: &AccountInfo<'a>,
+        mint: &AccountInfo<'a>,
+        platform_state: &AccountInfo<'a>,
+        pda_account: &AccountInfo<'a>,
+        token_program: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        program_id: &Pubkey,
+    ) -> Result<u8, ProgramError> {
+        assert_writable(escrow_account)?;
+        let (escrow, escrow_bump) = find_escrow_address(program_id, platform_state.key, mint.key);
+        if escrow != *escrow_account.key {
+            msg!("escrow account wrong");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Self::create_pda_account(
+            user,
+            escrow_account,
+            TokenAccount::LEN,
+            token_program.key,
+            system_program,
+            &[
+                ESCROW_SEED.as_bytes(),
+                platform_state.key.as_ref(),
+                mint.key.as_ref(),
+                &[escrow_bump],
+            ],
+        )?;
+        invoke(
+            &spl_token::instruction::initialize_account3(
+                token_program.key,
+                escrow_account.key,
+                mint.key,
+                pda_account.key,
+            )?,
+            &[escrow_account.clone(), mint.clone(), token_program.clone()],
+        )?;
+        invoke(
+            &transfer(
+                token_program.key,
+                token_account.key,
+                escrow_account.key,
+                user.key,
+                &[],
+                1,
+            )?,
+            &[
+                token_account.clone(),
+                escrow_account.clone(),
+                user.clone(),
+                token_program.clone(),
+            ],
+        )?;
+
+        Ok(escrow_bump)
+    }
+
+    /// Returns the NFT out of its escrow into `token_account` and closes the escrow, paying
+    /// its rent back to the user.
+    #[allow(clippy::too_many_arguments)]
+    fn close_escrow<'a>(
+        user: &AccountInfo<'a>,
+        token_account: &AccountInfo<'a>,
+        escrow_account: &AccountInfo<'a>,
+        record: &StakeRecord,
+        platform_state: &AccountInfo<'a>,
+        pda_account: &AccountInfo<'a>,
+        token_program: &AccountInfo<'a>,
+        program_id: &Pubkey,
+        nonce: u8,
+    ) -> ProgramResult {
+        let escrow = create_escrow_address(
+            program_id,
+            platform_state.key,
+            &record.mint,
+            record.escrow_bump,
+        )?;
+        if escrow != *escrow_account.key {
+            msg!("escrow account wrong");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let escrow_info = load_writable_token_account(escrow_account)?;
+        if escrow_info.owner != *pda_account.key || escrow_info.mint != record.mint {
+            return Err(FarmError::InvalidTokenAccountOwner.into());
+        }
+        if escrow_info.delegate.is_some() || escrow_info.close_authority.is_some() {
+            return Err(FarmError::TokenAccountHasAuthority.into());
+        }
+
+        let signer_seeds: &[&[u8]] =
+            &[VAULT_SEED.as_bytes(), platform_state.key.as_ref(), &[nonce]];
+        invoke_signed(
+            &transfer(
+                token_program.key,
+                escrow_account.key,
+                token_account.key,
+                pda_account.key,
+                &[],
+                escrow_info.amount,
+            )?,
+            &[
+                escrow_account.clone(),
+                token_account.clone(),
+                pda_account.clone(),
+                token_program.clone(),
+            ],
+            &[signer_seeds],
+        )?;
+        invoke_signed(
+            &spl_token::instruction::close_account(
+                token_program.key,
+                escrow_account.key,
+                user.key,
+                pda_account.key,
+                &[],
+            )?,
+            &[
+                escrow_account.clone(),
+                user.clone(),
+                pda_account.clone(),
+                token_program.clone(),
+            ],
+            &[signer_seeds],
+        )
+    }
+
+    /// Freezes or thaws a soft-staked token account. Mints whose freeze authority is the
+    /// platform PDA are frozen directly; otherwise the freeze authority has to be the
+    /// Metaplex master edition, passed with the token metadata program as the next accounts.
+    #[allow(clippy::too_many_arguments)]
+    fn set_frozen<'a, 'b>(
+        freeze: bool,
+        token_account: &AccountInfo<'a>,
+        mint: &AccountInfo<'a>,
+        platform_state: &AccountInfo<'a>,
+        pda_account: &AccountInfo<'a>,
+        token_program: &AccountInfo<'a>,
+        account_info_iter: &mut std::slice::Iter<'b, AccountInfo<'a>>,
+        nonce: u8,
+    ) -> ProgramResult {
+        let freeze_authority = Mint::unpack(&mint.try_borrow_data()?)?.freeze_authority;
+        let signer_seeds: &[&[u8]] =
+            &[VAULT_SEED.as_bytes(), platform_state.key.as_ref(), &[nonce]];
+
+        if freeze_authority == COption::Some(*pda_account.key) {
+            let instruction = if freeze {
+                spl_token::instruction::freeze_account(
+                    token_program.key,
+                    token_account.key,
+                    mint.key,
+                    pda_account.key,
+                    &[],
+                )?
+            } else {
+                spl_token::instruction::thaw_account(
+                    token_program.key,
+                    token_account.key,
+                    mint.key,
+                    pda_account.key,
+                    &[],
+                )?
+            };
+            return invoke_signed(
+                &instruction,
                 &[
-                    pda_reward_token_account.clone(),
-                    user_reward_account.clone(),
+                    token_account.clone(),
+                    mint.clone(),
                     pda_account.clone(),
                     token_program.clone(),
                 ],
-                &[&[
-                    pda_prefix.as_bytes(),
-                    &(platform_state.key).as_ref()[..],
-                    &[nonce],
-                ]],
+                &[signer_seeds],
+            );
+        }
+
+        let edition = next_account_info(account_info_iter)?;
+        let metadata_program = next_account_info(account_info_iter)?;
+        if freeze_authority != COption::Some(*edition.key)
+            || *metadata_program.key != mpl_token_metadata::id()
+        {
+            return Err(FarmError::CannotFreeze.into());
+        }
+        let instruction = if freeze {
+            freeze_delegated_account(pda_account.key, token_account.key, edition.key, mint.key)
+        } else {
+            thaw_delegated_account(pda_account.key, token_account.key, edition.key, mint.key)
+        };
+        invoke_signed(
+            &instruction,
+            &[
+                pda_account.clone(),
+                token_account.clone(),
+                edition.clone(),
+                mint.clone(),
+                token_program.clone(),
+                metadata_program.clone(),
+            ],
+            &[signer_seeds],
+        )
+    }
+
+    /// Loads a user state, checking it is the platform + user PDA derived with its stored bump.
+    fn unpack_user_state(
+        user_state_account: &AccountInfo,
+        platform_state: &AccountInfo,
+        user: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> Result<UserState, ProgramError> {
+        assert_program_owned(user_state_account, program_id)?;
+        let user_data = UserState::unpack(&user_state_account.try_borrow_data()?)?;
+
+        let user_state =
+            create_user_state_address(program_id, platform_state.key, user.key, user_data.bump)?;
+        if user_state != *user_state_account.key || user_data.user != *user.key {
+            msg!("user_state_acc wrong");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(user_data)
+    }
+
+    /// Checks `pda_account` is the platform PDA, re-derived from the bump stored at init.
+    fn assert_vault_pda(
+        pda_account: &AccountInfo,
+        platform_state: &AccountInfo,
+        vault_bump: u8,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let pda = create_vault_address(program_id, platform_state.key, vault_bump)?;
+        if pda != *pda_account.key {
+            msg!("error with farm pda");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(())
+    }
+
+    /// Checks `reward_vault` is the vault the platform was initialized with. Other token
+    /// accounts of the platform PDA, like escrows, must never pay out rewards.
+    fn assert_reward_vault(platform: &PlatForm, reward_vault: &AccountInfo) -> ProgramResult {
+        if platform.reward_vault != *reward_vault.key {
+            return Err(FarmError::InvalidRewardVault.into());
+        }
+        Ok(())
+    }
+
+    /// Books a record's settled rewards as done with, `paid` of them to the staker and the
+    /// rest forfeited to the vault.
+    fn release_reward(platform: &mut PlatForm, settled: u64, paid: u64) -> Result<(), FarmError> {
+        platform.total_accrued = platform
+            .total_accrued
+            .checked_sub(settled)
+            .ok_or(FarmError::NumericOverflow)?;
+        platform.total_paid = platform
+            .total_paid
+            .checked_add(paid)
+            .ok_or(FarmError::NumericOverflow)?;
+        Ok(())
+    }
+
+    /// Pays out what a settled record has accrued and marks it claimed at `now`. The record
+    /// only ever holds what accrued since its last claim, so claiming again within the same
+    /// second pays nothing.
+    fn claim_stake_record(
+        platform: &mut PlatForm,
+        record: &mut StakeRecord,
+        now: u64,
+    ) -> Result<u64, FarmError> {
+        let paid = record.accrued_reward;
+        Self::release_reward(platform, paid, paid)?;
+        record.accrued_reward = 0;
+        record.last_claim_time = now;
+        Ok(paid)
+    }
+
+    /// Moves everything accrued since the record was last settled into `accrued_reward`.
+    fn settle_stake_record(
+        platform: &mut PlatForm,
+        record: &mut StakeRecord,
+        now: u64,
+    ) -> Result<(), FarmError> {
+        Self::update_reward_per_nft(platform, now)?;
+        let pending = math::pending_weighted_reward(
+            platform.acc_reward_per_nft,
+            record.reward_per_nft_paid,
+            record.weight_bps,
+        )?;
+        record.accrued_reward = record
+            .accrued_reward
+            .checked_add(pending)
+            .ok_or(FarmError::NumericOverflow)?;
+        let unsettled = platform
+            .acc_reward_per_nft
+            .checked_sub(record.reward_per_nft_paid)
+            .and_then(|accrued| accrued.checked_mul(record.weight_bps as u128))
+            .ok_or(FarmError::NumericOverflow)?;
+        platform.unsettled_reward = platform
+            .unsettled_reward
+            .checked_sub(unsettled)
+            .ok_or(FarmError::NumericOverflow)?;
+        platform.total_accrued = platform
+            .total_accrued
+            .checked_add(pending)
+            .ok_or(FarmError::NumericOverflow)?;
+        record.reward_per_nft_paid = platform.acc_reward_per_nft;
+        Ok(())
+    }
+
+    /// Pays `amount` out of the reward vault, signing as the platform PDA.
+    fn transfer_reward<'a>(
+        token_program: &AccountInfo<'a>,
+        pda_reward_token_account: &AccountInfo<'a>,
+        user_reward_account: &AccountInfo<'a>,
+        pda_account: &AccountInfo<'a>,
+        platform_state: &AccountInfo<'a>,
+        nonce: u8,
+        amount: u64,
+    ) -> ProgramResult {
+        let pda_prefix = VAULT_SEED;
+        let transfer_token = transfer(
+            token_program.key,
+            pda_reward_token_account.key,
+            user_reward_account.key,
+            pda_account.key,
+            &[],
+            amount,
+        )?;
+        msg!("paying {} from the reward vault", amount);
+        invoke_signed(
+            &transfer_token,
+            &[
+                pda_reward_token_account.clone(),
+                user_reward_account.clone(),
+                pda_account.clone(),
+                token_program.clone(),
+            ],
+            &[&[pda_prefix.as_bytes(), platform_state.key.as_ref(), &[nonce]]],
+        )
+    }
+
+    /// Writes back a user state after an unstake, or closes it to `user` when it is left
+    /// empty on a platform that closes empty user states.
+    fn store_user_state(
+        user_data: UserState,
+        user_state_account: &AccountInfo,
+        user: &AccountInfo,
+        platform: &PlatForm,
+    ) -> ProgramResult {
+        if user_data.total_staked_nft == 0 && platform.close_empty_user_states {
+            return Self::close_account(user_state_account, user);
+        }
+        UserState::pack(user_data, &mut user_state_account.try_borrow_mut_data()?)
+    }
+
+    /// Creates the PDA `account` with `space` bytes for `owner`, paid by `payer`.
+    /// `create_account` fails on an address that already holds lamports, and anyone can send
+    /// some there, so such an account is topped up to rent exemption and allocated and
+    /// assigned instead.
+    fn create_pda_account<'a>(
+        payer: &AccountInfo<'a>,
+        account: &AccountInfo<'a>,
+        space: usize,
+        owner: &Pubkey,
+        system_program: &AccountInfo<'a>,
+        signer_seeds: &[&[u8]],
+    ) -> ProgramResult {
+        let required_lamports = Rent::get()?.minimum_balance(space);
+        let current_lamports = account.lamports();
+        if current_lamports == 0 {
+            return invoke_signed(
+                &create_account(
+                    payer.key,
+                    account.key,
+                    required_lamports,
+                    space as u64,
+                    owner,
+                ),
+                &[payer.clone(), account.clone(), system_program.clone()],
+                &[signer_seeds],
+            );
+        }
+
+        if current_lamports < required_lamports {
+            invoke(
+                &system_instruction::transfer(
+                    payer.key,
+                    account.key,
+                    required_lamports - current_lamports,
+                ),
+                &[payer.clone(), account.clone(), system_program.clone()],
             )?;
-    
         }
+        invoke_signed(
+            &allocate(account.key, space as u64),
+            &[account.clone(), system_program.clone()],
+            &[signer_seeds],
+        )?;
+        invoke_signed(
+            &assign(account.key, owner),
+            &[account.clone(), system_program.clone()],
+            &[signer_seeds],
+        )
+    }
 
-       
-        UserState::pack(user_data, &mut user_state_account.try_borrow_mut_data()?)?;
-        msg!("user packed!!!!");
+    /// Drains `account` into `destination` and wipes its data so the runtime reclaims it.
+    fn close_account(account: &AccountInfo, destination: &AccountInfo) -> ProgramResult {
+        let destination_starting_lamports = destination.lamports();
+        **destination.try_borrow_mut_lamports()? = destination_starting_lamports
+            .checked_add(account.lamports())
+            .ok_or(FarmError::NumericOverflow)?;
+        **account.try_borrow_mut_lamports()? = 0;
+        account.try_borrow_mut_data()?.fill(0);
+        Ok(())
+    }
 
+    /// Accrues `reward_rate` into the per-NFT accumulator for every second since the last update.
+    /// The accumulator tracks what a 1x NFT earns; records scale it by their own weight.
+    ///
+    /// Has to run before `total_weight_bps` changes: in emission mode the elapsed emission is
+    /// split by weight over the NFTs that were staked while it accrued. Emission with nothing
+    /// staked is not owed to anyone and stays in the vault, and so is anything while paused.
+    fn update_reward_per_nft(platform: &mut PlatForm, now: u64) -> Result<(), FarmError> {
+        let elapsed = if platform.paused {
+            0
+        } else {
+            now.saturating_sub(platform.last_update_time)
+        };
+        let accrued = match platform.reward_mode {
+            RewardMode::PerNft => math::accrued_per_nft(platform.reward_rate, elapsed)?,
+            RewardMode::Emission if platform.total_weight_bps == 0 => 0,
+            RewardMode::Emission => {
+                math::accrued_per_weight(platform.reward_rate, elapsed, platform.total_weight_bps)?
+            }
+        };
+        platform.acc_reward_per_nft = platform
+            .acc_reward_per_nft
+            .checked_add(accrued)
+            .ok_or(FarmError::NumericOverflow)?;
+        platform.unsettled_reward = accrued
+            .checked_mul(platform.total_weight_bps as u128)
+            .and_then(|owed| owed.checked_add(platform.unsettled_reward))
+            .ok_or(FarmError::NumericOverflow)?;
+        platform.last_update_time = platform.last_update_time.max(now);
         Ok(())
     }
 }
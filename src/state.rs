@@ -1,14 +1,342 @@
+use crate::{error::FarmError, math};
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
 use solana_program::{
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
     pubkey::Pubkey,
 };
+
+/// Seed prefix of the platform PDA that owns staked NFTs and the reward vault.
+pub const VAULT_SEED: &str = "rappid-paltform";
+/// Seed prefix of a user's `UserState` PDA.
+pub const USER_STATE_SEED: &str = "user-state";
+/// Seed prefix of an NFT's `StakeRecord` PDA.
+pub const STAKE_RECORD_SEED: &str = "stake-record";
+/// Seed prefix of the escrow token account holding an NFT under `CustodyMode::Escrow`.
+pub const ESCROW_SEED: &str = "escrow";
+/// Seed prefix of a mint's `Rarity` PDA.
+pub const RARITY_SEED: &str = "rarity";
+
+/// Platform PDA that custodies staked NFTs and signs for the reward vault.
+pub fn find_vault_address(program_id: &Pubkey, platform: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[VAULT_SEED.as_bytes(), platform.as_ref()], program_id)
+}
+
+pub fn find_user_state_address(
+    program_id: &Pubkey,
+    platform: &Pubkey,
+    user: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[USER_STATE_SEED.as_bytes(), platform.as_ref(), user.as_ref()],
+        program_id,
+    )
+}
+
+pub fn find_stake_record_address(
+    program_id: &Pubkey,
+    platform: &Pubkey,
+    mint: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            STAKE_RECORD_SEED.as_bytes(),
+            platform.as_ref(),
+            mint.as_ref(),
+        ],
+        program_id,
+    )
+}
+
+/// Escrow token account of `mint` on `platform`, owned by the platform PDA.
+pub fn find_escrow_address(program_id: &Pubkey, platform: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[ESCROW_SEED.as_bytes(), platform.as_ref(), mint.as_ref()],
+        program_id,
+    )
+}
+
+/// Re-derives the platform PDA from its stored bump, which costs a single hash instead of
+/// the bump search of `find_vault_address`.
+pub fn create_vault_address(
+    program_id: &Pubkey,
+    platform: &Pubkey,
+    bump: u8,
+) -> Result<Pubkey, ProgramError> {
+    Pubkey::create_program_address(
+        &[VAULT_SEED.as_bytes(), platform.as_ref(), &[bump]],
+        program_id,
+    )
+    .map_err(|_| ProgramError::InvalidSeeds)
+}
+
+pub fn create_user_state_address(
+    program_id: &Pubkey,
+    platform: &Pubkey,
+    user: &Pubkey,
+    bump: u8,
+) -> Result<Pubkey, ProgramError> {
+    Pubkey::create_program_address(
+        &[
+            USER_STATE_SEED.as_bytes(),
+            platform.as_ref(),
+            user.as_ref(),
+            &[bump],
+        ],
+        program_id,
+    )
+    .map_err(|_| ProgramError::InvalidSeeds)
+}
+
+pub fn create_stake_record_address(
+    program_id: &Pubkey,
+    platform: &Pubkey,
+    mint: &Pubkey,
+    bump: u8,
+) -> Result<Pubkey, ProgramError> {
+    Pubkey::create_program_address(
+        &[
+            STAKE_RECORD_SEED.as_bytes(),
+            platform.as_ref(),
+            mint.as_ref(),
+            &[bump],
+        ],
+        program_id,
+    )
+    .map_err(|_| ProgramError::InvalidSeeds)
+}
+
+pub fn create_escrow_address(
+    program_id: &Pubkey,
+    platform: &Pubkey,
+    mint: &Pubkey,
+    bump: u8,
+) -> Result<Pubkey, ProgramError> {
+    Pubkey::create_program_address(
+        &[
+            ESCROW_SEED.as_bytes(),
+            platform.as_ref(),
+            mint.as_ref(),
+            &[bump],
+        ],
+        program_id,
+    )
+    .map_err(|_| ProgramError::InvalidSeeds)
+}
+
+/// Rarity multiplier of `mint` on `platform`, set by the platform owner.
+pub fn find_rarity_address(program_id: &Pubkey, platform: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[RARITY_SEED.as_bytes(), platform.as_ref(), mint.as_ref()],
+        program_id,
+    )
+}
+
+pub fn create_rarity_address(
+    program_id: &Pubkey,
+    platform: &Pubkey,
+    mint: &Pubkey,
+    bump: u8,
+) -> Result<Pubkey, ProgramError> {
+    Pubkey::create_program_address(
+        &[
+            RARITY_SEED.as_bytes(),
+            platform.as_ref(),
+            mint.as_ref(),
+            &[bump],
+        ],
+        program_id,
+    )
+    .map_err(|_| ProgramError::InvalidSeeds)
+}
+
+/// Maximum number of verified creators a platform can allow.
+pub const MAX_ALLOWED_CREATORS: usize = 5;
+
+/// Maximum number of lock tiers a platform can offer besides the default tier 0.
+pub const MAX_LOCK_TIERS: usize = 4;
+
+/// Reward multiplier of an NFT that is neither locked in a tier nor otherwise boosted.
+pub const DEFAULT_MULTIPLIER_BPS: u16 = 10_000;
+
+/// How `StakeNft` decides whether an NFT belongs to the platform.
+#[repr(u8)]
+#[derive(Debug, PartialEq, Copy, Clone, FromPrimitive)]
+pub enum CollectionRule {
+    /// The metadata has a verified Metaplex collection equal to `PlatForm::collection`.
+    VerifiedCollection,
+    /// The metadata lists one of `PlatForm::creators` as a verified creator.
+    VerifiedCreator,
+}
+
+/// How `PlatForm::reward_rate` is shared out between staked NFTs.
+#[repr(u8)]
+#[derive(Debug, PartialEq, Copy, Clone, FromPrimitive)]
+pub enum RewardMode {
+    /// Every staked NFT earns `reward_rate` a second, however many are staked.
+    PerNft,
+    /// The pool emits `reward_rate` a second in total, split evenly over the staked NFTs.
+    Emission,
+}
+
+/// Where a staked NFT is kept while it earns rewards.
+#[repr(u8)]
+#[derive(Debug, PartialEq, Copy, Clone, FromPrimitive)]
+pub enum CustodyMode {
+    /// The user's token account is handed to the platform PDA with `SetAuthority`.
+    Authority,
+    /// The NFT is transferred into a per-mint escrow token account owned by the platform
+    /// PDA, which is closed again on unstake.
+    Escrow,
+    /// The NFT stays in the user's wallet: the platform PDA is approved as delegate and
+    /// the token account is frozen, through the mint's freeze authority if that is the
+    /// platform PDA or through the Metaplex edition delegate otherwise.
+    Frozen,
+}
+
+/// Lock rules applied to every NFT from the moment it is staked.
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+pub struct LockConfig {
+    /// Seconds after staking during which `UnStakeNft` is rejected, 0 for no hard lock.
+    pub hard_lock_duration: u64,
+    /// Seconds after staking during which unstaking forfeits `early_unstake_penalty_bps`
+    /// of the accrued rewards to the reward vault, 0 for no soft lock.
+    pub soft_lock_duration: u64,
+    pub early_unstake_penalty_bps: u16,
+}
+impl LockConfig {
+    pub const LEN: usize = 18;
+
+    pub fn unpack_from_array(src: &[u8; LockConfig::LEN]) -> Self {
+        let (hard_lock_duration, soft_lock_duration, early_unstake_penalty_bps) =
+            array_refs![src, 8, 8, 2];
+        LockConfig {
+            hard_lock_duration: u64::from_le_bytes(*hard_lock_duration),
+            soft_lock_duration: u64::from_le_bytes(*soft_lock_duration),
+            early_unstake_penalty_bps: u16::from_le_bytes(*early_unstake_penalty_bps),
+        }
+    }
+
+    pub fn pack_into_array(&self, dst: &mut [u8; LockConfig::LEN]) {
+        let (hard_lock_duration_dst, soft_lock_duration_dst, early_unstake_penalty_bps_dst) =
+            mut_array_refs![dst, 8, 8, 2];
+        *hard_lock_duration_dst = self.hard_lock_duration.to_le_bytes();
+        *soft_lock_duration_dst = self.soft_lock_duration.to_le_bytes();
+        *early_unstake_penalty_bps_dst = self.early_unstake_penalty_bps.to_le_bytes();
+    }
+
+    pub fn is_soft_locked(&self, stake_time: u64, now: u64) -> bool {
+        now < stake_time.saturating_add(self.soft_lock_duration)
+    }
+}
+
+/// A lock period a staker can opt into at `StakeNft` in exchange for a reward multiplier.
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+pub struct LockTier {
+    /// Seconds after staking before the NFT can be unstaked.
+    pub duration: u64,
+    /// Reward multiplier in basis points, 10_000 for 1x.
+    pub multiplier_bps: u16,
+}
+impl LockTier {
+    pub const LEN: usize = 10;
+
+    pub fn unpack_from_array(src: &[u8; LockTier::LEN]) -> Self {
+        let (duration, multiplier_bps) = array_refs![src, 8, 2];
+        LockTier {
+            duration: u64::from_le_bytes(*duration),
+            multiplier_bps: u16::from_le_bytes(*multiplier_bps),
+        }
+    }
+
+    pub fn pack_into_array(&self, dst: &mut [u8; LockTier::LEN]) {
+        let (duration_dst, multiplier_bps_dst) = mut_array_refs![dst, 8, 2];
+        *duration_dst = self.duration.to_le_bytes();
+        *multiplier_bps_dst = self.multiplier_bps.to_le_bytes();
+    }
+}
+
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub struct PlatForm {
     pub is_initialized: bool,
     pub owner: Pubkey,
     pub total_staked_nft: u64,
+    /// Reward base units paid per second, per NFT or for the whole pool depending on
+    /// `reward_mode`.
+    pub reward_rate: u64,
+    /// Rewards accrued per staked NFT since initialization, scaled by `math::PRECISION`.
+    pub acc_reward_per_nft: u128,
+    pub last_update_time: u64,
+    pub collection_rule: CollectionRule,
+    /// Verified Metaplex collection an NFT has to belong to in order to be staked.
+    pub collection: Pubkey,
+    pub creator_count: u8,
+    /// Verified creators accepted under `CollectionRule::VerifiedCreator`.
+    pub creators: [Pubkey; MAX_ALLOWED_CREATORS],
+    /// Bump seed of the platform PDA, see `create_vault_address`.
+    pub vault_bump: u8,
+    pub reward_mode: RewardMode,
+    pub custody_mode: CustodyMode,
+    pub lock: LockConfig,
+    pub lock_tier_count: u8,
+    /// Tiers `1..=lock_tier_count` offered to stakers, see `PlatForm::lock_tier`.
+    pub lock_tiers: [LockTier; MAX_LOCK_TIERS],
+    /// Sum of `StakeRecord::weight_bps` over every staked NFT.
+    pub total_weight_bps: u64,
+    /// Merkle root of the (mint, multiplier) rarity leaves stakers can prove against, see
+    /// `merkle`. All zeroes when the platform has none.
+    pub rarity_root: [u8; 32],
+    /// Token account rewards are paid from, owned by the platform PDA.
+    pub reward_vault: Pubkey,
+    /// Reward tokens put into the vault at init and through `TopUpRewards`.
+    pub total_deposited: u64,
+    /// Reward tokens taken back out by the owner through `WithdrawRewards`.
+    pub total_withdrawn: u64,
+    /// Rewards paid out to stakers.
+    pub total_paid: u64,
+    /// Sum of `StakeRecord::accrued_reward` over every staked NFT.
+    pub total_accrued: u64,
+    /// Weighted accruals not yet settled into any record, `weight_bps * (acc - paid)` summed
+    /// over every staked NFT.
+    pub unsettled_reward: u128,
+    /// Set by the owner through `SetPaused`. Nothing accrues and nothing can be staked or
+    /// harvested while it is, but NFTs can still leave.
+    pub paused: bool,
+    /// Key proposed by the owner through `ProposeOwner`, which has to sign `AcceptOwner` to
+    /// take over. `Pubkey::default()` when nothing is pending.
+    pub pending_owner: Pubkey,
+    /// Whether a user state is closed, and its rent returned, as soon as its last NFT is
+    /// unstaked. Set by the owner through `SetAutoCloseUserState`.
+    pub close_empty_user_states: bool,
+}
+impl PlatForm {
+    /// Rewards stakers have earned and not been paid yet, which the vault has to keep.
+    /// Only current as of `last_update_time`.
+    pub fn total_owed(&self) -> Result<u64, FarmError> {
+        self.total_accrued
+            .checked_add(math::unsettled_amount(self.unsettled_reward)?)
+            .ok_or(FarmError::NumericOverflow)
+    }
+
+    pub fn allowed_creators(&self) -> &[Pubkey] {
+        let count = (self.creator_count as usize).min(MAX_ALLOWED_CREATORS);
+        &self.creators[..count]
+    }
+
+    /// The tier a staker picked by `index`. Tier 0 is always available and adds neither a
+    /// lock nor a multiplier; the configured tiers are numbered from 1.
+    pub fn lock_tier(&self, index: u8) -> Option<LockTier> {
+        match index {
+            0 => Some(LockTier {
+                duration: 0,
+                multiplier_bps: DEFAULT_MULTIPLIER_BPS,
+            }),
+            _ if index <= self.lock_tier_count => self.lock_tiers.get(index as usize - 1).copied(),
+            _ => None,
+        }
+    }
 }
 impl Sealed for PlatForm {}
 impl IsInitialized for PlatForm {
@@ -17,41 +345,257 @@ impl IsInitialized for PlatForm {
     }
 }
 impl Pack for PlatForm {
-    const LEN: usize = 41;
+    const LEN: usize = 483;
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
         let src = array_ref![src, 0, PlatForm::LEN];
-        let (is_initialized, owner, total_staked_nft) = array_refs![src, 1, 32, 8];
+        let (
+            is_initialized,
+            owner,
+            total_staked_nft,
+            reward_rate,
+            acc_reward_per_nft,
+            last_update_time,
+            collection_rule,
+            collection,
+            creator_count,
+            creators_src,
+            vault_bump,
+            reward_mode,
+            custody_mode,
+            lock,
+            lock_tier_count,
+            lock_tiers_src,
+            total_weight_bps,
+            rarity_root,
+            reward_vault,
+            total_deposited,
+            total_withdrawn,
+            total_paid,
+            total_accrued,
+            unsettled_reward,
+            paused,
+            pending_owner,
+            close_empty_user_states,
+        ) = array_refs![
+            src,
+            1,
+            32,
+            8,
+            8,
+            16,
+            8,
+            1,
+            32,
+            1,
+            32 * MAX_ALLOWED_CREATORS,
+            1,
+            1,
+            1,
+            LockConfig::LEN,
+            1,
+            LockTier::LEN * MAX_LOCK_TIERS,
+            8,
+            32,
+            32,
+            8,
+            8,
+            8,
+            8,
+            16,
+            1,
+            32,
+            1
+        ];
         let is_initialized = match is_initialized {
             [0] => false,
             [1] => true,
             _ => return Err(ProgramError::InvalidAccountData),
         };
+        let mut creators = [Pubkey::default(); MAX_ALLOWED_CREATORS];
+        for (creator, src) in creators.iter_mut().zip(creators_src.chunks_exact(32)) {
+            *creator = Pubkey::new(src);
+        }
+        let mut lock_tiers = [LockTier::default(); MAX_LOCK_TIERS];
+        for (tier, src) in lock_tiers
+            .iter_mut()
+            .zip(lock_tiers_src.chunks_exact(LockTier::LEN))
+        {
+            *tier = LockTier::unpack_from_array(array_ref![src, 0, LockTier::LEN]);
+        }
         Ok(PlatForm {
             is_initialized,
             owner: Pubkey::new_from_array(*owner),
             total_staked_nft: u64::from_le_bytes(*total_staked_nft),
+            reward_rate: u64::from_le_bytes(*reward_rate),
+            acc_reward_per_nft: u128::from_le_bytes(*acc_reward_per_nft),
+            last_update_time: u64::from_le_bytes(*last_update_time),
+            collection_rule: CollectionRule::from_u8(collection_rule[0])
+                .ok_or(ProgramError::InvalidAccountData)?,
+            collection: Pubkey::new_from_array(*collection),
+            creator_count: creator_count[0],
+            creators,
+            vault_bump: vault_bump[0],
+            reward_mode: RewardMode::from_u8(reward_mode[0])
+                .ok_or(ProgramError::InvalidAccountData)?,
+            custody_mode: CustodyMode::from_u8(custody_mode[0])
+                .ok_or(ProgramError::InvalidAccountData)?,
+            lock: LockConfig::unpack_from_array(lock),
+            lock_tier_count: lock_tier_count[0],
+            lock_tiers,
+            total_weight_bps: u64::from_le_bytes(*total_weight_bps),
+            rarity_root: *rarity_root,
+            reward_vault: Pubkey::new_from_array(*reward_vault),
+            total_deposited: u64::from_le_bytes(*total_deposited),
+            total_withdrawn: u64::from_le_bytes(*total_withdrawn),
+            total_paid: u64::from_le_bytes(*total_paid),
+            total_accrued: u64::from_le_bytes(*total_accrued),
+            unsettled_reward: u128::from_le_bytes(*unsettled_reward),
+            paused: match paused {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            pending_owner: Pubkey::new_from_array(*pending_owner),
+            close_empty_user_states: match close_empty_user_states {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
         })
     }
     fn pack_into_slice(&self, dst: &mut [u8]) {
         let dst = array_mut_ref![dst, 0, PlatForm::LEN];
-        let (is_initialized_dst, owner_dst, total_staked_nft_dst) = mut_array_refs![dst, 1, 32, 8];
+        let (
+            is_initialized_dst,
+            owner_dst,
+            total_staked_nft_dst,
+            reward_rate_dst,
+            acc_reward_per_nft_dst,
+            last_update_time_dst,
+            collection_rule_dst,
+            collection_dst,
+            creator_count_dst,
+            creators_dst,
+            vault_bump_dst,
+            reward_mode_dst,
+            custody_mode_dst,
+            lock_dst,
+            lock_tier_count_dst,
+            lock_tiers_dst,
+            total_weight_bps_dst,
+            rarity_root_dst,
+            reward_vault_dst,
+            total_deposited_dst,
+            total_withdrawn_dst,
+            total_paid_dst,
+            total_accrued_dst,
+            unsettled_reward_dst,
+            paused_dst,
+            pending_owner_dst,
+            close_empty_user_states_dst,
+        ) = mut_array_refs![
+            dst,
+            1,
+            32,
+            8,
+            8,
+            16,
+            8,
+            1,
+            32,
+            1,
+            32 * MAX_ALLOWED_CREATORS,
+            1,
+            1,
+            1,
+            LockConfig::LEN,
+            1,
+            LockTier::LEN * MAX_LOCK_TIERS,
+            8,
+            32,
+            32,
+            8,
+            8,
+            8,
+            8,
+            16,
+            1,
+            32,
+            1
+        ];
         let PlatForm {
             is_initialized,
             owner,
             total_staked_nft,
+            reward_rate,
+            acc_reward_per_nft,
+            last_update_time,
+            collection_rule,
+            collection,
+            creator_count,
+            creators,
+            vault_bump,
+            reward_mode,
+            custody_mode,
+            lock,
+            lock_tier_count,
+            lock_tiers,
+            total_weight_bps,
+            rarity_root,
+            reward_vault,
+            total_deposited,
+            total_withdrawn,
+            total_paid,
+            total_accrued,
+            unsettled_reward,
+            paused,
+            pending_owner,
+            close_empty_user_states,
         } = self;
         is_initialized_dst[0] = *is_initialized as u8;
         owner_dst.copy_from_slice(owner.as_ref());
         *total_staked_nft_dst = total_staked_nft.to_le_bytes();
+        *reward_rate_dst = reward_rate.to_le_bytes();
+        *acc_reward_per_nft_dst = acc_reward_per_nft.to_le_bytes();
+        *last_update_time_dst = last_update_time.to_le_bytes();
+        collection_rule_dst[0] = *collection_rule as u8;
+        collection_dst.copy_from_slice(collection.as_ref());
+        creator_count_dst[0] = *creator_count;
+        for (dst, creator) in creators_dst.chunks_exact_mut(32).zip(creators.iter()) {
+            dst.copy_from_slice(creator.as_ref());
+        }
+        vault_bump_dst[0] = *vault_bump;
+        reward_mode_dst[0] = *reward_mode as u8;
+        custody_mode_dst[0] = *custody_mode as u8;
+        lock.pack_into_array(lock_dst);
+        lock_tier_count_dst[0] = *lock_tier_count;
+        for (dst, tier) in lock_tiers_dst
+            .chunks_exact_mut(LockTier::LEN)
+            .zip(lock_tiers.iter())
+        {
+            tier.pack_into_array(array_mut_ref![dst, 0, LockTier::LEN]);
+        }
+        *total_weight_bps_dst = total_weight_bps.to_le_bytes();
+        *rarity_root_dst = *rarity_root;
+        reward_vault_dst.copy_from_slice(reward_vault.as_ref());
+        *total_deposited_dst = total_deposited.to_le_bytes();
+        *total_withdrawn_dst = total_withdrawn.to_le_bytes();
+        *total_paid_dst = total_paid.to_le_bytes();
+        *total_accrued_dst = total_accrued.to_le_bytes();
+        *unsettled_reward_dst = unsettled_reward.to_le_bytes();
+        paused_dst[0] = *paused as u8;
+        pending_owner_dst.copy_from_slice(pending_owner.as_ref());
+        close_empty_user_states_dst[0] = *close_empty_user_states as u8;
     }
 }
 
+/// Per-user aggregate across every NFT the user has staked on a platform.
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub struct UserState {
     pub is_initialized: bool,
     pub user: Pubkey,
     pub total_staked_nft: u64,
-    pub last_staked_time: u64,
+    pub bump: u8,
 }
 impl Sealed for UserState {}
 impl IsInitialized for UserState {
@@ -60,11 +604,10 @@ impl IsInitialized for UserState {
     }
 }
 impl Pack for UserState {
-    const LEN: usize = 49;
+    const LEN: usize = 42;
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
         let src = array_ref![src, 0, UserState::LEN];
-        let (is_initialized, user, total_staked_nft, last_staked_time) =
-            array_refs![src, 1, 32, 8, 8];
+        let (is_initialized, user, total_staked_nft, bump) = array_refs![src, 1, 32, 8, 1];
         let is_initialized = match is_initialized {
             [0] => false,
             [1] => true,
@@ -74,22 +617,230 @@ impl Pack for UserState {
             is_initialized,
             user: Pubkey::new_from_array(*user),
             total_staked_nft: u64::from_le_bytes(*total_staked_nft),
-            last_staked_time: u64::from_le_bytes(*last_staked_time),
+            bump: bump[0],
         })
     }
     fn pack_into_slice(&self, dst: &mut [u8]) {
         let dst = array_mut_ref![dst, 0, UserState::LEN];
-        let (is_initialized_dst, user_dst, total_staked_nft_dst, last_staked_time_dst) =
-            mut_array_refs![dst, 1, 32, 8, 8];
+        let (is_initialized_dst, user_dst, total_staked_nft_dst, bump_dst) =
+            mut_array_refs![dst, 1, 32, 8, 1];
         let UserState {
             is_initialized,
             user,
             total_staked_nft,
-            last_staked_time,
+            bump,
         } = self;
         is_initialized_dst[0] = *is_initialized as u8;
         user_dst.copy_from_slice(user.as_ref());
         *total_staked_nft_dst = total_staked_nft.to_le_bytes();
-        *last_staked_time_dst = last_staked_time.to_le_bytes();
+        bump_dst[0] = *bump;
+    }
+}
+
+/// One staked NFT, keyed by platform + mint. Exists only while the NFT is staked.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct StakeRecord {
+    pub is_initialized: bool,
+    pub platform: Pubkey,
+    pub staker: Pubkey,
+    pub mint: Pubkey,
+    pub stake_time: u64,
+    /// Time of the last paid harvest. Rewards up to it have been paid out.
+    pub last_claim_time: u64,
+    /// Rewards settled into the record but not yet paid out.
+    pub accrued_reward: u64,
+    /// `PlatForm::acc_reward_per_nft` at the last settlement.
+    pub reward_per_nft_paid: u128,
+    pub bump: u8,
+    /// Bump of the escrow token account, only set under `CustodyMode::Escrow`.
+    pub escrow_bump: u8,
+    /// Bump of the mint's rarity account, found once at stake time so harvests only have to
+    /// re-derive it. Unused when `rarity_proven`.
+    pub rarity_bump: u8,
+    /// Index of the lock tier picked at stake time, see `PlatForm::lock_tier`.
+    pub lock_tier: u8,
+    /// Time before which the NFT can't be unstaked, from the platform hard lock or the tier.
+    pub unlock_time: u64,
+    /// Reward multiplier of this stake in basis points, 10_000 for 1x: the tier multiplier
+    /// scaled by the rarity multiplier.
+    pub weight_bps: u64,
+    /// Multiplier of the lock tier at stake time, kept so later tier changes don't apply.
+    pub tier_multiplier_bps: u16,
+    /// Rarity multiplier of the mint as of the last stake or harvest.
+    pub rarity_bps: u16,
+    /// Whether `rarity_bps` was proven against the platform's rarity root at stake time.
+    /// Proven multipliers stay cached for the whole stake instead of following the mint's
+    /// rarity account.
+    pub rarity_proven: bool,
+}
+impl Sealed for StakeRecord {}
+impl IsInitialized for StakeRecord {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl Pack for StakeRecord {
+    const LEN: usize = 162;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, StakeRecord::LEN];
+        let (
+            is_initialized,
+            platform,
+            staker,
+            mint,
+            stake_time,
+            last_claim_time,
+            accrued_reward,
+            reward_per_nft_paid,
+            bump,
+            escrow_bump,
+            rarity_bump,
+            lock_tier,
+            unlock_time,
+            weight_bps,
+            tier_multiplier_bps,
+            rarity_bps,
+            rarity_proven,
+        ) = array_refs![src, 1, 32, 32, 32, 8, 8, 8, 16, 1, 1, 1, 1, 8, 8, 2, 2, 1];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        Ok(StakeRecord {
+            is_initialized,
+            platform: Pubkey::new_from_array(*platform),
+            staker: Pubkey::new_from_array(*staker),
+            mint: Pubkey::new_from_array(*mint),
+            stake_time: u64::from_le_bytes(*stake_time),
+            last_claim_time: u64::from_le_bytes(*last_claim_time),
+            accrued_reward: u64::from_le_bytes(*accrued_reward),
+            reward_per_nft_paid: u128::from_le_bytes(*reward_per_nft_paid),
+            bump: bump[0],
+            escrow_bump: escrow_bump[0],
+            rarity_bump: rarity_bump[0],
+            lock_tier: lock_tier[0],
+            unlock_time: u64::from_le_bytes(*unlock_time),
+            weight_bps: u64::from_le_bytes(*weight_bps),
+            tier_multiplier_bps: u16::from_le_bytes(*tier_multiplier_bps),
+            rarity_bps: u16::from_le_bytes(*rarity_bps),
+            rarity_proven: match rarity_proven {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+        })
+    }
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, StakeRecord::LEN];
+        let (
+            is_initialized_dst,
+            platform_dst,
+            staker_dst,
+            mint_dst,
+            stake_time_dst,
+            last_claim_time_dst,
+            accrued_reward_dst,
+            reward_per_nft_paid_dst,
+            bump_dst,
+            escrow_bump_dst,
+            rarity_bump_dst,
+            lock_tier_dst,
+            unlock_time_dst,
+            weight_bps_dst,
+            tier_multiplier_bps_dst,
+            rarity_bps_dst,
+            rarity_proven_dst,
+        ) = mut_array_refs![dst, 1, 32, 32, 32, 8, 8, 8, 16, 1, 1, 1, 1, 8, 8, 2, 2, 1];
+        let StakeRecord {
+            is_initialized,
+            platform,
+            staker,
+            mint,
+            stake_time,
+            last_claim_time,
+            accrued_reward,
+            reward_per_nft_paid,
+            bump,
+            escrow_bump,
+            rarity_bump,
+            lock_tier,
+            unlock_time,
+            weight_bps,
+            tier_multiplier_bps,
+            rarity_bps,
+            rarity_proven,
+        } = self;
+        is_initialized_dst[0] = *is_initialized as u8;
+        platform_dst.copy_from_slice(platform.as_ref());
+        staker_dst.copy_from_slice(staker.as_ref());
+        mint_dst.copy_from_slice(mint.as_ref());
+        *stake_time_dst = stake_time.to_le_bytes();
+        *last_claim_time_dst = last_claim_time.to_le_bytes();
+        *accrued_reward_dst = accrued_reward.to_le_bytes();
+        *reward_per_nft_paid_dst = reward_per_nft_paid.to_le_bytes();
+        bump_dst[0] = *bump;
+        escrow_bump_dst[0] = *escrow_bump;
+        rarity_bump_dst[0] = *rarity_bump;
+        lock_tier_dst[0] = *lock_tier;
+        *unlock_time_dst = unlock_time.to_le_bytes();
+        *weight_bps_dst = weight_bps.to_le_bytes();
+        *tier_multiplier_bps_dst = tier_multiplier_bps.to_le_bytes();
+        *rarity_bps_dst = rarity_bps.to_le_bytes();
+        rarity_proven_dst[0] = *rarity_proven as u8;
+    }
+}
+
+/// Reward multiplier of one mint, keyed by platform + mint. Mints without one earn 1x.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Rarity {
+    pub is_initialized: bool,
+    pub platform: Pubkey,
+    pub mint: Pubkey,
+    /// Reward multiplier in basis points, 10_000 for 1x.
+    pub multiplier_bps: u16,
+    pub bump: u8,
+}
+impl Sealed for Rarity {}
+impl IsInitialized for Rarity {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl Pack for Rarity {
+    const LEN: usize = 68;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, Rarity::LEN];
+        let (is_initialized, platform, mint, multiplier_bps, bump) =
+            array_refs![src, 1, 32, 32, 2, 1];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        Ok(Rarity {
+            is_initialized,
+            platform: Pubkey::new_from_array(*platform),
+            mint: Pubkey::new_from_array(*mint),
+            multiplier_bps: u16::from_le_bytes(*multiplier_bps),
+            bump: bump[0],
+        })
+    }
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, Rarity::LEN];
+        let (is_initialized_dst, platform_dst, mint_dst, multiplier_bps_dst, bump_dst) =
+            mut_array_refs![dst, 1, 32, 32, 2, 1];
+        let Rarity {
+            is_initialized,
+            platform,
+            mint,
+            multiplier_bps,
+            bump,
+        } = self;
+        is_initialized_dst[0] = *is_initialized as u8;
+        platform_dst.copy_from_slice(platform.as_ref());
+        mint_dst.copy_from_slice(mint.as_ref());
+        *multiplier_bps_dst = multiplier_bps.to_le_bytes();
+        bump_dst[0] = *bump;
     }
 }
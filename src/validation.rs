@@ -0,0 +1,58 @@
+//! Account constraint checks shared by the instruction handlers
+
+use crate::error::FarmError;
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, program_error::ProgramError,
+    program_pack::Pack, pubkey::Pubkey, system_program,
+};
+use spl_token::state::Account as TokenAccount;
+
+pub fn assert_signer(account: &AccountInfo) -> ProgramResult {
+    if !account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    Ok(())
+}
+
+pub fn assert_writable(account: &AccountInfo) -> ProgramResult {
+    if !account.is_writable {
+        return Err(FarmError::AccountNotWritable.into());
+    }
+    Ok(())
+}
+
+/// Checks the account is state owned by this program.
+pub fn assert_program_owned(account: &AccountInfo, program_id: &Pubkey) -> ProgramResult {
+    if account.owner != program_id {
+        return Err(FarmError::InvalidOwner.into());
+    }
+    Ok(())
+}
+
+pub fn assert_token_program(account: &AccountInfo) -> ProgramResult {
+    if *account.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+pub fn assert_system_program(account: &AccountInfo) -> ProgramResult {
+    if *account.key != system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+/// Checks the account is owned by the token program and loads it.
+pub fn load_token_account(account: &AccountInfo) -> Result<TokenAccount, ProgramError> {
+    if *account.owner != spl_token::id() {
+        return Err(FarmError::InvalidTokenAccountOwner.into());
+    }
+    TokenAccount::unpack(&account.try_borrow_data()?)
+}
+
+/// Checks the account is a writable token account and loads it.
+pub fn load_writable_token_account(account: &AccountInfo) -> Result<TokenAccount, ProgramError> {
+    assert_writable(account)?;
+    load_token_account(account)
+}
@@ -0,0 +1,265 @@
+mod common;
+
+use common::*;
+use farm::{
+    error::FarmError,
+    instruction::{
+        max_stake_many, max_unstake_many, NftInstruction, MAX_STAKE_MANY, MAX_UNSTAKE_MANY,
+    },
+    state::{CustodyMode, LockConfig, LockTier},
+};
+use solana_program::{program_error::ProgramError, program_option::COption, program_pack::Pack};
+use spl_token::state::{AccountState, Mint};
+
+const DAY: i64 = 86_400;
+
+/// A wallet holding `count` NFTs of the farm's collection.
+fn wallet(farm: &Farm, count: usize) -> Vec<Staker> {
+    let first = farm.staker();
+    let mut nfts = (1..count)
+        .map(|_| farm.another_nft(&first))
+        .collect::<Vec<_>>();
+    nfts.insert(0, first);
+    nfts
+}
+
+#[test]
+fn test_stake_and_unstake_many() {
+    for custody_mode in [
+        CustodyMode::Authority,
+        CustodyMode::Escrow,
+        CustodyMode::Frozen,
+    ] {
+        let farm = Farm::with(PlatformOptions {
+            custody_mode,
+            lock: LockConfig::default(),
+            ..PlatformOptions::default()
+        });
+        let nfts = wallet(&farm, max_stake_many(custody_mode));
+        let batch = nfts.iter().collect::<Vec<_>>();
+        if custody_mode == CustodyMode::Frozen {
+            for nft in &nfts {
+                let mut mint = Mint::unpack(&nft.nft_mint.data.borrow()).unwrap();
+                mint.freeze_authority = COption::Some(*farm.pda.key);
+                Mint::pack(mint, &mut nft.nft_mint.data.borrow_mut()).unwrap();
+            }
+        }
+
+        farm.stake_many(&batch, 0).unwrap();
+        for nft in &nfts {
+            assert_eq!(stake_record(&nft.stake_record).mint, *nft.nft_mint.key);
+        }
+        let staked = nfts.len() as u64;
+        assert_eq!(user_state(&nfts[0].user_state).total_staked_nft, staked);
+        let state = platform(&farm.platform_state);
+        assert_eq!(state.total_staked_nft, staked);
+        assert_eq!(state.total_weight_bps, staked * 10_000);
+
+        advance_time(DAY);
+        farm.unstake_many(&batch).unwrap();
+        for nft in &nfts {
+            let account = token(&nft.nft_account);
+            assert_eq!(account.owner, *nft.user.key);
+            assert_eq!(account.amount, 1);
+            assert_eq!(account.state, AccountState::Initialized);
+            assert_eq!(nft.stake_record.lamports(), 0);
+        }
+        assert_eq!(
+            token(&nfts[0].reward_account).amount,
+            staked * DAY as u64 * RATE
+        );
+        assert_eq!(user_state(&nfts[0].user_state).total_staked_nft, 0);
+        let state = platform(&farm.platform_state);
+        assert_eq!(state.total_staked_nft, 0);
+        assert_eq!(state.total_weight_bps, 0);
+        assert_eq!(state.total_owed(), Ok(0));
+    }
+}
+
+#[test]
+fn test_custody_batches_are_limited_to_what_fits_a_transaction() {
+    for custody_mode in [CustodyMode::Escrow, CustodyMode::Frozen] {
+        let farm = Farm::with(PlatformOptions {
+            custody_mode,
+            lock: LockConfig::default(),
+            ..PlatformOptions::default()
+        });
+        let nfts = wallet(&farm, MAX_UNSTAKE_MANY);
+        let batch = nfts.iter().collect::<Vec<_>>();
+        if custody_mode == CustodyMode::Frozen {
+            for nft in &nfts {
+                let mut mint = Mint::unpack(&nft.nft_mint.data.borrow()).unwrap();
+                mint.freeze_authority = COption::Some(*farm.pda.key);
+                Mint::pack(mint, &mut nft.nft_mint.data.borrow_mut()).unwrap();
+            }
+        }
+
+        let max_stake = max_stake_many(custody_mode);
+        assert_eq!(
+            farm.stake_many(&batch[..=max_stake], 0),
+            Err(ProgramError::InvalidArgument)
+        );
+        for chunk in batch.chunks(max_stake) {
+            farm.stake_many(chunk, 0).unwrap();
+        }
+
+        let max_unstake = max_unstake_many(custody_mode);
+        assert_eq!(
+            farm.unstake_many(&batch[..=max_unstake]),
+            Err(ProgramError::InvalidArgument)
+        );
+        farm.unstake_many(&batch[..max_unstake]).unwrap();
+        assert_eq!(
+            user_state(&nfts[0].user_state).total_staked_nft,
+            (MAX_UNSTAKE_MANY - max_unstake) as u64
+        );
+    }
+}
+
+#[test]
+fn test_batches_mix_with_single_instructions() {
+    let farm = Farm::with(PlatformOptions {
+        custody_mode: CustodyMode::Authority,
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let nfts = wallet(&farm, 3);
+
+    farm.stake_many(&[&nfts[0], &nfts[1]], 0).unwrap();
+    farm.stake(&nfts[2]).unwrap();
+    advance_time(DAY);
+
+    farm.unstake(&nfts[0]).unwrap();
+    farm.unstake_many(&[&nfts[1], &nfts[2]]).unwrap();
+    assert_eq!(token(&nfts[0].reward_account).amount, 3 * DAY as u64 * RATE);
+    assert_eq!(user_state(&nfts[0].user_state).total_staked_nft, 0);
+}
+
+#[test]
+fn test_batch_lock_tier_applies_to_every_nft() {
+    let farm = Farm::with(PlatformOptions {
+        custody_mode: CustodyMode::Authority,
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    farm.set_lock_tiers(&[LockTier {
+        duration: 7 * DAY as u64,
+        multiplier_bps: 20_000,
+    }])
+    .unwrap();
+    let nfts = wallet(&farm, 2);
+    let batch = nfts.iter().collect::<Vec<_>>();
+
+    farm.stake_many(&batch, 1).unwrap();
+    assert_eq!(platform(&farm.platform_state).total_weight_bps, 40_000);
+
+    // a single locked NFT holds back the whole batch
+    advance_time(7 * DAY - 1);
+    assert_eq!(
+        farm.unstake_many(&batch),
+        Err(FarmError::StakeLocked.into())
+    );
+    advance_time(1);
+    farm.unstake_many(&batch).unwrap();
+    assert_eq!(
+        token(&nfts[0].reward_account).amount,
+        2 * 2 * 7 * DAY as u64 * RATE
+    );
+}
+
+#[test]
+fn test_one_bad_nft_fails_the_batch() {
+    let farm = Farm::with(PlatformOptions {
+        custody_mode: CustodyMode::Authority,
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let nfts = wallet(&farm, 3);
+    // not part of the farm's collection
+    nfts[2].metadata.data.borrow_mut().copy_from_slice(
+        &metadata_account(nfts[2].nft_mint.key, &[], None)
+            .data
+            .borrow(),
+    );
+    let batch = nfts.iter().collect::<Vec<_>>();
+
+    assert_eq!(
+        farm.stake_many(&batch, 0),
+        Err(FarmError::CollectionNotAllowed.into())
+    );
+}
+
+#[test]
+fn test_duplicate_nft_fails_the_batch() {
+    let farm = Farm::with(PlatformOptions {
+        custody_mode: CustodyMode::Authority,
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let nfts = wallet(&farm, 2);
+
+    assert_eq!(
+        farm.stake_many(&[&nfts[0], &nfts[1], &nfts[0]], 0),
+        Err(FarmError::AlreadyInUse.into())
+    );
+}
+
+#[test]
+fn test_batch_counts_are_limited() {
+    let farm = Farm::with(PlatformOptions {
+        custody_mode: CustodyMode::Authority,
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let nfts = wallet(&farm, 2);
+    let accounts = farm.stake_many_accounts(&[&nfts[0], &nfts[1]]);
+
+    for data in [
+        vec![20, 0, 0],
+        vec![20, MAX_STAKE_MANY as u8 + 1, 0],
+        vec![20, 2],
+        vec![21, 0],
+        vec![21, MAX_UNSTAKE_MANY as u8 + 1],
+    ] {
+        assert_eq!(
+            process(&farm.program_id, &accounts, &data),
+            Err(ProgramError::InvalidAccountData),
+            "{:?}",
+            data
+        );
+    }
+
+    // the count has to be backed by accounts
+    assert_eq!(
+        process(
+            &farm.program_id,
+            &accounts,
+            &NftInstruction::StakeMany {
+                count: 3,
+                lock_tier: 0
+            }
+            .pack()
+        ),
+        Err(ProgramError::NotEnoughAccountKeys)
+    );
+}
+
+#[test]
+fn test_unstake_many_only_takes_own_nfts() {
+    let farm = Farm::with(PlatformOptions {
+        custody_mode: CustodyMode::Authority,
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let mine = farm.staker();
+    let theirs = farm.staker();
+    farm.stake(&mine).unwrap();
+    farm.stake(&theirs).unwrap();
+
+    // their stake record is checked against the signer like any other
+    assert_eq!(
+        farm.unstake_many(&[&mine, &theirs]),
+        Err(FarmError::InvalidAuthority.into())
+    );
+    assert_eq!(token(&theirs.nft_account).owner, *farm.pda.key);
+}
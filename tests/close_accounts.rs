@@ -0,0 +1,171 @@
+mod common;
+
+use common::*;
+use farm::{
+    error::FarmError,
+    state::{LockConfig, UserState},
+};
+use solana_program::{program_pack::Pack, system_program};
+
+#[test]
+fn test_close_user_state_returns_rent() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+
+    assert_eq!(
+        farm.close_user_state(&staker),
+        Err(FarmError::StillStaked.into())
+    );
+
+    farm.unstake(&staker).unwrap();
+    let rent = staker.user_state.lamports();
+    let before = staker.user.lamports();
+    farm.close_user_state(&staker).unwrap();
+    assert_eq!(staker.user_state.lamports(), 0);
+    assert_eq!(staker.user.lamports(), before + rent);
+    assert!(staker.user_state.data.borrow().iter().all(|&b| b == 0));
+
+    // closed accounts are handed back to the system program by the runtime
+    staker.user_state.assign(&system_program::id());
+    staker.stake_record.assign(&system_program::id());
+    farm.stake(&staker).unwrap();
+    assert_eq!(user_state(&staker.user_state).total_staked_nft, 1);
+}
+
+#[test]
+fn test_only_the_user_can_close_their_state() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    let other = farm.staker();
+    farm.init_user(&staker).unwrap();
+
+    let result = process(
+        &farm.program_id,
+        &[
+            other.user.clone(),
+            staker.user_state.clone(),
+            farm.platform_state.clone(),
+        ],
+        &farm::instruction::NftInstruction::CloseUserState.pack(),
+    );
+    assert!(result.is_err());
+    assert!(staker.user_state.lamports() > 0);
+}
+
+#[test]
+fn test_auto_close_after_last_unstake() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    farm.set_auto_close_user_state(true).unwrap();
+    assert!(platform(&farm.platform_state).close_empty_user_states);
+
+    let staker = farm.staker();
+    let second = farm.another_nft(&staker);
+    farm.stake(&staker).unwrap();
+    farm.stake(&second).unwrap();
+
+    farm.unstake(&staker).unwrap();
+    assert_eq!(user_state(&staker.user_state).total_staked_nft, 1);
+
+    let before = staker.user.lamports();
+    let rent = staker.user_state.lamports();
+    let record_rent = second.stake_record.lamports();
+    farm.unstake(&second).unwrap();
+    assert_eq!(staker.user_state.lamports(), 0);
+    assert_eq!(staker.user.lamports(), before + rent + record_rent);
+}
+
+#[test]
+fn test_user_state_kept_without_auto_close() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    farm.unstake(&staker).unwrap();
+
+    assert!(staker.user_state.lamports() > 0);
+    assert_eq!(
+        UserState::unpack(&staker.user_state.data.borrow())
+            .unwrap()
+            .total_staked_nft,
+        0
+    );
+}
+
+#[test]
+fn test_auto_close_on_emergency_unstake() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    farm.set_auto_close_user_state(true).unwrap();
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    farm.set_paused(true).unwrap();
+
+    farm.emergency_unstake(&staker).unwrap();
+    assert_eq!(staker.user_state.lamports(), 0);
+}
+
+#[test]
+fn test_close_platform_needs_it_empty() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+
+    assert_eq!(farm.close_platform(), Err(FarmError::StillStaked.into()));
+
+    farm.unstake(&staker).unwrap();
+    assert_eq!(
+        farm.close_platform(),
+        Err(FarmError::RewardVaultNotEmpty.into())
+    );
+
+    farm.withdraw(token(&farm.vault_reward).amount).unwrap();
+    let rent = farm.platform_state.lamports() + farm.vault_reward.lamports();
+    let before = farm.owner.lamports();
+    farm.close_platform().unwrap();
+    assert_eq!(farm.owner.lamports(), before + rent);
+    assert_eq!(farm.platform_state.lamports(), 0);
+    assert_eq!(farm.vault_reward.lamports(), 0);
+
+    // users can still get their rent back afterwards
+    farm.close_user_state(&staker).unwrap();
+    assert_eq!(staker.user_state.lamports(), 0);
+}
+
+#[test]
+fn test_only_owner_can_close_platform() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    farm.withdraw(REWARD_SUPPLY).unwrap();
+    let stranger = farm.staker();
+
+    let mut accounts = farm.close_platform_accounts();
+    accounts[0] = stranger.user.clone();
+    assert_eq!(
+        process(
+            &farm.program_id,
+            &accounts,
+            &farm::instruction::NftInstruction::ClosePlatform.pack(),
+        ),
+        Err(FarmError::InvalidAuthority.into())
+    );
+    assert!(farm.platform_state.lamports() > 0);
+}
@@ -0,0 +1,132 @@
+mod common;
+
+use common::*;
+use farm::{error::FarmError, instruction::NftInstruction, state::CollectionRule};
+use solana_program::{pubkey::Pubkey, system_program};
+
+/// A farm admitting NFTs with any of `creators` as a verified creator.
+fn creator_farm(creators: &[Pubkey]) -> Farm {
+    let farm = Farm::new();
+    let options = PlatformOptions::default();
+    let data = NftInstruction::InitializePlatform {
+        amount: options.amount,
+        reward_rate: options.reward_rate,
+        reward_mode: options.reward_mode,
+        custody_mode: options.custody_mode,
+        lock: options.lock,
+        collection_rule: CollectionRule::VerifiedCreator,
+        collection: farm.collection,
+        creators: creators.to_vec(),
+    }
+    .pack();
+    process(&farm.program_id, &farm.init_accounts(), &data).unwrap();
+    farm
+}
+
+/// `staker` with its NFT mint replaced by one of the given supply and decimals.
+fn with_mint(staker: Staker, supply: u64, decimals: u8) -> Staker {
+    Staker {
+        nft_mint: account(
+            *staker.nft_mint.key,
+            spl_token::id(),
+            1,
+            mint_data(supply, decimals, None),
+            false,
+            false,
+        ),
+        ..staker
+    }
+}
+
+/// `staker` with metadata listing `creators` and `collection`.
+fn with_metadata(
+    staker: Staker,
+    creators: &[(Pubkey, bool)],
+    collection: Option<(Pubkey, bool)>,
+) -> Staker {
+    Staker {
+        metadata: metadata_account(staker.nft_mint.key, creators, collection),
+        ..staker
+    }
+}
+
+fn assert_not_staked(staker: &Staker) {
+    assert_eq!(*staker.stake_record.owner, system_program::id());
+    assert_eq!(token(&staker.nft_account).amount, 1);
+}
+
+#[test]
+fn test_stake_rejects_mints_that_are_not_nfts() {
+    let farm = Farm::with(PlatformOptions::default());
+    for (supply, decimals) in [(1_000_000_000, 6), (2, 0), (0, 0), (1, 2)] {
+        let staker = with_mint(farm.staker(), supply, decimals);
+        assert_eq!(farm.stake(&staker), Err(FarmError::NotAnNft.into()));
+        assert_not_staked(&staker);
+    }
+
+    let staker = with_mint(farm.staker(), 1, 0);
+    farm.stake(&staker).unwrap();
+}
+
+#[test]
+fn test_stake_rejects_nfts_outside_the_verified_collection() {
+    let farm = Farm::with(PlatformOptions::default());
+    let unverified = with_metadata(farm.staker(), &[], Some((farm.collection, false)));
+    let other_collection = with_metadata(farm.staker(), &[], Some((Pubkey::new_unique(), true)));
+    let no_collection = with_metadata(farm.staker(), &[(farm.collection, true)], None);
+    for staker in [&unverified, &other_collection, &no_collection] {
+        assert_eq!(
+            farm.stake(staker),
+            Err(FarmError::CollectionNotAllowed.into())
+        );
+        assert_not_staked(staker);
+    }
+
+    let verified = with_metadata(farm.staker(), &[], Some((farm.collection, true)));
+    farm.stake(&verified).unwrap();
+}
+
+#[test]
+fn test_verified_allowed_creator_is_accepted() {
+    let creators = [Pubkey::new_unique(), Pubkey::new_unique()];
+    let farm = creator_farm(&creators);
+    for creator in creators {
+        let staker = with_metadata(
+            farm.staker(),
+            &[(Pubkey::new_unique(), true), (creator, true)],
+            None,
+        );
+        farm.stake(&staker).unwrap();
+        assert_eq!(
+            stake_record(&staker.stake_record).mint,
+            *staker.nft_mint.key
+        );
+    }
+}
+
+#[test]
+fn test_unverified_creator_is_rejected() {
+    let creator = Pubkey::new_unique();
+    let farm = creator_farm(&[creator]);
+    let staker = with_metadata(farm.staker(), &[(creator, false)], None);
+    assert_eq!(
+        farm.stake(&staker),
+        Err(FarmError::CollectionNotAllowed.into())
+    );
+    assert_not_staked(&staker);
+}
+
+#[test]
+fn test_creator_not_on_the_list_is_rejected() {
+    let farm = creator_farm(&[Pubkey::new_unique()]);
+    let stranger = with_metadata(farm.staker(), &[(Pubkey::new_unique(), true)], None);
+    // a verified collection does not count under `VerifiedCreator`
+    let collection_only = farm.staker();
+    for staker in [&stranger, &collection_only] {
+        assert_eq!(
+            farm.stake(staker),
+            Err(FarmError::CollectionNotAllowed.into())
+        );
+        assert_not_staked(staker);
+    }
+}
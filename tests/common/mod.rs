@@ -0,0 +1,1050 @@
+//! In-process runtime for driving `Processor::process` from tests.
+//!
+//! Accounts are leaked so `AccountInfo`s can be cloned freely and share state the
+//! way they do on-chain. Cross-program invocations of the system and token programs
+//! are executed directly through the syscall stubs, as are the delegated freeze and
+//! thaw instructions of the token metadata program.
+
+#![allow(dead_code)]
+
+use farm::{
+    instruction::{PendingRewards, RarityEntry, RarityProof},
+    metadata::{find_edition_address, find_metadata_address, mpl_token_metadata},
+    processor::Processor,
+    state::{
+        find_escrow_address, find_rarity_address, find_stake_record_address,
+        find_user_state_address, find_vault_address, CustodyMode, LockConfig, LockTier, PlatForm,
+        Rarity, RewardMode, StakeRecord, UserState,
+    },
+};
+use solana_program::{
+    account_info::AccountInfo,
+    clock::Clock,
+    entrypoint::{ProgramResult, SUCCESS},
+    instruction::Instruction,
+    message::Message,
+    program_error::ProgramError,
+    program_option::COption,
+    program_pack::Pack,
+    program_stubs::{set_syscall_stubs, SyscallStubs},
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction::SystemInstruction,
+    system_program,
+};
+use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+use std::{
+    cell::{Cell, RefCell},
+    sync::Once,
+};
+
+thread_local! {
+    static NOW: Cell<i64> = const { Cell::new(1_650_000_000) };
+    static PROGRAM_ID: Cell<Pubkey> = Cell::new(Pubkey::default());
+    static RETURN_DATA: RefCell<Option<(Pubkey, Vec<u8>)>> = const { RefCell::new(None) };
+}
+
+struct TestSyscallStubs;
+
+impl SyscallStubs for TestSyscallStubs {
+    fn sol_log(&self, _message: &str) {}
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        let clock = Clock {
+            unix_timestamp: NOW.with(|now| now.get()),
+            ..Clock::default()
+        };
+        unsafe { *(var_addr as *mut Clock) = clock };
+        SUCCESS
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        SUCCESS
+    }
+
+    fn sol_set_return_data(&self, data: &[u8]) {
+        let program_id = PROGRAM_ID.with(|id| id.get());
+        RETURN_DATA.with(|return_data| {
+            *return_data.borrow_mut() = Some((program_id, data.to_vec()));
+        });
+    }
+
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        RETURN_DATA.with(|return_data| return_data.borrow().clone())
+    }
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let program_id = PROGRAM_ID.with(|id| id.get());
+        let pda_signers: Vec<Pubkey> = signers_seeds
+            .iter()
+            .map(|seeds| Pubkey::create_program_address(seeds, &program_id).unwrap())
+            .collect();
+        let accounts: Vec<AccountInfo> = instruction
+            .accounts
+            .iter()
+            .map(|meta| {
+                let mut info = account_infos
+                    .iter()
+                    .find(|info| *info.key == meta.pubkey)
+                    .unwrap_or_else(|| panic!("account {} missing from invoke", meta.pubkey))
+                    .clone();
+                if meta.is_signer {
+                    assert!(
+                        info.is_signer || pda_signers.contains(info.key),
+                        "{} must sign",
+                        info.key
+                    );
+                    info.is_signer = true;
+                }
+                info
+            })
+            .collect();
+
+        if instruction.program_id == spl_token::id() {
+            spl_token::processor::Processor::process(
+                &instruction.program_id,
+                &accounts,
+                &instruction.data,
+            )
+        } else if instruction.program_id == system_program::id() {
+            process_system_instruction(&accounts, &instruction.data)
+        } else if instruction.program_id == mpl_token_metadata::id() {
+            process_delegated_freeze(&accounts, &instruction.data)
+        } else {
+            panic!("unexpected invoke of {}", instruction.program_id)
+        }
+    }
+}
+
+fn process_system_instruction(accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let instruction: SystemInstruction =
+        solana_program::program_utils::limited_deserialize(data, 1024).unwrap();
+    match instruction {
+        SystemInstruction::CreateAccount {
+            lamports,
+            space,
+            owner,
+        } => {
+            let (from, to) = (&accounts[0], &accounts[1]);
+            assert!(
+                to.lamports() == 0 && *to.owner == system_program::id(),
+                "{} already in use",
+                to.key
+            );
+            assert_eq!(to.data_len() as u64, space, "test account sized wrong");
+            **from.try_borrow_mut_lamports()? -= lamports;
+            **to.try_borrow_mut_lamports()? += lamports;
+            to.assign(&owner);
+            Ok(())
+        }
+        SystemInstruction::Transfer { lamports } => {
+            **accounts[0].try_borrow_mut_lamports()? -= lamports;
+            **accounts[1].try_borrow_mut_lamports()? += lamports;
+            Ok(())
+        }
+        SystemInstruction::Allocate { space } => {
+            let account = &accounts[0];
+            assert!(
+                *account.owner == system_program::id()
+                    && account.data.borrow().iter().all(|b| *b == 0),
+                "{} already in use",
+                account.key
+            );
+            assert_eq!(account.data_len() as u64, space, "test account sized wrong");
+            Ok(())
+        }
+        SystemInstruction::Assign { owner } => {
+            let account = &accounts[0];
+            assert_eq!(
+                *account.owner,
+                system_program::id(),
+                "{} already assigned",
+                account.key
+            );
+            account.assign(&owner);
+            Ok(())
+        }
+        other => panic!("unexpected system instruction {:?}", other),
+    }
+}
+
+/// `FreezeDelegatedAccount` / `ThawDelegatedAccount`: the approved delegate has the
+/// master edition use its freeze authority over the mint.
+fn process_delegated_freeze(accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let (delegate, token_account, edition, mint) =
+        (&accounts[0], &accounts[1], &accounts[2], &accounts[3]);
+    assert!(delegate.is_signer, "delegate must sign");
+    assert_eq!(*edition.key, find_edition_address(mint.key).0);
+    let account = TokenAccount::unpack(&token_account.data.borrow())?;
+    assert_eq!(
+        account.delegate,
+        COption::Some(*delegate.key),
+        "not the delegate"
+    );
+    let instruction = match data {
+        [26] => spl_token::instruction::TokenInstruction::FreezeAccount,
+        [27] => spl_token::instruction::TokenInstruction::ThawAccount,
+        other => panic!("unexpected token metadata instruction {:?}", other),
+    };
+    let edition = AccountInfo {
+        is_signer: true,
+        ..edition.clone()
+    };
+    spl_token::processor::Processor::process(
+        &spl_token::id(),
+        &[token_account.clone(), mint.clone(), edition],
+        &instruction.pack(),
+    )
+}
+
+pub fn set_time(unix_timestamp: i64) {
+    NOW.with(|now| now.set(unix_timestamp));
+}
+
+pub fn advance_time(seconds: i64) {
+    NOW.with(|now| now.set(now.get() + seconds));
+}
+
+pub fn now() -> u64 {
+    NOW.with(|now| now.get()) as u64
+}
+
+pub fn take_return_data() -> Option<(Pubkey, Vec<u8>)> {
+    RETURN_DATA.with(|return_data| return_data.borrow_mut().take())
+}
+
+pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    static STUBS: Once = Once::new();
+    STUBS.call_once(|| {
+        set_syscall_stubs(Box::new(TestSyscallStubs));
+    });
+    PROGRAM_ID.with(|id| id.set(*program_id));
+    Processor::process(program_id, accounts, data)
+}
+
+/// Runs `instruction` against the matching accounts of `pool`, taking the signer and
+/// writable flags from the instruction's account metas.
+pub fn process_instruction(
+    instruction: &Instruction,
+    pool: &[AccountInfo<'static>],
+) -> ProgramResult {
+    let accounts: Vec<AccountInfo> = instruction
+        .accounts
+        .iter()
+        .map(|meta| {
+            let info = pool
+                .iter()
+                .find(|info| *info.key == meta.pubkey)
+                .unwrap_or_else(|| panic!("account {} missing from pool", meta.pubkey));
+            AccountInfo {
+                is_signer: meta.is_signer,
+                is_writable: meta.is_writable,
+                ..info.clone()
+            }
+        })
+        .collect();
+    process(&instruction.program_id, &accounts, &instruction.data)
+}
+
+pub fn account(
+    key: Pubkey,
+    owner: Pubkey,
+    lamports: u64,
+    data: Vec<u8>,
+    is_signer: bool,
+    is_writable: bool,
+) -> AccountInfo<'static> {
+    AccountInfo::new(
+        Box::leak(Box::new(key)),
+        is_signer,
+        is_writable,
+        Box::leak(Box::new(lamports)),
+        Box::leak(data.into_boxed_slice()),
+        Box::leak(Box::new(owner)),
+        false,
+        0,
+    )
+}
+
+pub fn program_account(key: Pubkey) -> AccountInfo<'static> {
+    let info = account(key, Pubkey::default(), 1, vec![], false, false);
+    AccountInfo {
+        executable: true,
+        ..info
+    }
+}
+
+/// An unfunded account of `len` bytes for the program to create through the system program.
+pub fn empty_account(key: Pubkey, len: usize) -> AccountInfo<'static> {
+    account(key, system_program::id(), 0, vec![0; len], false, true)
+}
+
+pub fn with_signer(info: &AccountInfo<'static>, is_signer: bool) -> AccountInfo<'static> {
+    AccountInfo {
+        is_signer,
+        ..info.clone()
+    }
+}
+
+pub fn with_writable(info: &AccountInfo<'static>, is_writable: bool) -> AccountInfo<'static> {
+    AccountInfo {
+        is_writable,
+        ..info.clone()
+    }
+}
+
+pub fn with_owner(info: &AccountInfo<'static>, owner: Pubkey) -> AccountInfo<'static> {
+    AccountInfo {
+        owner: Box::leak(Box::new(owner)),
+        ..info.clone()
+    }
+}
+
+pub fn mint_data(supply: u64, decimals: u8, freeze_authority: Option<Pubkey>) -> Vec<u8> {
+    let mut data = vec![0; Mint::LEN];
+    Mint::pack(
+        Mint {
+            mint_authority: COption::None,
+            supply,
+            decimals,
+            is_initialized: true,
+            freeze_authority: freeze_authority.into(),
+        },
+        &mut data,
+    )
+    .unwrap();
+    data
+}
+
+pub fn token_account_data(mint: &Pubkey, owner: &Pubkey, amount: u64) -> Vec<u8> {
+    let mut data = vec![0; TokenAccount::LEN];
+    TokenAccount::pack(
+        TokenAccount {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            state: AccountState::Initialized,
+            ..TokenAccount::default()
+        },
+        &mut data,
+    )
+    .unwrap();
+    data
+}
+
+pub fn token_account(
+    key: Pubkey,
+    mint: &Pubkey,
+    owner: &Pubkey,
+    amount: u64,
+) -> AccountInfo<'static> {
+    account(
+        key,
+        spl_token::id(),
+        Rent::default().minimum_balance(TokenAccount::LEN),
+        token_account_data(mint, owner, amount),
+        false,
+        true,
+    )
+}
+
+fn push_string(buf: &mut Vec<u8>, value: &str) {
+    buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    buf.extend_from_slice(value.as_bytes());
+}
+
+/// Serialized `MetadataV1` with an optional collection and `(address, verified)` creators.
+pub fn metadata_data(
+    mint: &Pubkey,
+    creators: &[(Pubkey, bool)],
+    collection: Option<(Pubkey, bool)>,
+) -> Vec<u8> {
+    let mut buf = vec![4];
+    buf.extend_from_slice(Pubkey::new_unique().as_ref());
+    buf.extend_from_slice(mint.as_ref());
+    push_string(&mut buf, "Rappid");
+    push_string(&mut buf, "RPD");
+    push_string(&mut buf, "https://example.com/nft.json");
+    buf.extend_from_slice(&0u16.to_le_bytes());
+    buf.push(1);
+    buf.extend_from_slice(&(creators.len() as u32).to_le_bytes());
+    for (address, verified) in creators {
+        buf.extend_from_slice(address.as_ref());
+        buf.push(*verified as u8);
+        buf.push(100 / creators.len() as u8);
+    }
+    buf.extend_from_slice(&[0, 1, 0, 0]);
+    match collection {
+        Some((key, verified)) => {
+            buf.push(1);
+            buf.push(verified as u8);
+            buf.extend_from_slice(key.as_ref());
+        }
+        None => buf.push(0),
+    }
+    buf.resize(679, 0);
+    buf
+}
+
+pub fn metadata_account(
+    mint: &Pubkey,
+    creators: &[(Pubkey, bool)],
+    collection: Option<(Pubkey, bool)>,
+) -> AccountInfo<'static> {
+    account(
+        find_metadata_address(mint).0,
+        mpl_token_metadata::id(),
+        1,
+        metadata_data(mint, creators, collection),
+        false,
+        false,
+    )
+}
+
+pub fn token(info: &AccountInfo) -> TokenAccount {
+    TokenAccount::unpack(&info.data.borrow()).unwrap()
+}
+
+pub fn platform(info: &AccountInfo) -> PlatForm {
+    PlatForm::unpack(&info.data.borrow()).unwrap()
+}
+
+pub fn user_state(info: &AccountInfo) -> UserState {
+    UserState::unpack(&info.data.borrow()).unwrap()
+}
+
+pub fn stake_record(info: &AccountInfo) -> StakeRecord {
+    StakeRecord::unpack(&info.data.borrow()).unwrap()
+}
+
+/// `solana_sdk::packet::PACKET_DATA_SIZE`, the most a serialized transaction can take.
+pub const PACKET_DATA_SIZE: usize = 1232;
+
+/// Serialized size of a transaction of `instructions`, paid for by their first signer.
+pub fn transaction_size(instructions: &[Instruction]) -> usize {
+    let payer = instructions
+        .iter()
+        .flat_map(|instruction| &instruction.accounts)
+        .find(|meta| meta.is_signer)
+        .unwrap()
+        .pubkey;
+    let message = Message::new(instructions, Some(&payer));
+    // the signature count takes one byte up to 127 signatures
+    1 + 64 * message.header.num_required_signatures as usize + message.serialize().len()
+}
+
+/// An initialized-or-not platform with a funded admin and an empty reward vault.
+pub struct Farm {
+    pub program_id: Pubkey,
+    pub owner: AccountInfo<'static>,
+    pub platform_state: AccountInfo<'static>,
+    pub pda: AccountInfo<'static>,
+    pub reward_mint: Pubkey,
+    pub admin_reward: AccountInfo<'static>,
+    pub vault_reward: AccountInfo<'static>,
+    pub collection: Pubkey,
+    pub system_program: AccountInfo<'static>,
+    pub token_program: AccountInfo<'static>,
+    pub metadata_program: AccountInfo<'static>,
+}
+
+/// A wallet holding one NFT of the farm's collection.
+pub struct Staker {
+    pub user: AccountInfo<'static>,
+    pub user_state: AccountInfo<'static>,
+    pub stake_record: AccountInfo<'static>,
+    pub nft_mint: AccountInfo<'static>,
+    pub nft_account: AccountInfo<'static>,
+    pub metadata: AccountInfo<'static>,
+    pub reward_account: AccountInfo<'static>,
+    pub escrow: AccountInfo<'static>,
+    pub edition: AccountInfo<'static>,
+    pub rarity: AccountInfo<'static>,
+}
+
+/// What `staker` has been paid so far.
+pub fn paid(staker: &Staker) -> u64 {
+    token(&staker.reward_account).amount
+}
+
+pub const REWARD_SUPPLY: u64 = 1_000_000_000;
+
+/// The reward rate of `PlatformOptions::default()`.
+pub const RATE: u64 = 10;
+
+/// The settings a `Farm` is initialized with.
+#[derive(Clone, Copy)]
+pub struct PlatformOptions {
+    pub amount: u64,
+    pub reward_rate: u64,
+    pub reward_mode: RewardMode,
+    pub custody_mode: CustodyMode,
+    pub lock: LockConfig,
+}
+
+/// A one day soft lock forfeiting everything, the behaviour of the original program.
+pub const ONE_DAY_FORFEIT: LockConfig = LockConfig {
+    hard_lock_duration: 0,
+    soft_lock_duration: 86_400,
+    early_unstake_penalty_bps: 10_000,
+};
+
+impl Default for PlatformOptions {
+    fn default() -> Self {
+        PlatformOptions {
+            amount: REWARD_SUPPLY,
+            reward_rate: RATE,
+            reward_mode: RewardMode::PerNft,
+            custody_mode: CustodyMode::Authority,
+            lock: ONE_DAY_FORFEIT,
+        }
+    }
+}
+
+impl Farm {
+    pub fn new() -> Self {
+        let program_id = Pubkey::new_unique();
+        let owner_key = Pubkey::new_unique();
+        let platform_key = Pubkey::new_unique();
+        let (pda, _) = find_vault_address(&program_id, &platform_key);
+        let reward_mint = Pubkey::new_unique();
+        Farm {
+            program_id,
+            owner: account(
+                owner_key,
+                system_program::id(),
+                10_000_000_000,
+                vec![],
+                true,
+                true,
+            ),
+            platform_state: with_signer(&empty_account(platform_key, PlatForm::LEN), true),
+            pda: account(pda, system_program::id(), 0, vec![], false, false),
+            reward_mint,
+            admin_reward: token_account(
+                Pubkey::new_unique(),
+                &reward_mint,
+                &owner_key,
+                REWARD_SUPPLY,
+            ),
+            vault_reward: token_account(Pubkey::new_unique(), &reward_mint, &pda, 0),
+            collection: Pubkey::new_unique(),
+            system_program: program_account(system_program::id()),
+            token_program: program_account(spl_token::id()),
+            metadata_program: program_account(mpl_token_metadata::id()),
+        }
+    }
+
+    /// A new farm initialized with `options`.
+    pub fn with(options: PlatformOptions) -> Self {
+        let farm = Farm::new();
+        farm.initialize_with(options);
+        farm
+    }
+
+    pub fn init_accounts(&self) -> Vec<AccountInfo<'static>> {
+        vec![
+            self.platform_state.clone(),
+            self.owner.clone(),
+            self.admin_reward.clone(),
+            self.vault_reward.clone(),
+            self.pda.clone(),
+            self.system_program.clone(),
+            self.token_program.clone(),
+        ]
+    }
+
+    pub fn init_data(&self, amount: u64, reward_rate: u64) -> Vec<u8> {
+        self.init_data_with(PlatformOptions {
+            amount,
+            reward_rate,
+            ..PlatformOptions::default()
+        })
+    }
+
+    pub fn init_data_with(&self, options: PlatformOptions) -> Vec<u8> {
+        farm::instruction::NftInstruction::InitializePlatform {
+            amount: options.amount,
+            reward_rate: options.reward_rate,
+            reward_mode: options.reward_mode,
+            custody_mode: options.custody_mode,
+            lock: options.lock,
+            collection_rule: farm::state::CollectionRule::VerifiedCollection,
+            collection: self.collection,
+            creators: vec![],
+        }
+        .pack()
+    }
+
+    pub fn initialize(&self, amount: u64, reward_rate: u64) {
+        process(
+            &self.program_id,
+            &self.init_accounts(),
+            &self.init_data(amount, reward_rate),
+        )
+        .unwrap();
+    }
+
+    pub fn initialize_with(&self, options: PlatformOptions) {
+        process(
+            &self.program_id,
+            &self.init_accounts(),
+            &self.init_data_with(options),
+        )
+        .unwrap();
+    }
+
+    pub fn staker(&self) -> Staker {
+        let user_key = Pubkey::new_unique();
+        let mint_key = Pubkey::new_unique();
+        let (user_state, _) =
+            find_user_state_address(&self.program_id, self.platform_state.key, &user_key);
+        let (stake_record, _) =
+            find_stake_record_address(&self.program_id, self.platform_state.key, &mint_key);
+        let (escrow, _) = find_escrow_address(&self.program_id, self.platform_state.key, &mint_key);
+        let (rarity, _) = find_rarity_address(&self.program_id, self.platform_state.key, &mint_key);
+        Staker {
+            user: account(
+                user_key,
+                system_program::id(),
+                1_000_000_000,
+                vec![],
+                true,
+                true,
+            ),
+            user_state: empty_account(user_state, UserState::LEN),
+            stake_record: empty_account(stake_record, StakeRecord::LEN),
+            nft_mint: account(
+                mint_key,
+                spl_token::id(),
+                1,
+                mint_data(1, 0, None),
+                false,
+                false,
+            ),
+            nft_account: token_account(Pubkey::new_unique(), &mint_key, &user_key, 1),
+            metadata: metadata_account(&mint_key, &[], Some((self.collection, true))),
+            reward_account: token_account(Pubkey::new_unique(), &self.reward_mint, &user_key, 0),
+            escrow: empty_account(escrow, TokenAccount::LEN),
+            edition: account(
+                find_edition_address(&mint_key).0,
+                mpl_token_metadata::id(),
+                1,
+                vec![],
+                false,
+                false,
+            ),
+            rarity: empty_account(rarity, Rarity::LEN),
+        }
+    }
+
+    /// Another NFT of the collection held by the same wallet as `staker`.
+    pub fn another_nft(&self, staker: &Staker) -> Staker {
+        let nft = self.staker();
+        Staker {
+            user: staker.user.clone(),
+            user_state: staker.user_state.clone(),
+            nft_account: token_account(Pubkey::new_unique(), nft.nft_mint.key, staker.user.key, 1),
+            reward_account: staker.reward_account.clone(),
+            ..nft
+        }
+    }
+
+    pub fn init_user_accounts(&self, staker: &Staker) -> Vec<AccountInfo<'static>> {
+        vec![
+            staker.user.clone(),
+            staker.user_state.clone(),
+            self.system_program.clone(),
+            self.platform_state.clone(),
+        ]
+    }
+
+    pub fn init_user(&self, staker: &Staker) -> ProgramResult {
+        process(
+            &self.program_id,
+            &self.init_user_accounts(staker),
+            &farm::instruction::NftInstruction::InitializeUser.pack(),
+        )
+    }
+
+    pub fn stake_accounts(&self, staker: &Staker) -> Vec<AccountInfo<'static>> {
+        vec![
+            staker.user.clone(),
+            staker.user_state.clone(),
+            staker.stake_record.clone(),
+            self.platform_state.clone(),
+            staker.nft_account.clone(),
+            staker.nft_mint.clone(),
+            staker.metadata.clone(),
+            self.pda.clone(),
+            self.token_program.clone(),
+            self.system_program.clone(),
+            staker.rarity.clone(),
+            staker.escrow.clone(),
+            staker.edition.clone(),
+            self.metadata_program.clone(),
+        ]
+    }
+
+    pub fn unstake_accounts(&self, staker: &Staker) -> Vec<AccountInfo<'static>> {
+        vec![
+            staker.user.clone(),
+            staker.user_state.clone(),
+            staker.stake_record.clone(),
+            self.platform_state.clone(),
+            staker.nft_account.clone(),
+            staker.nft_mint.clone(),
+            self.pda.clone(),
+            self.token_program.clone(),
+            self.system_program.clone(),
+            staker.reward_account.clone(),
+            self.vault_reward.clone(),
+            staker.escrow.clone(),
+            staker.edition.clone(),
+            self.metadata_program.clone(),
+        ]
+    }
+
+    /// The accounts following an NFT of a batch, depending on the platform's custody mode.
+    fn custody_accounts(&self, staker: &Staker) -> Vec<AccountInfo<'static>> {
+        match platform(&self.platform_state).custody_mode {
+            CustodyMode::Authority => vec![],
+            CustodyMode::Escrow => vec![staker.escrow.clone()],
+            CustodyMode::Frozen => vec![staker.edition.clone()],
+        }
+    }
+
+    pub fn stake_many_accounts(&self, stakers: &[&Staker]) -> Vec<AccountInfo<'static>> {
+        let user = stakers[0];
+        let mut accounts = vec![
+            user.user.clone(),
+            user.user_state.clone(),
+            self.platform_state.clone(),
+            self.pda.clone(),
+            self.token_program.clone(),
+            self.system_program.clone(),
+            self.metadata_program.clone(),
+        ];
+        for staker in stakers {
+            accounts.extend([
+                staker.nft_account.clone(),
+                staker.nft_mint.clone(),
+                staker.stake_record.clone(),
+                staker.metadata.clone(),
+                staker.rarity.clone(),
+            ]);
+            accounts.extend(self.custody_accounts(staker));
+        }
+        accounts
+    }
+
+    pub fn stake_many(&self, stakers: &[&Staker], lock_tier: u8) -> ProgramResult {
+        process(
+            &self.program_id,
+            &self.stake_many_accounts(stakers),
+            &farm::instruction::NftInstruction::StakeMany {
+                count: stakers.len() as u8,
+                lock_tier,
+            }
+            .pack(),
+        )
+    }
+
+    pub fn unstake_many_accounts(&self, stakers: &[&Staker]) -> Vec<AccountInfo<'static>> {
+        let user = stakers[0];
+        let mut accounts = vec![
+            user.user.clone(),
+            user.user_state.clone(),
+            self.platform_state.clone(),
+            self.pda.clone(),
+            self.token_program.clone(),
+            self.system_program.clone(),
+            user.reward_account.clone(),
+            self.vault_reward.clone(),
+            self.metadata_program.clone(),
+        ];
+        for staker in stakers {
+            accounts.extend([
+                staker.nft_account.clone(),
+                staker.nft_mint.clone(),
+                staker.stake_record.clone(),
+            ]);
+            accounts.extend(self.custody_accounts(staker));
+        }
+        accounts
+    }
+
+    pub fn unstake_many(&self, stakers: &[&Staker]) -> ProgramResult {
+        process(
+            &self.program_id,
+            &self.unstake_many_accounts(stakers),
+            &farm::instruction::NftInstruction::UnstakeMany {
+                count: stakers.len() as u8,
+            }
+            .pack(),
+        )
+    }
+
+    pub fn harvest_all_accounts(&self, stakers: &[&Staker]) -> Vec<AccountInfo<'static>> {
+        let user = stakers[0];
+        let mut accounts = vec![
+            user.user.clone(),
+            self.platform_state.clone(),
+            user.reward_account.clone(),
+            self.vault_reward.clone(),
+            self.pda.clone(),
+            self.token_program.clone(),
+        ];
+        for staker in stakers {
+            accounts.extend([staker.stake_record.clone(), staker.rarity.clone()]);
+        }
+        accounts
+    }
+
+    pub fn harvest_all(&self, stakers: &[&Staker]) -> ProgramResult {
+        process(
+            &self.program_id,
+            &self.harvest_all_accounts(stakers),
+            &farm::instruction::NftInstruction::HarvestAll {
+                count: stakers.len() as u8,
+            }
+            .pack(),
+        )
+    }
+
+    pub fn query_pending_rewards_accounts(&self, stakers: &[&Staker]) -> Vec<AccountInfo<'static>> {
+        let mut accounts = vec![self.platform_state.clone()];
+        accounts.extend(stakers.iter().map(|staker| staker.stake_record.clone()));
+        accounts
+    }
+
+    /// Runs `QueryPendingRewards` and decodes what it returned.
+    pub fn query_pending_rewards(
+        &self,
+        stakers: &[&Staker],
+    ) -> Result<PendingRewards, ProgramError> {
+        take_return_data();
+        process(
+            &self.program_id,
+            &self.query_pending_rewards_accounts(stakers),
+            &farm::instruction::NftInstruction::QueryPendingRewards.pack(),
+        )?;
+        let (program_id, data) = take_return_data().expect("no return data");
+        assert_eq!(program_id, self.program_id);
+        PendingRewards::unpack(&data)
+    }
+
+    pub fn emergency_unstake_accounts(&self, staker: &Staker) -> Vec<AccountInfo<'static>> {
+        vec![
+            staker.user.clone(),
+            staker.user_state.clone(),
+            staker.stake_record.clone(),
+            self.platform_state.clone(),
+            staker.nft_account.clone(),
+            staker.nft_mint.clone(),
+            self.pda.clone(),
+            self.token_program.clone(),
+            self.system_program.clone(),
+            staker.escrow.clone(),
+            staker.edition.clone(),
+            self.metadata_program.clone(),
+        ]
+    }
+
+    pub fn harvest_accounts(&self, staker: &Staker) -> Vec<AccountInfo<'static>> {
+        vec![
+            staker.user.clone(),
+            staker.stake_record.clone(),
+            self.platform_state.clone(),
+            staker.reward_account.clone(),
+            self.vault_reward.clone(),
+            self.pda.clone(),
+            self.token_program.clone(),
+            staker.rarity.clone(),
+        ]
+    }
+
+    pub fn stake(&self, staker: &Staker) -> ProgramResult {
+        self.stake_with_tier(staker, 0)
+    }
+
+    pub fn stake_with_tier(&self, staker: &Staker, lock_tier: u8) -> ProgramResult {
+        self.stake_with_proof(staker, lock_tier, None)
+    }
+
+    pub fn stake_with_proof(
+        &self,
+        staker: &Staker,
+        lock_tier: u8,
+        rarity: Option<RarityProof>,
+    ) -> ProgramResult {
+        process(
+            &self.program_id,
+            &self.stake_accounts(staker),
+            &farm::instruction::NftInstruction::StakeNft { lock_tier, rarity }.pack(),
+        )
+    }
+
+    pub fn unstake(&self, staker: &Staker) -> ProgramResult {
+        process(
+            &self.program_id,
+            &self.unstake_accounts(staker),
+            &farm::instruction::NftInstruction::UnStakeNft.pack(),
+        )
+    }
+
+    pub fn harvest(&self, staker: &Staker) -> ProgramResult {
+        process(
+            &self.program_id,
+            &self.harvest_accounts(staker),
+            &farm::instruction::NftInstruction::Harvest.pack(),
+        )
+    }
+
+    pub fn emergency_unstake(&self, staker: &Staker) -> ProgramResult {
+        process(
+            &self.program_id,
+            &self.emergency_unstake_accounts(staker),
+            &farm::instruction::NftInstruction::EmergencyUnstake.pack(),
+        )
+    }
+
+    pub fn close_user_state(&self, staker: &Staker) -> ProgramResult {
+        process(
+            &self.program_id,
+            &[
+                staker.user.clone(),
+                staker.user_state.clone(),
+                self.platform_state.clone(),
+            ],
+            &farm::instruction::NftInstruction::CloseUserState.pack(),
+        )
+    }
+
+    pub fn close_platform_accounts(&self) -> Vec<AccountInfo<'static>> {
+        vec![
+            self.owner.clone(),
+            self.platform_state.clone(),
+            self.vault_reward.clone(),
+            self.pda.clone(),
+            self.token_program.clone(),
+        ]
+    }
+
+    pub fn close_platform(&self) -> ProgramResult {
+        process(
+            &self.program_id,
+            &self.close_platform_accounts(),
+            &farm::instruction::NftInstruction::ClosePlatform.pack(),
+        )
+    }
+
+    pub fn set_auto_close_user_state(&self, enabled: bool) -> ProgramResult {
+        process(
+            &self.program_id,
+            &[self.owner.clone(), self.platform_state.clone()],
+            &farm::instruction::NftInstruction::SetAutoCloseUserState { enabled }.pack(),
+        )
+    }
+
+    pub fn update_reward_rate(&self, reward_rate: u64) -> ProgramResult {
+        process(
+            &self.program_id,
+            &[self.owner.clone(), self.platform_state.clone()],
+            &farm::instruction::NftInstruction::UpdateRewardRate { reward_rate }.pack(),
+        )
+    }
+
+    pub fn set_paused(&self, paused: bool) -> ProgramResult {
+        process(
+            &self.program_id,
+            &[self.owner.clone(), self.platform_state.clone()],
+            &farm::instruction::NftInstruction::SetPaused { paused }.pack(),
+        )
+    }
+
+    pub fn set_lock_tiers(&self, tiers: &[LockTier]) -> ProgramResult {
+        process(
+            &self.program_id,
+            &[self.owner.clone(), self.platform_state.clone()],
+            &farm::instruction::NftInstruction::SetLockTiers {
+                tiers: tiers.to_vec(),
+            }
+            .pack(),
+        )
+    }
+
+    pub fn top_up(
+        &self,
+        depositor: &AccountInfo<'static>,
+        source: &AccountInfo<'static>,
+        amount: u64,
+    ) -> ProgramResult {
+        process(
+            &self.program_id,
+            &[
+                depositor.clone(),
+                source.clone(),
+                self.platform_state.clone(),
+                self.vault_reward.clone(),
+                self.pda.clone(),
+                self.token_program.clone(),
+            ],
+            &farm::instruction::NftInstruction::TopUpRewards { amount }.pack(),
+        )
+    }
+
+    pub fn withdraw_accounts(&self) -> Vec<AccountInfo<'static>> {
+        vec![
+            self.owner.clone(),
+            self.platform_state.clone(),
+            self.vault_reward.clone(),
+            self.admin_reward.clone(),
+            self.pda.clone(),
+            self.token_program.clone(),
+        ]
+    }
+
+    pub fn withdraw(&self, amount: u64) -> ProgramResult {
+        process(
+            &self.program_id,
+            &self.withdraw_accounts(),
+            &farm::instruction::NftInstruction::WithdrawRewards { amount }.pack(),
+        )
+    }
+
+    pub fn set_rarity_root(&self, root: [u8; 32]) -> ProgramResult {
+        process(
+            &self.program_id,
+            &[self.owner.clone(), self.platform_state.clone()],
+            &farm::instruction::NftInstruction::SetRarityRoot { root }.pack(),
+        )
+    }
+
+    pub fn set_rarity(&self, rarities: &[(&Staker, u16)]) -> ProgramResult {
+        let mut accounts = vec![
+            self.owner.clone(),
+            self.platform_state.clone(),
+            self.system_program.clone(),
+        ];
+        accounts.extend(rarities.iter().map(|(staker, _)| staker.rarity.clone()));
+        let entries = rarities
+            .iter()
+            .map(|(staker, multiplier_bps)| RarityEntry {
+                mint: *staker.nft_mint.key,
+                multiplier_bps: *multiplier_bps,
+            })
+            .collect();
+        process(
+            &self.program_id,
+            &accounts,
+            &farm::instruction::NftInstruction::SetRarity { entries }.pack(),
+        )
+    }
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use common::*;
+use farm::state::RewardMode;
+
+const EMISSION: u64 = 100;
+const DAY: i64 = 86_400;
+
+#[test]
+fn test_single_staker_receives_whole_emission() {
+    let farm = Farm::with(PlatformOptions {
+        reward_rate: EMISSION,
+        reward_mode: RewardMode::Emission,
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    advance_time(2 * DAY);
+    farm.unstake(&staker).unwrap();
+    assert_eq!(paid(&staker), 2 * DAY as u64 * EMISSION);
+}
+
+#[test]
+fn test_emission_is_shared_between_stakers() {
+    let farm = Farm::with(PlatformOptions {
+        reward_rate: EMISSION,
+        reward_mode: RewardMode::Emission,
+        ..PlatformOptions::default()
+    });
+    let alice = farm.staker();
+    let bob = farm.staker();
+    let carol = farm.staker();
+
+    farm.stake(&alice).unwrap();
+    advance_time(DAY);
+    farm.stake(&bob).unwrap();
+    advance_time(DAY);
+    farm.stake(&carol).unwrap();
+    advance_time(3 * DAY);
+    farm.unstake(&alice).unwrap();
+    advance_time(2 * DAY);
+    farm.unstake(&bob).unwrap();
+    farm.unstake(&carol).unwrap();
+
+    let day = DAY as u64 * EMISSION;
+    assert_eq!(paid(&alice), day + day / 2 + 3 * day / 3);
+    assert_eq!(paid(&bob), day / 2 + 3 * day / 3 + 2 * day / 2);
+    assert_eq!(paid(&carol), 3 * day / 3 + 2 * day / 2);
+    // the pool emitted exactly seven days, however many NFTs were staked
+    assert_eq!(paid(&alice) + paid(&bob) + paid(&carol), 7 * day);
+}
+
+#[test]
+fn test_nothing_accrues_while_pool_is_empty() {
+    let farm = Farm::with(PlatformOptions {
+        reward_rate: EMISSION,
+        reward_mode: RewardMode::Emission,
+        ..PlatformOptions::default()
+    });
+    advance_time(5 * DAY);
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    advance_time(2 * DAY);
+    farm.harvest(&staker).unwrap();
+    assert_eq!(paid(&staker), 2 * DAY as u64 * EMISSION);
+    assert_eq!(
+        token(&farm.vault_reward).amount,
+        REWARD_SUPPLY - 2 * DAY as u64 * EMISSION
+    );
+}
+
+#[test]
+fn test_rate_change_applies_to_the_whole_pool() {
+    let farm = Farm::with(PlatformOptions {
+        reward_rate: EMISSION,
+        reward_mode: RewardMode::Emission,
+        ..PlatformOptions::default()
+    });
+    let alice = farm.staker();
+    let bob = farm.staker();
+    farm.stake(&alice).unwrap();
+    farm.stake(&bob).unwrap();
+    advance_time(2 * DAY);
+    let update = farm::instruction::update_reward_rate(
+        &farm.program_id,
+        farm.owner.key,
+        farm.platform_state.key,
+        2 * EMISSION,
+    );
+    process_instruction(&update, &farm.init_accounts()).unwrap();
+    advance_time(DAY);
+    farm.harvest(&alice).unwrap();
+    farm.harvest(&bob).unwrap();
+
+    let expected = (2 * DAY as u64 * EMISSION + DAY as u64 * 2 * EMISSION) / 2;
+    assert_eq!(paid(&alice), expected);
+    assert_eq!(paid(&bob), expected);
+}
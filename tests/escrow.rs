@@ -0,0 +1,165 @@
+mod common;
+
+use common::*;
+use farm::{
+    error::FarmError,
+    instruction::NftInstruction,
+    state::{find_escrow_address, CustodyMode, StakeRecord},
+};
+use solana_program::{
+    account_info::AccountInfo, program_error::ProgramError, program_option::COption,
+    program_pack::Pack, pubkey::Pubkey, rent::Rent, system_program,
+};
+use spl_token::state::Account as TokenAccount;
+
+fn set_close_authority(info: &AccountInfo, authority: Pubkey) {
+    let mut account = token(info);
+    account.close_authority = COption::Some(authority);
+    TokenAccount::pack(account, &mut info.data.borrow_mut()).unwrap();
+}
+
+#[test]
+fn test_escrow_holds_nft_until_unstake() {
+    let farm = Farm::with(PlatformOptions {
+        custody_mode: CustodyMode::Escrow,
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+
+    let escrow = token(&staker.escrow);
+    assert_eq!(escrow.owner, *farm.pda.key);
+    assert_eq!(escrow.mint, *staker.nft_mint.key);
+    assert_eq!(escrow.amount, 1);
+    assert_eq!(escrow.delegate, COption::None);
+    assert_eq!(escrow.close_authority, COption::None);
+    // the user's own token account stays with the user, just empty
+    assert_eq!(token(&staker.nft_account).owner, *staker.user.key);
+    assert_eq!(token(&staker.nft_account).amount, 0);
+    let (_, escrow_bump) = find_escrow_address(
+        &farm.program_id,
+        farm.platform_state.key,
+        staker.nft_mint.key,
+    );
+    assert_eq!(stake_record(&staker.stake_record).escrow_bump, escrow_bump);
+
+    advance_time(2 * 86_400);
+    let lamports_before = staker.user.lamports();
+    farm.unstake(&staker).unwrap();
+    assert_eq!(token(&staker.nft_account).amount, 1);
+    assert_eq!(staker.escrow.lamports(), 0);
+    assert_eq!(
+        staker.user.lamports() - lamports_before,
+        Rent::default().minimum_balance(TokenAccount::LEN)
+            + Rent::default().minimum_balance(StakeRecord::LEN)
+    );
+    assert_eq!(token(&staker.reward_account).amount, 2 * 86_400 * 10);
+}
+
+#[test]
+fn test_escrow_restake_after_unstake() {
+    let farm = Farm::with(PlatformOptions {
+        custody_mode: CustodyMode::Escrow,
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    farm.unstake(&staker).unwrap();
+    // closed accounts are handed back to the system program by the runtime
+    for info in [&staker.escrow, &staker.stake_record] {
+        info.assign(&system_program::id());
+    }
+    farm.stake(&staker).unwrap();
+    assert_eq!(token(&staker.escrow).amount, 1);
+}
+
+#[test]
+fn test_escrow_opens_on_a_prefunded_address() {
+    let farm = Farm::with(PlatformOptions {
+        custody_mode: CustodyMode::Escrow,
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    // lamports anyone can send to the escrow address ahead of the stake
+    **staker.escrow.lamports.borrow_mut() = 1;
+    farm.stake(&staker).unwrap();
+
+    assert_eq!(*staker.escrow.owner, spl_token::id());
+    assert_eq!(
+        staker.escrow.lamports(),
+        Rent::default().minimum_balance(TokenAccount::LEN)
+    );
+    let escrow = token(&staker.escrow);
+    assert_eq!(escrow.owner, *farm.pda.key);
+    assert_eq!(escrow.amount, 1);
+
+    advance_time(2 * 86_400);
+    farm.unstake(&staker).unwrap();
+    assert_eq!(token(&staker.nft_account).amount, 1);
+}
+
+#[test]
+fn test_escrow_unstake_rejects_foreign_destination() {
+    let farm = Farm::with(PlatformOptions {
+        custody_mode: CustodyMode::Escrow,
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+
+    let mut accounts = farm.unstake_accounts(&staker);
+    accounts[4] = token_account(
+        Pubkey::new_unique(),
+        staker.nft_mint.key,
+        &Pubkey::new_unique(),
+        0,
+    );
+    assert_eq!(
+        process(
+            &farm.program_id,
+            &accounts,
+            &NftInstruction::UnStakeNft.pack()
+        ),
+        Err(FarmError::InvalidTokenAccountOwner.into())
+    );
+
+    let mut accounts = farm.unstake_accounts(&staker);
+    accounts[11] = empty_account(Pubkey::new_unique(), TokenAccount::LEN);
+    assert_eq!(
+        process(
+            &farm.program_id,
+            &accounts,
+            &NftInstruction::UnStakeNft.pack()
+        ),
+        Err(ProgramError::InvalidAccountData)
+    );
+}
+
+#[test]
+fn test_escrow_with_lingering_close_authority_is_rejected() {
+    let farm = Farm::with(PlatformOptions {
+        custody_mode: CustodyMode::Escrow,
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    set_close_authority(&staker.escrow, *staker.user.key);
+    assert_eq!(
+        farm.unstake(&staker),
+        Err(FarmError::TokenAccountHasAuthority.into())
+    );
+}
+
+#[test]
+fn test_authority_mode_rejects_close_authority() {
+    let farm = Farm::with(PlatformOptions {
+        custody_mode: CustodyMode::Authority,
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    set_close_authority(&staker.nft_account, *staker.user.key);
+    assert_eq!(
+        farm.stake(&staker),
+        Err(FarmError::TokenAccountHasAuthority.into())
+    );
+}
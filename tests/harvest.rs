@@ -0,0 +1,90 @@
+mod common;
+
+use common::*;
+use farm::state::RewardMode;
+
+#[test]
+fn test_second_harvest_in_same_slot_pays_zero() {
+    let farm = Farm::with(PlatformOptions::default());
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    advance_time(2 * 86_400);
+    farm.harvest(&staker).unwrap();
+    let paid = token(&staker.reward_account).amount;
+    assert_eq!(paid, 2 * 86_400 * 10);
+
+    for _ in 0..3 {
+        farm.harvest(&staker).unwrap();
+    }
+    assert_eq!(token(&staker.reward_account).amount, paid);
+    assert_eq!(token(&farm.vault_reward).amount, REWARD_SUPPLY - paid);
+}
+
+#[test]
+fn test_harvest_pays_only_since_last_claim() {
+    let farm = Farm::with(PlatformOptions::default());
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    advance_time(2 * 86_400);
+    farm.harvest(&staker).unwrap();
+    let record = stake_record(&staker.stake_record);
+    assert_eq!(record.last_claim_time, now());
+    assert_eq!(record.accrued_reward, 0);
+
+    advance_time(100);
+    farm.harvest(&staker).unwrap();
+    assert_eq!(
+        token(&staker.reward_account).amount,
+        2 * 86_400 * 10 + 100 * 10
+    );
+    assert_eq!(stake_record(&staker.stake_record).last_claim_time, now());
+
+    advance_time(50);
+    farm.unstake(&staker).unwrap();
+    assert_eq!(
+        token(&staker.reward_account).amount,
+        2 * 86_400 * 10 + 150 * 10
+    );
+}
+
+#[test]
+fn test_harvest_before_one_day_keeps_rewards_accrued() {
+    let farm = Farm::with(PlatformOptions::default());
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    advance_time(3_600);
+    farm.harvest(&staker).unwrap();
+    let record = stake_record(&staker.stake_record);
+    assert_eq!(token(&staker.reward_account).amount, 0);
+    assert_eq!(record.accrued_reward, 3_600 * 10);
+    assert_eq!(record.last_claim_time, record.stake_time);
+
+    advance_time(86_400);
+    farm.harvest(&staker).unwrap();
+    farm.harvest(&staker).unwrap();
+    assert_eq!(token(&staker.reward_account).amount, (3_600 + 86_400) * 10);
+}
+
+#[test]
+fn test_second_emission_harvest_in_same_slot_pays_zero() {
+    let farm = Farm::with(PlatformOptions {
+        reward_mode: RewardMode::Emission,
+        ..PlatformOptions::default()
+    });
+    let alice = farm.staker();
+    let bob = farm.staker();
+    farm.stake(&alice).unwrap();
+    farm.stake(&bob).unwrap();
+    advance_time(2 * 86_400);
+
+    farm.harvest(&alice).unwrap();
+    let paid = token(&alice.reward_account).amount;
+    assert_eq!(paid, 86_400 * 10);
+    farm.harvest(&alice).unwrap();
+    assert_eq!(token(&alice.reward_account).amount, paid);
+    assert_eq!(stake_record(&alice.stake_record).last_claim_time, now());
+
+    // the other staker's share is untouched by the repeated claims
+    farm.harvest(&bob).unwrap();
+    assert_eq!(token(&bob.reward_account).amount, paid);
+}
@@ -0,0 +1,168 @@
+mod common;
+
+use common::*;
+use farm::{
+    error::FarmError,
+    instruction::{NftInstruction, MAX_HARVEST_ALL},
+    state::{LockConfig, LockTier},
+};
+use solana_program::program_error::ProgramError;
+
+const DAY: i64 = 86_400;
+
+/// A wallet holding `count` staked NFTs of the farm's collection.
+fn staked_wallet(farm: &Farm, count: usize) -> Vec<Staker> {
+    let first = farm.staker();
+    let mut nfts = (1..count)
+        .map(|_| farm.another_nft(&first))
+        .collect::<Vec<_>>();
+    nfts.insert(0, first);
+    for nft in &nfts {
+        farm.stake(nft).unwrap();
+    }
+    nfts
+}
+
+#[test]
+fn test_harvest_all_pays_once() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let nfts = staked_wallet(&farm, MAX_HARVEST_ALL);
+    let batch = nfts.iter().collect::<Vec<_>>();
+
+    advance_time(DAY);
+    farm.harvest_all(&batch).unwrap();
+    let earned = MAX_HARVEST_ALL as u64 * DAY as u64 * RATE;
+    assert_eq!(paid(&nfts[0]), earned);
+    assert_eq!(token(&farm.vault_reward).amount, REWARD_SUPPLY - earned);
+    for nft in &nfts {
+        let record = stake_record(&nft.stake_record);
+        assert_eq!(record.accrued_reward, 0);
+        assert_eq!(record.last_claim_time, now());
+    }
+    let state = platform(&farm.platform_state);
+    assert_eq!(state.total_paid, earned);
+    assert_eq!(state.total_owed(), Ok(0));
+}
+
+#[test]
+fn test_harvest_all_matches_single_harvests() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let mine = staked_wallet(&farm, 3);
+    let theirs = staked_wallet(&farm, 3);
+
+    advance_time(DAY);
+    farm.harvest_all(&mine.iter().collect::<Vec<_>>()).unwrap();
+    for nft in &theirs {
+        farm.harvest(nft).unwrap();
+    }
+    assert_eq!(paid(&mine[0]), paid(&theirs[0]));
+}
+
+#[test]
+fn test_harvest_all_can_cover_a_subset() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let nfts = staked_wallet(&farm, 3);
+
+    advance_time(DAY);
+    farm.harvest_all(&[&nfts[0], &nfts[2]]).unwrap();
+    assert_eq!(paid(&nfts[0]), 2 * DAY as u64 * RATE);
+    assert_eq!(
+        stake_record(&nfts[1].stake_record).last_claim_time,
+        now() - DAY as u64
+    );
+
+    // a record passed twice pays nothing the second time
+    advance_time(DAY);
+    farm.harvest_all(&[&nfts[1], &nfts[1]]).unwrap();
+    assert_eq!(paid(&nfts[0]), 4 * DAY as u64 * RATE);
+}
+
+#[test]
+fn test_harvest_all_keeps_locked_rewards() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    farm.set_lock_tiers(&[LockTier {
+        duration: 7 * DAY as u64,
+        multiplier_bps: 10_000,
+    }])
+    .unwrap();
+    let unlocked = farm.staker();
+    let locked = farm.another_nft(&unlocked);
+    farm.stake(&unlocked).unwrap();
+    farm.stake_with_tier(&locked, 1).unwrap();
+
+    advance_time(DAY);
+    farm.harvest_all(&[&unlocked, &locked]).unwrap();
+    assert_eq!(paid(&unlocked), DAY as u64 * RATE);
+    assert_eq!(
+        stake_record(&locked.stake_record).accrued_reward,
+        DAY as u64 * RATE
+    );
+}
+
+#[test]
+fn test_harvest_all_only_takes_own_records() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let mine = staked_wallet(&farm, 1);
+    let theirs = staked_wallet(&farm, 1);
+
+    advance_time(DAY);
+    assert_eq!(
+        farm.harvest_all(&[&mine[0], &theirs[0]]),
+        Err(FarmError::InvalidAuthority.into())
+    );
+}
+
+#[test]
+fn test_harvest_all_count() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let nfts = staked_wallet(&farm, 2);
+    let accounts = farm.harvest_all_accounts(&[&nfts[0], &nfts[1]]);
+
+    for data in [vec![22], vec![22, 0], vec![22, MAX_HARVEST_ALL as u8 + 1]] {
+        assert_eq!(
+            process(&farm.program_id, &accounts, &data),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+    assert_eq!(
+        process(
+            &farm.program_id,
+            &accounts,
+            &NftInstruction::HarvestAll { count: 3 }.pack()
+        ),
+        Err(ProgramError::NotEnoughAccountKeys)
+    );
+}
+
+#[test]
+fn test_harvest_all_rejected_while_paused() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let nfts = staked_wallet(&farm, 2);
+    farm.set_paused(true).unwrap();
+
+    assert_eq!(
+        farm.harvest_all(&[&nfts[0], &nfts[1]]),
+        Err(FarmError::PlatformPaused.into())
+    );
+}
@@ -0,0 +1,304 @@
+mod common;
+
+use common::*;
+use farm::{
+    instruction::{self, max_stake_many, max_unstake_many, MAX_HARVEST_ALL},
+    state::{CollectionRule, CustodyMode, RewardMode},
+};
+use solana_program::{account_info::AccountInfo, instruction::Instruction, pubkey::Pubkey};
+
+fn pool(farm: &Farm, staker: &Staker) -> Vec<AccountInfo<'static>> {
+    let mut pool = farm.init_accounts();
+    pool.extend(farm.unstake_accounts(staker));
+    pool.push(staker.metadata.clone());
+    pool.push(staker.rarity.clone());
+    pool
+}
+
+#[test]
+fn test_builders_match_processor_account_order() {
+    let farm = Farm::new();
+    let staker = farm.staker();
+    let pool = pool(&farm, &staker);
+    let keys = |accounts: Vec<AccountInfo>| accounts.iter().map(|a| *a.key).collect::<Vec<_>>();
+    let metas = |ix: &solana_program::instruction::Instruction| {
+        ix.accounts.iter().map(|m| m.pubkey).collect::<Vec<_>>()
+    };
+
+    let init = instruction::initialize_platform(
+        &farm.program_id,
+        farm.platform_state.key,
+        farm.owner.key,
+        farm.admin_reward.key,
+        farm.vault_reward.key,
+        REWARD_SUPPLY,
+        10,
+        RewardMode::PerNft,
+        CustodyMode::Authority,
+        ONE_DAY_FORFEIT,
+        CollectionRule::VerifiedCollection,
+        &farm.collection,
+        &[],
+    );
+    assert_eq!(metas(&init), keys(farm.init_accounts()));
+    process_instruction(&init, &pool).unwrap();
+
+    let init_user =
+        instruction::initialize_user(&farm.program_id, staker.user.key, farm.platform_state.key);
+    assert_eq!(metas(&init_user), keys(farm.init_user_accounts(&staker)));
+    process_instruction(&init_user, &pool).unwrap();
+
+    let stake = instruction::stake_nft(
+        &farm.program_id,
+        staker.user.key,
+        farm.platform_state.key,
+        staker.nft_account.key,
+        staker.nft_mint.key,
+        0,
+        None,
+    );
+    assert_eq!(metas(&stake), keys(farm.stake_accounts(&staker)));
+    process_instruction(&stake, &pool).unwrap();
+
+    advance_time(3 * 86_400);
+    let query = instruction::query_pending_rewards(
+        &farm.program_id,
+        farm.platform_state.key,
+        &[*staker.nft_mint.key],
+    );
+    process_instruction(&query, &pool).unwrap();
+    let (_, data) = take_return_data().unwrap();
+    let pending = instruction::PendingRewards::unpack(&data).unwrap().pending;
+    assert_eq!(pending, 3 * 86_400 * 10);
+
+    let harvest = instruction::harvest(
+        &farm.program_id,
+        staker.user.key,
+        farm.platform_state.key,
+        staker.nft_mint.key,
+        staker.reward_account.key,
+        farm.vault_reward.key,
+    );
+    assert_eq!(metas(&harvest), keys(farm.harvest_accounts(&staker)));
+    process_instruction(&harvest, &pool).unwrap();
+    assert_eq!(token(&staker.reward_account).amount, 3 * 86_400 * 10);
+
+    let update = instruction::update_reward_rate(
+        &farm.program_id,
+        farm.owner.key,
+        farm.platform_state.key,
+        20,
+    );
+    process_instruction(&update, &pool).unwrap();
+
+    // only picked up at the next harvest, so the unstake below still pays 1x
+    let set_rarity = instruction::set_rarity(
+        &farm.program_id,
+        farm.owner.key,
+        farm.platform_state.key,
+        &[instruction::RarityEntry {
+            mint: *staker.nft_mint.key,
+            multiplier_bps: 20_000,
+        }],
+    );
+    process_instruction(&set_rarity, &pool).unwrap();
+    assert_eq!(*staker.rarity.owner, farm.program_id);
+
+    advance_time(2 * 86_400);
+    let unstake = instruction::unstake_nft(
+        &farm.program_id,
+        staker.user.key,
+        farm.platform_state.key,
+        staker.nft_account.key,
+        staker.nft_mint.key,
+        staker.reward_account.key,
+        farm.vault_reward.key,
+    );
+    assert_eq!(metas(&unstake), keys(farm.unstake_accounts(&staker)));
+    process_instruction(&unstake, &pool).unwrap();
+    assert_eq!(token(&staker.nft_account).owner, *staker.user.key);
+    assert_eq!(
+        token(&staker.reward_account).amount,
+        3 * 86_400 * 10 + 2 * 86_400 * 20
+    );
+
+    // nothing is owed any more, so the rest of the vault can go back to the owner
+    let left = token(&farm.vault_reward).amount;
+    let withdraw = instruction::withdraw_rewards(
+        &farm.program_id,
+        farm.owner.key,
+        farm.platform_state.key,
+        farm.vault_reward.key,
+        farm.admin_reward.key,
+        left,
+    );
+    assert_eq!(metas(&withdraw), keys(farm.withdraw_accounts()));
+    process_instruction(&withdraw, &pool).unwrap();
+    assert_eq!(token(&farm.vault_reward).amount, 0);
+
+    let propose = instruction::propose_owner(
+        &farm.program_id,
+        farm.owner.key,
+        farm.platform_state.key,
+        staker.user.key,
+    );
+    process_instruction(&propose, &pool).unwrap();
+    let accept =
+        instruction::accept_owner(&farm.program_id, staker.user.key, farm.platform_state.key);
+    process_instruction(&accept, &pool).unwrap();
+    assert_eq!(platform(&farm.platform_state).owner, *staker.user.key);
+
+    // the vault was emptied above, so the new owner can wind the platform down
+    let close_platform = instruction::close_platform(
+        &farm.program_id,
+        staker.user.key,
+        farm.platform_state.key,
+        farm.vault_reward.key,
+    );
+    process_instruction(&close_platform, &pool).unwrap();
+    assert_eq!(farm.platform_state.lamports(), 0);
+
+    let close_user_state =
+        instruction::close_user_state(&farm.program_id, staker.user.key, farm.platform_state.key);
+    process_instruction(&close_user_state, &pool).unwrap();
+    assert_eq!(staker.user_state.lamports(), 0);
+}
+
+#[test]
+fn test_batch_builders_match_processor_account_order() {
+    let farm = Farm::with(PlatformOptions {
+        custody_mode: CustodyMode::Escrow,
+        ..PlatformOptions::default()
+    });
+    let first = farm.staker();
+    let second = farm.another_nft(&first);
+    let nfts = [
+        (*first.nft_account.key, *first.nft_mint.key),
+        (*second.nft_account.key, *second.nft_mint.key),
+    ];
+    let mut pool = farm.stake_many_accounts(&[&first, &second]);
+    pool.extend(farm.unstake_many_accounts(&[&first, &second]));
+    pool.extend(farm.harvest_all_accounts(&[&first, &second]));
+    let keys = |accounts: Vec<AccountInfo>| accounts.iter().map(|a| *a.key).collect::<Vec<_>>();
+    let metas = |ix: &solana_program::instruction::Instruction| {
+        ix.accounts.iter().map(|m| m.pubkey).collect::<Vec<_>>()
+    };
+
+    let stake = instruction::stake_many(
+        &farm.program_id,
+        first.user.key,
+        farm.platform_state.key,
+        &nfts,
+        0,
+        CustodyMode::Escrow,
+    );
+    assert_eq!(
+        metas(&stake),
+        keys(farm.stake_many_accounts(&[&first, &second]))
+    );
+    process_instruction(&stake, &pool).unwrap();
+
+    advance_time(86_400);
+    let harvest = instruction::harvest_all(
+        &farm.program_id,
+        first.user.key,
+        farm.platform_state.key,
+        &[*first.nft_mint.key, *second.nft_mint.key],
+        first.reward_account.key,
+        farm.vault_reward.key,
+    );
+    assert_eq!(
+        metas(&harvest),
+        keys(farm.harvest_all_accounts(&[&first, &second]))
+    );
+    process_instruction(&harvest, &pool).unwrap();
+    assert_eq!(token(&first.reward_account).amount, 2 * 86_400 * 10);
+
+    let unstake = instruction::unstake_many(
+        &farm.program_id,
+        first.user.key,
+        farm.platform_state.key,
+        &nfts,
+        first.reward_account.key,
+        farm.vault_reward.key,
+        CustodyMode::Escrow,
+    );
+    assert_eq!(
+        metas(&unstake),
+        keys(farm.unstake_many_accounts(&[&first, &second]))
+    );
+    process_instruction(&unstake, &pool).unwrap();
+    assert_eq!(token(&second.nft_account).amount, 1);
+}
+
+#[test]
+fn test_full_batches_fit_in_one_transaction() {
+    let program_id = Pubkey::new_unique();
+    let user = Pubkey::new_unique();
+    let platform_state = Pubkey::new_unique();
+    let nfts = |count: usize| {
+        (0..count)
+            .map(|_| (Pubkey::new_unique(), Pubkey::new_unique()))
+            .collect::<Vec<_>>()
+    };
+    let stake_many = |count: usize, custody_mode: CustodyMode| {
+        instruction::stake_many(
+            &program_id,
+            &user,
+            &platform_state,
+            &nfts(count),
+            0,
+            custody_mode,
+        )
+    };
+    let unstake_many = |count: usize, custody_mode: CustodyMode| {
+        instruction::unstake_many(
+            &program_id,
+            &user,
+            &platform_state,
+            &nfts(count),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            custody_mode,
+        )
+    };
+
+    let harvest_all = |count: usize| {
+        let mints = (0..count).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        instruction::harvest_all(
+            &program_id,
+            &user,
+            &platform_state,
+            &mints,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        )
+    };
+
+    for custody_mode in [
+        CustodyMode::Authority,
+        CustodyMode::Escrow,
+        CustodyMode::Frozen,
+    ] {
+        let max = max_stake_many(custody_mode);
+        assert!(transaction_size(&[stake_many(max, custody_mode)]) <= PACKET_DATA_SIZE);
+        assert!(transaction_size(&[stake_many(max + 1, custody_mode)]) > PACKET_DATA_SIZE);
+
+        let max = max_unstake_many(custody_mode);
+        assert!(transaction_size(&[unstake_many(max, custody_mode)]) <= PACKET_DATA_SIZE);
+        assert!(transaction_size(&[unstake_many(max + 1, custody_mode)]) > PACKET_DATA_SIZE);
+    }
+
+    // `ComputeBudgetInstruction::SetComputeUnitLimit(1_400_000)`
+    let compute_unit_limit = Instruction {
+        program_id: "ComputeBudget111111111111111111111111111111"
+            .parse()
+            .unwrap(),
+        accounts: vec![],
+        data: [&[2][..], &1_400_000u32.to_le_bytes()].concat(),
+    };
+    let full = [compute_unit_limit.clone(), harvest_all(MAX_HARVEST_ALL)];
+    assert!(transaction_size(&full) <= PACKET_DATA_SIZE);
+    let over = [compute_unit_limit, harvest_all(MAX_HARVEST_ALL + 1)];
+    assert!(transaction_size(&over) > PACKET_DATA_SIZE);
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use common::*;
+use farm::{
+    error::FarmError,
+    state::{LockConfig, LockTier, RewardMode},
+};
+use solana_program::program_error::ProgramError;
+
+const DAY: i64 = 86_400;
+
+const TIERS: [LockTier; 2] = [
+    LockTier {
+        duration: 30 * DAY as u64,
+        multiplier_bps: 15_000,
+    },
+    LockTier {
+        duration: 90 * DAY as u64,
+        multiplier_bps: 20_000,
+    },
+];
+
+#[test]
+fn test_tier_is_recorded_at_stake() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    farm.set_lock_tiers(&TIERS).unwrap();
+    let staker = farm.staker();
+    let start = now();
+    farm.stake_with_tier(&staker, 2).unwrap();
+
+    let record = stake_record(&staker.stake_record);
+    assert_eq!(record.lock_tier, 2);
+    assert_eq!(record.unlock_time, start + 90 * DAY as u64);
+    assert_eq!(record.weight_bps, 20_000);
+    assert_eq!(platform(&farm.platform_state).total_weight_bps, 20_000);
+}
+
+#[test]
+fn test_tier_locks_and_multiplies_rewards() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    farm.set_lock_tiers(&TIERS).unwrap();
+    let staker = farm.staker();
+    farm.stake_with_tier(&staker, 1).unwrap();
+
+    advance_time(30 * DAY - 1);
+    assert_eq!(farm.unstake(&staker), Err(FarmError::StakeLocked.into()));
+    farm.harvest(&staker).unwrap();
+    assert_eq!(paid(&staker), 0);
+
+    advance_time(1);
+    farm.harvest(&staker).unwrap();
+    assert_eq!(paid(&staker), 30 * DAY as u64 * RATE * 3 / 2);
+    farm.unstake(&staker).unwrap();
+    assert_eq!(platform(&farm.platform_state).total_weight_bps, 0);
+}
+
+#[test]
+fn test_emission_is_split_by_tier_weight() {
+    let farm = Farm::with(PlatformOptions {
+        reward_mode: RewardMode::Emission,
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    farm.set_lock_tiers(&TIERS).unwrap();
+    let plain = farm.staker();
+    let boosted = farm.staker();
+    farm.stake(&plain).unwrap();
+    farm.stake_with_tier(&boosted, 2).unwrap();
+
+    advance_time(90 * DAY);
+    farm.harvest(&plain).unwrap();
+    farm.harvest(&boosted).unwrap();
+    let emitted = 90 * DAY as u64 * RATE;
+    assert_eq!(paid(&plain), emitted / 3);
+    assert_eq!(paid(&boosted), emitted * 2 / 3);
+}
+
+#[test]
+fn test_unknown_tier_is_rejected() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    farm.set_lock_tiers(&TIERS).unwrap();
+    let staker = farm.staker();
+    assert_eq!(
+        farm.stake_with_tier(&staker, 3),
+        Err(FarmError::InvalidLockTier.into())
+    );
+}
+
+#[test]
+fn test_set_lock_tiers_validation() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    farm.set_lock_tiers(&TIERS).unwrap();
+    let zero = LockTier {
+        duration: DAY as u64,
+        multiplier_bps: 0,
+    };
+    assert_eq!(
+        farm.set_lock_tiers(&[zero]),
+        Err(ProgramError::InvalidArgument)
+    );
+    assert_eq!(
+        farm.set_lock_tiers(&[TIERS[0]; 5]),
+        Err(ProgramError::InvalidAccountData)
+    );
+
+    // existing stakes keep their tier when the tiers are replaced
+    let staker = farm.staker();
+    farm.stake_with_tier(&staker, 2).unwrap();
+    farm.set_lock_tiers(&[]).unwrap();
+    assert_eq!(platform(&farm.platform_state).lock_tier(2), None);
+    assert_eq!(stake_record(&staker.stake_record).weight_bps, 20_000);
+}
+
+#[test]
+fn test_set_lock_tiers_requires_owner() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    farm.set_lock_tiers(&TIERS).unwrap();
+    let intruder = farm.staker();
+    assert_eq!(
+        process(
+            &farm.program_id,
+            &[intruder.user.clone(), farm.platform_state.clone()],
+            &farm::instruction::NftInstruction::SetLockTiers { tiers: vec![] }.pack(),
+        ),
+        Err(FarmError::InvalidAuthority.into())
+    );
+}
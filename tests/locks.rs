@@ -0,0 +1,117 @@
+mod common;
+
+use common::*;
+use farm::{error::FarmError, state::LockConfig};
+use solana_program::program_error::ProgramError;
+
+const DAY: i64 = 86_400;
+
+#[test]
+fn test_hard_lock_rejects_unstake_until_expiry() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig {
+            hard_lock_duration: 7 * DAY as u64,
+            ..LockConfig::default()
+        },
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+
+    advance_time(7 * DAY - 1);
+    assert_eq!(farm.unstake(&staker), Err(FarmError::StakeLocked.into()));
+    assert_eq!(token(&staker.nft_account).owner, *farm.pda.key);
+    farm.harvest(&staker).unwrap();
+    assert_eq!(paid(&staker), 0);
+
+    advance_time(1);
+    farm.unstake(&staker).unwrap();
+    assert_eq!(token(&staker.nft_account).owner, *staker.user.key);
+    assert_eq!(paid(&staker), 7 * DAY as u64 * RATE);
+}
+
+#[test]
+fn test_soft_lock_penalty_stays_in_vault() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig {
+            soft_lock_duration: 30 * DAY as u64,
+            early_unstake_penalty_bps: 2_500,
+            ..LockConfig::default()
+        },
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+
+    advance_time(10 * DAY);
+    farm.unstake(&staker).unwrap();
+    let earned = 10 * DAY as u64 * RATE;
+    assert_eq!(paid(&staker), earned * 3 / 4);
+    assert_eq!(
+        token(&farm.vault_reward).amount,
+        REWARD_SUPPLY - earned * 3 / 4
+    );
+}
+
+#[test]
+fn test_soft_lock_expires() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig {
+            soft_lock_duration: 30 * DAY as u64,
+            early_unstake_penalty_bps: 2_500,
+            ..LockConfig::default()
+        },
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+
+    // harvesting inside the lock keeps everything on the record
+    advance_time(29 * DAY);
+    farm.harvest(&staker).unwrap();
+    assert_eq!(paid(&staker), 0);
+    assert_eq!(
+        stake_record(&staker.stake_record).accrued_reward,
+        29 * DAY as u64 * RATE
+    );
+
+    advance_time(DAY);
+    farm.harvest(&staker).unwrap();
+    assert_eq!(paid(&staker), 30 * DAY as u64 * RATE);
+    advance_time(DAY);
+    farm.unstake(&staker).unwrap();
+    assert_eq!(paid(&staker), 31 * DAY as u64 * RATE);
+}
+
+#[test]
+fn test_no_lock_pays_immediately() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    advance_time(100);
+    farm.harvest(&staker).unwrap();
+    assert_eq!(paid(&staker), 100 * RATE);
+    advance_time(50);
+    farm.unstake(&staker).unwrap();
+    assert_eq!(paid(&staker), 150 * RATE);
+}
+
+#[test]
+fn test_penalty_above_one_hundred_percent_is_rejected() {
+    let farm = Farm::new();
+    let data = farm.init_data_with(PlatformOptions {
+        lock: LockConfig {
+            soft_lock_duration: DAY as u64,
+            early_unstake_penalty_bps: 10_001,
+            ..LockConfig::default()
+        },
+        ..PlatformOptions::default()
+    });
+    assert_eq!(
+        process(&farm.program_id, &farm.init_accounts(), &data),
+        Err(ProgramError::InvalidArgument)
+    );
+}
@@ -0,0 +1,155 @@
+mod common;
+
+use common::*;
+use farm::{error::FarmError, instruction::NftInstruction};
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+fn send(farm: &Farm, signer: &AccountInfo<'static>, instruction: NftInstruction) -> ProgramResult {
+    process(
+        &farm.program_id,
+        &[signer.clone(), farm.platform_state.clone()],
+        &instruction.pack(),
+    )
+}
+
+fn propose(farm: &Farm, new_owner: Pubkey) -> ProgramResult {
+    send(
+        farm,
+        &farm.owner,
+        NftInstruction::ProposeOwner { new_owner },
+    )
+}
+
+#[test]
+fn test_ownership_moves_once_accepted() {
+    let farm = Farm::with(PlatformOptions::default());
+    let successor = farm.staker().user;
+
+    propose(&farm, *successor.key).unwrap();
+    let state = platform(&farm.platform_state);
+    assert_eq!(state.owner, *farm.owner.key);
+    assert_eq!(state.pending_owner, *successor.key);
+
+    send(&farm, &successor, NftInstruction::AcceptOwner).unwrap();
+    let state = platform(&farm.platform_state);
+    assert_eq!(state.owner, *successor.key);
+    assert_eq!(state.pending_owner, Pubkey::default());
+
+    // only the new owner can configure the platform from here on
+    assert_eq!(
+        farm.set_paused(true),
+        Err(FarmError::InvalidAuthority.into())
+    );
+    send(
+        &farm,
+        &successor,
+        NftInstruction::SetPaused { paused: true },
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_accept_by_wrong_key_is_rejected() {
+    let farm = Farm::with(PlatformOptions::default());
+    let successor = farm.staker().user;
+    let stranger = farm.staker().user;
+    propose(&farm, *successor.key).unwrap();
+
+    assert_eq!(
+        send(&farm, &stranger, NftInstruction::AcceptOwner),
+        Err(FarmError::InvalidAuthority.into())
+    );
+    assert_eq!(
+        send(&farm, &farm.owner, NftInstruction::AcceptOwner),
+        Err(FarmError::InvalidAuthority.into())
+    );
+    assert_eq!(
+        send(
+            &farm,
+            &with_signer(&successor, false),
+            NftInstruction::AcceptOwner
+        ),
+        Err(ProgramError::MissingRequiredSignature)
+    );
+    assert_eq!(platform(&farm.platform_state).owner, *farm.owner.key);
+}
+
+#[test]
+fn test_only_owner_can_propose() {
+    let farm = Farm::with(PlatformOptions::default());
+    let stranger = farm.staker().user;
+
+    assert_eq!(
+        send(
+            &farm,
+            &stranger,
+            NftInstruction::ProposeOwner {
+                new_owner: *stranger.key
+            }
+        ),
+        Err(FarmError::InvalidAuthority.into())
+    );
+    assert_eq!(
+        platform(&farm.platform_state).pending_owner,
+        Pubkey::default()
+    );
+}
+
+#[test]
+fn test_cancelled_proposal_cannot_be_accepted() {
+    let farm = Farm::with(PlatformOptions::default());
+    let successor = farm.staker().user;
+    propose(&farm, *successor.key).unwrap();
+
+    propose(&farm, Pubkey::default()).unwrap();
+    assert_eq!(
+        send(&farm, &successor, NftInstruction::AcceptOwner),
+        Err(FarmError::InvalidAuthority.into())
+    );
+
+    // a newer proposal replaces the old one
+    let other = farm.staker().user;
+    propose(&farm, *successor.key).unwrap();
+    propose(&farm, *other.key).unwrap();
+    assert_eq!(
+        send(&farm, &successor, NftInstruction::AcceptOwner),
+        Err(FarmError::InvalidAuthority.into())
+    );
+    send(&farm, &other, NftInstruction::AcceptOwner).unwrap();
+    assert_eq!(platform(&farm.platform_state).owner, *other.key);
+}
+
+#[test]
+fn test_renounced_platform_has_no_owner() {
+    let farm = Farm::with(PlatformOptions::default());
+    let successor = farm.staker().user;
+    propose(&farm, *successor.key).unwrap();
+
+    send(&farm, &farm.owner, NftInstruction::RenounceOwnership).unwrap();
+    let state = platform(&farm.platform_state);
+    assert_eq!(state.owner, Pubkey::default());
+    assert_eq!(state.pending_owner, Pubkey::default());
+
+    assert_eq!(
+        send(&farm, &successor, NftInstruction::AcceptOwner),
+        Err(FarmError::InvalidAuthority.into())
+    );
+    assert_eq!(
+        farm.set_paused(true),
+        Err(FarmError::InvalidAuthority.into())
+    );
+    assert_eq!(
+        propose(&farm, *successor.key),
+        Err(FarmError::InvalidAuthority.into())
+    );
+
+    // not even the default key itself can act for a renounced platform
+    let nobody = with_signer(&program_account(Pubkey::default()), true);
+    assert_eq!(
+        send(&farm, &nobody, NftInstruction::SetPaused { paused: true }),
+        Err(FarmError::InvalidAuthority.into())
+    );
+}
@@ -0,0 +1,156 @@
+mod common;
+
+use common::*;
+use farm::{
+    error::FarmError,
+    instruction::NftInstruction,
+    state::{CustodyMode, LockConfig},
+};
+use solana_program::{program_error::ProgramError, program_option::COption, program_pack::Pack};
+use spl_token::state::{AccountState, Mint};
+
+const DAY: i64 = 86_400;
+
+#[test]
+fn test_paused_platform_rejects_stake_and_harvest() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let staked = farm.staker();
+    farm.stake(&staked).unwrap();
+    farm.set_paused(true).unwrap();
+    assert!(platform(&farm.platform_state).paused);
+
+    let late = farm.staker();
+    assert_eq!(farm.stake(&late), Err(FarmError::PlatformPaused.into()));
+    advance_time(DAY);
+    assert_eq!(farm.harvest(&staked), Err(FarmError::PlatformPaused.into()));
+
+    farm.set_paused(false).unwrap();
+    farm.stake(&late).unwrap();
+    farm.harvest(&staked).unwrap();
+}
+
+#[test]
+fn test_nothing_accrues_while_paused() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+
+    advance_time(DAY);
+    farm.set_paused(true).unwrap();
+    advance_time(3 * DAY);
+
+    // unstaking still works and pays what accrued before the pause
+    farm.unstake(&staker).unwrap();
+    assert_eq!(token(&staker.nft_account).owner, *staker.user.key);
+    assert_eq!(paid(&staker), DAY as u64 * RATE);
+    assert_eq!(platform(&farm.platform_state).total_owed(), Ok(0));
+}
+
+#[test]
+fn test_unpausing_resumes_accrual() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    farm.set_paused(true).unwrap();
+    advance_time(DAY);
+    farm.set_paused(false).unwrap();
+    advance_time(2 * DAY);
+
+    farm.harvest(&staker).unwrap();
+    assert_eq!(paid(&staker), 2 * DAY as u64 * RATE);
+}
+
+#[test]
+fn test_emergency_unstake_forfeits_rewards_and_ignores_locks() {
+    for custody_mode in [
+        CustodyMode::Authority,
+        CustodyMode::Escrow,
+        CustodyMode::Frozen,
+    ] {
+        let farm = Farm::with(PlatformOptions {
+            custody_mode,
+            lock: LockConfig {
+                hard_lock_duration: 30 * DAY as u64,
+                ..LockConfig::default()
+            },
+            ..PlatformOptions::default()
+        });
+        let staker = farm.staker();
+        let other = farm.staker();
+        if custody_mode == CustodyMode::Frozen {
+            for info in [&staker.nft_mint, &other.nft_mint] {
+                let mut mint = Mint::unpack(&info.data.borrow()).unwrap();
+                mint.freeze_authority = COption::Some(*farm.pda.key);
+                Mint::pack(mint, &mut info.data.borrow_mut()).unwrap();
+            }
+        }
+        farm.stake(&staker).unwrap();
+        farm.stake(&other).unwrap();
+        advance_time(DAY);
+        farm.set_paused(true).unwrap();
+
+        farm.emergency_unstake(&staker).unwrap();
+        let nft = token(&staker.nft_account);
+        assert_eq!(nft.owner, *staker.user.key);
+        assert_eq!(nft.amount, 1);
+        assert_eq!(nft.state, AccountState::Initialized);
+        assert_eq!(paid(&staker), 0);
+        assert_eq!(staker.stake_record.lamports(), 0);
+        assert_eq!(user_state(&staker.user_state).total_staked_nft, 0);
+
+        // only the remaining staker is owed anything
+        let state = platform(&farm.platform_state);
+        assert_eq!(state.total_staked_nft, 1);
+        assert_eq!(state.total_weight_bps, 10_000);
+        assert_eq!(state.total_owed(), Ok(DAY as u64 * RATE));
+    }
+}
+
+#[test]
+fn test_emergency_unstake_requires_pause() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+
+    assert_eq!(
+        farm.emergency_unstake(&staker),
+        Err(FarmError::PlatformNotPaused.into())
+    );
+    assert_eq!(token(&staker.nft_account).owner, *farm.pda.key);
+}
+
+#[test]
+fn test_only_owner_can_pause() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+
+    let result = process(
+        &farm.program_id,
+        &[staker.user.clone(), farm.platform_state.clone()],
+        &NftInstruction::SetPaused { paused: true }.pack(),
+    );
+    assert_eq!(result, Err(FarmError::InvalidAuthority.into()));
+
+    let result = process(
+        &farm.program_id,
+        &[with_signer(&farm.owner, false), farm.platform_state.clone()],
+        &NftInstruction::SetPaused { paused: true }.pack(),
+    );
+    assert_eq!(result, Err(ProgramError::MissingRequiredSignature));
+    assert!(!platform(&farm.platform_state).paused);
+}
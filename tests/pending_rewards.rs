@@ -0,0 +1,212 @@
+mod common;
+
+use common::*;
+use farm::{
+    error::FarmError,
+    instruction::{NftInstruction, PendingRewards},
+    state::{LockConfig, LockTier, RewardMode},
+};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+const DAY: i64 = 86_400;
+
+#[test]
+fn test_query_matches_what_harvest_pays() {
+    for reward_mode in [RewardMode::PerNft, RewardMode::Emission] {
+        let farm = Farm::with(PlatformOptions {
+            reward_mode,
+            lock: LockConfig::default(),
+            ..PlatformOptions::default()
+        });
+        let staker = farm.staker();
+        let other = farm.staker();
+        farm.stake(&staker).unwrap();
+        advance_time(DAY / 3);
+        farm.stake(&other).unwrap();
+        advance_time(DAY + 7);
+
+        let rewards = farm.query_pending_rewards(&[&staker]).unwrap();
+        assert!(rewards.pending > 0);
+        assert_eq!(rewards.claimable, rewards.pending);
+
+        farm.harvest(&staker).unwrap();
+        assert_eq!(paid(&staker), rewards.pending);
+        assert_eq!(
+            farm.query_pending_rewards(&[&staker]),
+            Ok(PendingRewards::default())
+        );
+    }
+}
+
+#[test]
+fn test_query_writes_nothing() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    advance_time(DAY);
+
+    let platform_before = farm.platform_state.data.borrow().to_vec();
+    let record_before = staker.stake_record.data.borrow().to_vec();
+    farm.query_pending_rewards(&[&staker]).unwrap();
+    assert_eq!(*farm.platform_state.data.borrow(), platform_before);
+    assert_eq!(*staker.stake_record.data.borrow(), record_before);
+
+    // read-only accounts are enough
+    let accounts = farm
+        .query_pending_rewards_accounts(&[&staker])
+        .iter()
+        .map(|info| with_writable(info, false))
+        .collect::<Vec<_>>();
+    process(
+        &farm.program_id,
+        &accounts,
+        &NftInstruction::QueryPendingRewards.pack(),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_query_sums_records() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let first = farm.staker();
+    let second = farm.another_nft(&first);
+    farm.stake(&first).unwrap();
+    advance_time(DAY);
+    farm.stake(&second).unwrap();
+    advance_time(DAY);
+
+    let earned = 3 * DAY as u64 * RATE;
+    assert_eq!(
+        farm.query_pending_rewards(&[&first, &second]),
+        Ok(PendingRewards {
+            pending: earned,
+            claimable: earned
+        })
+    );
+}
+
+#[test]
+fn test_query_rejects_duplicate_records() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let first = farm.staker();
+    let second = farm.another_nft(&first);
+    farm.stake(&first).unwrap();
+    farm.stake(&second).unwrap();
+    advance_time(DAY);
+
+    take_return_data();
+    assert_eq!(
+        farm.query_pending_rewards(&[&first, &second, &first]),
+        Err(FarmError::DuplicateAccount.into())
+    );
+    assert_eq!(take_return_data(), None);
+}
+
+#[test]
+fn test_locked_rewards_are_pending_not_claimable() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig {
+            soft_lock_duration: 30 * DAY as u64,
+            early_unstake_penalty_bps: 5_000,
+            ..LockConfig::default()
+        },
+        ..PlatformOptions::default()
+    });
+    farm.set_lock_tiers(&[LockTier {
+        duration: 60 * DAY as u64,
+        multiplier_bps: 10_000,
+    }])
+    .unwrap();
+    let soft = farm.staker();
+    let hard = farm.staker();
+    farm.stake(&soft).unwrap();
+    farm.stake_with_tier(&hard, 1).unwrap();
+
+    advance_time(DAY);
+    let earned = DAY as u64 * RATE;
+    for staker in [&soft, &hard] {
+        assert_eq!(
+            farm.query_pending_rewards(&[staker]),
+            Ok(PendingRewards {
+                pending: earned,
+                claimable: 0
+            })
+        );
+    }
+
+    advance_time(30 * DAY);
+    assert_eq!(
+        farm.query_pending_rewards(&[&soft, &hard]),
+        Ok(PendingRewards {
+            pending: 2 * 31 * earned,
+            claimable: 31 * earned
+        })
+    );
+}
+
+#[test]
+fn test_nothing_is_claimable_while_paused() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    advance_time(DAY);
+    farm.set_paused(true).unwrap();
+    advance_time(DAY);
+
+    assert_eq!(
+        farm.query_pending_rewards(&[&staker]),
+        Ok(PendingRewards {
+            pending: DAY as u64 * RATE,
+            claimable: 0
+        })
+    );
+}
+
+#[test]
+fn test_query_rejects_foreign_records() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let other_farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let staker = other_farm.staker();
+    other_farm.stake(&staker).unwrap();
+
+    // a record of another platform, even one owned by this program
+    let foreign = with_owner(&staker.stake_record, farm.program_id);
+    let accounts = [farm.platform_state.clone(), foreign];
+    assert_eq!(
+        process(
+            &farm.program_id,
+            &accounts,
+            &NftInstruction::QueryPendingRewards.pack()
+        ),
+        Err(ProgramError::InvalidAccountData)
+    );
+
+    let not_a_record = with_owner(&staker.stake_record, Pubkey::new_unique());
+    let accounts = [other_farm.platform_state.clone(), not_a_record];
+    assert_eq!(
+        process(
+            &other_farm.program_id,
+            &accounts,
+            &NftInstruction::QueryPendingRewards.pack()
+        ),
+        Err(FarmError::InvalidOwner.into())
+    );
+}
@@ -0,0 +1,213 @@
+mod common;
+
+use common::*;
+use farm::{
+    error::FarmError,
+    instruction::{NftInstruction, RarityEntry, MAX_RARITY_ENTRIES},
+    state::{LockConfig, LockTier, Rarity, RewardMode},
+};
+use solana_program::{program_error::ProgramError, program_pack::Pack, rent::Rent};
+
+const DAY: i64 = 86_400;
+
+#[test]
+fn test_set_rarity_creates_and_updates_in_bulk() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let rare = farm.staker();
+    let legendary = farm.staker();
+    farm.set_rarity(&[(&rare, 15_000), (&legendary, 30_000)])
+        .unwrap();
+
+    let rarity = Rarity::unpack(&rare.rarity.data.borrow()).unwrap();
+    assert_eq!(rarity.platform, *farm.platform_state.key);
+    assert_eq!(rarity.mint, *rare.nft_mint.key);
+    assert_eq!(rarity.multiplier_bps, 15_000);
+    assert_eq!(*rare.rarity.owner, farm.program_id);
+
+    farm.set_rarity(&[(&legendary, 25_000)]).unwrap();
+    let rarity = Rarity::unpack(&legendary.rarity.data.borrow()).unwrap();
+    assert_eq!(rarity.multiplier_bps, 25_000);
+}
+
+#[test]
+fn test_set_rarity_on_a_prefunded_address() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let rare = farm.staker();
+    // lamports anyone can send to the rarity address before the owner sets it
+    **rare.rarity.lamports.borrow_mut() = 1;
+    farm.set_rarity(&[(&rare, 15_000)]).unwrap();
+
+    assert_eq!(*rare.rarity.owner, farm.program_id);
+    assert_eq!(
+        rare.rarity.lamports(),
+        Rent::default().minimum_balance(Rarity::LEN)
+    );
+    let rarity = Rarity::unpack(&rare.rarity.data.borrow()).unwrap();
+    assert_eq!(rarity.multiplier_bps, 15_000);
+}
+
+#[test]
+fn test_rarity_multiplies_rewards() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let floor = farm.staker();
+    let rare = farm.staker();
+    farm.set_rarity(&[(&rare, 25_000)]).unwrap();
+    farm.stake(&floor).unwrap();
+    farm.stake(&rare).unwrap();
+    assert_eq!(stake_record(&rare.stake_record).weight_bps, 25_000);
+
+    advance_time(DAY);
+    farm.harvest(&floor).unwrap();
+    farm.harvest(&rare).unwrap();
+    assert_eq!(paid(&floor), DAY as u64 * RATE);
+    assert_eq!(paid(&rare), DAY as u64 * RATE * 5 / 2);
+}
+
+#[test]
+fn test_rarity_stacks_with_lock_tier() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    farm.set_lock_tiers(&[LockTier {
+        duration: DAY as u64,
+        multiplier_bps: 15_000,
+    }])
+    .unwrap();
+    let staker = farm.staker();
+    farm.set_rarity(&[(&staker, 20_000)]).unwrap();
+    farm.stake_with_tier(&staker, 1).unwrap();
+
+    assert_eq!(stake_record(&staker.stake_record).weight_bps, 30_000);
+    advance_time(DAY);
+    farm.harvest(&staker).unwrap();
+    assert_eq!(paid(&staker), DAY as u64 * RATE * 3);
+}
+
+#[test]
+fn test_rarity_set_while_staked_applies_from_next_harvest() {
+    let farm = Farm::with(PlatformOptions {
+        reward_mode: RewardMode::Emission,
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let floor = farm.staker();
+    let rare = farm.staker();
+    farm.stake(&floor).unwrap();
+    farm.stake(&rare).unwrap();
+
+    advance_time(DAY);
+    farm.set_rarity(&[(&rare, 30_000)]).unwrap();
+    // the day before the harvest is still split evenly
+    farm.harvest(&rare).unwrap();
+    let emitted = DAY as u64 * RATE;
+    assert_eq!(paid(&rare), emitted / 2);
+    assert_eq!(platform(&farm.platform_state).total_weight_bps, 40_000);
+
+    advance_time(DAY);
+    farm.harvest(&floor).unwrap();
+    farm.harvest(&rare).unwrap();
+    assert_eq!(paid(&floor), emitted / 2 + emitted / 4);
+    assert_eq!(paid(&rare), emitted / 2 + emitted * 3 / 4);
+
+    farm.unstake(&floor).unwrap();
+    farm.unstake(&rare).unwrap();
+    assert_eq!(platform(&farm.platform_state).total_weight_bps, 0);
+}
+
+#[test]
+fn test_rarity_account_must_match_mint() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    let other = farm.staker();
+    let mut accounts = farm.stake_accounts(&staker);
+    accounts[10] = other.rarity.clone();
+    assert_eq!(
+        process(
+            &farm.program_id,
+            &accounts,
+            &NftInstruction::StakeNft {
+                lock_tier: 0,
+                rarity: None,
+            }
+            .pack()
+        ),
+        Err(ProgramError::InvalidAccountData)
+    );
+
+    // a rarity set for another mint can't stand in for this one at harvest either
+    farm.set_rarity(&[(&other, 30_000)]).unwrap();
+    farm.stake(&staker).unwrap();
+    let mut accounts = farm.harvest_accounts(&staker);
+    accounts[7] = other.rarity.clone();
+    assert!(matches!(
+        process(&farm.program_id, &accounts, &NftInstruction::Harvest.pack()),
+        Err(ProgramError::InvalidAccountData) | Err(ProgramError::InvalidSeeds)
+    ));
+}
+
+#[test]
+fn test_set_rarity_validation() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    assert_eq!(
+        farm.set_rarity(&[(&staker, 0)]),
+        Err(ProgramError::InvalidArgument)
+    );
+
+    let entries = vec![
+        RarityEntry {
+            mint: *staker.nft_mint.key,
+            multiplier_bps: 20_000,
+        };
+        MAX_RARITY_ENTRIES + 1
+    ];
+    assert_eq!(
+        process(
+            &farm.program_id,
+            &[
+                farm.owner.clone(),
+                farm.platform_state.clone(),
+                farm.system_program.clone()
+            ],
+            &NftInstruction::SetRarity { entries }.pack(),
+        ),
+        Err(ProgramError::InvalidAccountData)
+    );
+
+    let intruder = farm.staker();
+    assert_eq!(
+        process(
+            &farm.program_id,
+            &[
+                intruder.user.clone(),
+                farm.platform_state.clone(),
+                farm.system_program.clone(),
+                staker.rarity.clone(),
+            ],
+            &NftInstruction::SetRarity {
+                entries: vec![RarityEntry {
+                    mint: *staker.nft_mint.key,
+                    multiplier_bps: 20_000,
+                }],
+            }
+            .pack(),
+        ),
+        Err(FarmError::InvalidAuthority.into())
+    );
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use common::*;
+use farm::{
+    error::FarmError,
+    instruction::{RarityEntry, RarityProof},
+    merkle::RarityTree,
+    state::LockConfig,
+};
+
+const DAY: i64 = 86_400;
+
+fn tree(rarities: &[(&Staker, u16)]) -> RarityTree {
+    let mut entries = rarities
+        .iter()
+        .map(|(staker, multiplier_bps)| RarityEntry {
+            mint: *staker.nft_mint.key,
+            multiplier_bps: *multiplier_bps,
+        })
+        .collect::<Vec<_>>();
+    // mints nobody here stakes, so the proofs have some depth
+    entries.extend((0..5).map(|_| RarityEntry {
+        mint: solana_program::pubkey::Pubkey::new_unique(),
+        multiplier_bps: 10_000,
+    }));
+    RarityTree::new(entries).unwrap()
+}
+
+#[test]
+fn test_proven_rarity_is_cached_on_the_record() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    let tree = tree(&[(&staker, 20_000)]);
+    farm.set_rarity_root(tree.root()).unwrap();
+
+    farm.stake_with_proof(&staker, 0, tree.proof(staker.nft_mint.key))
+        .unwrap();
+    let record = stake_record(&staker.stake_record);
+    assert!(record.rarity_proven);
+    assert_eq!(record.rarity_bps, 20_000);
+    assert_eq!(record.weight_bps, 20_000);
+
+    // a rarity account set later doesn't override the proven multiplier
+    farm.set_rarity(&[(&staker, 30_000)]).unwrap();
+    advance_time(DAY);
+    farm.harvest(&staker).unwrap();
+    assert_eq!(token(&staker.reward_account).amount, DAY as u64 * RATE * 2);
+    assert_eq!(stake_record(&staker.stake_record).weight_bps, 20_000);
+}
+
+#[test]
+fn test_invalid_proofs_are_rejected() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    let other = farm.staker();
+    let tree = tree(&[(&staker, 20_000), (&other, 15_000)]);
+
+    // no root set yet
+    let proof = tree.proof(staker.nft_mint.key);
+    assert_eq!(
+        farm.stake_with_proof(&staker, 0, proof.clone()),
+        Err(FarmError::InvalidRarityProof.into())
+    );
+
+    farm.set_rarity_root(tree.root()).unwrap();
+    let inflated = proof.clone().map(|proof| RarityProof {
+        multiplier_bps: 40_000,
+        ..proof
+    });
+    assert_eq!(
+        farm.stake_with_proof(&staker, 0, inflated),
+        Err(FarmError::InvalidRarityProof.into())
+    );
+    assert_eq!(
+        farm.stake_with_proof(&staker, 0, tree.proof(other.nft_mint.key)),
+        Err(FarmError::InvalidRarityProof.into())
+    );
+
+    farm.stake_with_proof(&staker, 0, proof).unwrap();
+}
+
+#[test]
+fn test_root_change_keeps_existing_stakes() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    let tree = tree(&[(&staker, 20_000)]);
+    farm.set_rarity_root(tree.root()).unwrap();
+    farm.stake_with_proof(&staker, 0, tree.proof(staker.nft_mint.key))
+        .unwrap();
+
+    farm.set_rarity_root([0; 32]).unwrap();
+    advance_time(DAY);
+    farm.harvest(&staker).unwrap();
+    assert_eq!(token(&staker.reward_account).amount, DAY as u64 * RATE * 2);
+}
@@ -0,0 +1,155 @@
+mod common;
+
+use common::*;
+use farm::{
+    error::FarmError,
+    instruction::NftInstruction,
+    state::{CustodyMode, LockConfig, RewardMode},
+};
+use solana_program::pubkey::Pubkey;
+
+const DAY: i64 = 86_400;
+const FUNDS: u64 = REWARD_SUPPLY;
+
+#[test]
+fn test_anyone_can_top_up() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let depositor = farm.staker();
+    let source = token_account(
+        Pubkey::new_unique(),
+        &farm.reward_mint,
+        depositor.user.key,
+        500,
+    );
+
+    farm.top_up(&depositor.user, &source, 500).unwrap();
+    assert_eq!(token(&farm.vault_reward).amount, FUNDS + 500);
+    assert_eq!(token(&source).amount, 0);
+    assert_eq!(platform(&farm.platform_state).total_deposited, FUNDS + 500);
+}
+
+#[test]
+fn test_withdraw_keeps_what_is_owed() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    advance_time(DAY);
+
+    let owed = DAY as u64 * RATE;
+    assert_eq!(
+        farm.withdraw(FUNDS - owed + 1),
+        Err(FarmError::RewardsOwed.into())
+    );
+    farm.withdraw(FUNDS - owed).unwrap();
+    assert_eq!(token(&farm.admin_reward).amount, FUNDS - owed);
+
+    farm.harvest(&staker).unwrap();
+    assert_eq!(token(&staker.reward_account).amount, owed);
+    assert_eq!(token(&farm.vault_reward).amount, 0);
+    let platform = platform(&farm.platform_state);
+    assert_eq!(platform.total_deposited, FUNDS);
+    assert_eq!(platform.total_withdrawn, FUNDS - owed);
+    assert_eq!(platform.total_paid, owed);
+    assert_eq!(platform.total_owed().unwrap(), 0);
+}
+
+#[test]
+fn test_forfeited_rewards_can_be_withdrawn() {
+    // the default options forfeit everything unstaked within a day
+    let farm = Farm::with(PlatformOptions::default());
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    advance_time(DAY / 2);
+    assert_eq!(farm.withdraw(FUNDS), Err(FarmError::RewardsOwed.into()));
+
+    farm.unstake(&staker).unwrap();
+    assert_eq!(platform(&farm.platform_state).total_owed().unwrap(), 0);
+    farm.withdraw(FUNDS).unwrap();
+    assert_eq!(token(&farm.admin_reward).amount, FUNDS);
+}
+
+#[test]
+fn test_owed_is_exact_across_weighted_stakes() {
+    let farm = Farm::with(PlatformOptions {
+        reward_mode: RewardMode::Emission,
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let stakers = [farm.staker(), farm.staker(), farm.staker()];
+    farm.set_rarity(&[(&stakers[1], 15_000)]).unwrap();
+    for staker in &stakers {
+        farm.stake(staker).unwrap();
+        advance_time(7);
+    }
+
+    for staker in &stakers {
+        farm.harvest(staker).unwrap();
+    }
+    let paid: u64 = stakers
+        .iter()
+        .map(|staker| token(&staker.reward_account).amount)
+        .sum();
+    let platform = platform(&farm.platform_state);
+    assert_eq!(platform.total_paid, paid);
+    // the harvests settled everything, rounding dust included
+    assert_eq!(platform.total_accrued, 0);
+    assert_eq!(platform.unsettled_reward, 0);
+    assert!(paid <= 3 * 7 * RATE);
+}
+
+#[test]
+fn test_withdraw_requires_owner() {
+    let farm = Farm::with(PlatformOptions {
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let mut accounts = farm.withdraw_accounts();
+    accounts[0] = farm.staker().user;
+    assert_eq!(
+        process(
+            &farm.program_id,
+            &accounts,
+            &NftInstruction::WithdrawRewards { amount: 1 }.pack()
+        ),
+        Err(FarmError::InvalidAuthority.into())
+    );
+}
+
+#[test]
+fn test_escrow_cannot_stand_in_for_the_reward_vault() {
+    let farm = Farm::with(PlatformOptions {
+        custody_mode: CustodyMode::Escrow,
+        lock: LockConfig::default(),
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    advance_time(DAY);
+
+    let mut accounts = farm.withdraw_accounts();
+    accounts[2] = staker.escrow.clone();
+    assert_eq!(
+        process(
+            &farm.program_id,
+            &accounts,
+            &NftInstruction::WithdrawRewards { amount: 1 }.pack()
+        ),
+        Err(FarmError::InvalidRewardVault.into())
+    );
+
+    let thief = farm.staker();
+    let mut accounts = farm.harvest_accounts(&staker);
+    accounts[3] = thief.reward_account.clone();
+    accounts[4] = staker.escrow.clone();
+    assert_eq!(
+        process(&farm.program_id, &accounts, &NftInstruction::Harvest.pack()),
+        Err(FarmError::InvalidRewardVault.into())
+    );
+    assert_eq!(token(&staker.escrow).amount, 1);
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use common::*;
+use farm::error::FarmError;
+use solana_program::program_error::ProgramError;
+
+const DAY: i64 = 86_400;
+
+#[test]
+fn test_rate_change_settles_at_the_old_rate() {
+    let (old_rate, new_rate) = (10, 25);
+    let (t1, t2) = (3 * DAY, DAY / 2 + 17);
+    let farm = Farm::with(PlatformOptions {
+        reward_rate: old_rate,
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    advance_time(t1);
+    farm.update_reward_rate(new_rate).unwrap();
+    assert_eq!(platform(&farm.platform_state).reward_rate, new_rate);
+    advance_time(t2);
+    farm.harvest(&staker).unwrap();
+
+    assert_eq!(
+        token(&staker.reward_account).amount,
+        old_rate * t1 as u64 + new_rate * t2 as u64
+    );
+}
+
+#[test]
+fn test_rate_can_drop_to_zero() {
+    let farm = Farm::with(PlatformOptions::default());
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    advance_time(2 * DAY);
+    farm.update_reward_rate(0).unwrap();
+    advance_time(5 * DAY);
+    farm.harvest(&staker).unwrap();
+    assert_eq!(token(&staker.reward_account).amount, 2 * DAY as u64 * 10);
+}
+
+#[test]
+fn test_only_the_owner_updates_the_rate() {
+    let farm = Farm::with(PlatformOptions::default());
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    let accounts = [staker.user.clone(), farm.platform_state.clone()];
+    assert_eq!(
+        process(
+            &farm.program_id,
+            &accounts,
+            &farm::instruction::NftInstruction::UpdateRewardRate { reward_rate: 1_000 }.pack(),
+        ),
+        Err(ProgramError::from(FarmError::InvalidAuthority))
+    );
+    assert_eq!(platform(&farm.platform_state).reward_rate, 10);
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use common::*;
+use farm::{error::FarmError, state::CustodyMode};
+use solana_program::{
+    account_info::AccountInfo, program_error::ProgramError, program_option::COption,
+    program_pack::Pack, pubkey::Pubkey,
+};
+use spl_token::{
+    error::TokenError,
+    state::{AccountState, Mint},
+};
+
+fn set_freeze_authority(mint: &AccountInfo, freeze_authority: Pubkey) {
+    let mut data = Mint::unpack(&mint.data.borrow()).unwrap();
+    data.freeze_authority = COption::Some(freeze_authority);
+    Mint::pack(data, &mut mint.data.borrow_mut()).unwrap();
+}
+
+/// Tries to move the staked NFT out of the user's wallet.
+fn try_transfer(staker: &Staker) -> Result<(), ProgramError> {
+    let destination = token_account(
+        Pubkey::new_unique(),
+        staker.nft_mint.key,
+        &Pubkey::new_unique(),
+        0,
+    );
+    spl_token::processor::Processor::process(
+        &spl_token::id(),
+        &[staker.nft_account.clone(), destination, staker.user.clone()],
+        &spl_token::instruction::TokenInstruction::Transfer { amount: 1 }.pack(),
+    )
+}
+
+fn assert_soft_staking_round_trip(farm: &Farm, staker: &Staker) {
+    farm.stake(staker).unwrap();
+    let account = token(&staker.nft_account);
+    assert_eq!(account.owner, *staker.user.key);
+    assert_eq!(account.amount, 1);
+    assert_eq!(account.state, AccountState::Frozen);
+    assert_eq!(account.delegate, COption::Some(*farm.pda.key));
+    assert_eq!(try_transfer(staker), Err(TokenError::AccountFrozen.into()));
+
+    advance_time(2 * 86_400);
+    farm.harvest(staker).unwrap();
+    advance_time(86_400);
+    farm.unstake(staker).unwrap();
+    let account = token(&staker.nft_account);
+    assert_eq!(account.state, AccountState::Initialized);
+    assert_eq!(account.delegate, COption::None);
+    assert_eq!(account.amount, 1);
+    // rewards accrue exactly as they do for custodial staking
+    assert_eq!(token(&staker.reward_account).amount, 3 * 86_400 * 10);
+    try_transfer(staker).unwrap();
+}
+
+#[test]
+fn test_soft_staking_with_platform_freeze_authority() {
+    let farm = Farm::with(PlatformOptions {
+        custody_mode: CustodyMode::Frozen,
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    set_freeze_authority(&staker.nft_mint, *farm.pda.key);
+    assert_soft_staking_round_trip(&farm, &staker);
+}
+
+#[test]
+fn test_soft_staking_through_edition_delegate() {
+    let farm = Farm::with(PlatformOptions {
+        custody_mode: CustodyMode::Frozen,
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    set_freeze_authority(&staker.nft_mint, *staker.edition.key);
+    assert_soft_staking_round_trip(&farm, &staker);
+}
+
+#[test]
+fn test_soft_staking_needs_a_usable_freeze_authority() {
+    let farm = Farm::with(PlatformOptions {
+        custody_mode: CustodyMode::Frozen,
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    set_freeze_authority(&staker.nft_mint, Pubkey::new_unique());
+    assert_eq!(farm.stake(&staker), Err(FarmError::CannotFreeze.into()));
+
+    let staker = farm.staker();
+    assert_eq!(farm.stake(&staker), Err(FarmError::CannotFreeze.into()));
+}
+
+#[test]
+fn test_soft_unstake_requires_platform_delegate() {
+    let farm = Farm::with(PlatformOptions {
+        custody_mode: CustodyMode::Frozen,
+        ..PlatformOptions::default()
+    });
+    let staker = farm.staker();
+    set_freeze_authority(&staker.nft_mint, *farm.pda.key);
+    farm.stake(&staker).unwrap();
+
+    let mut account = token(&staker.nft_account);
+    account.delegate = COption::Some(Pubkey::new_unique());
+    spl_token::state::Account::pack(account, &mut staker.nft_account.data.borrow_mut()).unwrap();
+    assert_eq!(
+        farm.unstake(&staker),
+        Err(FarmError::InvalidTokenAccountOwner.into())
+    );
+}
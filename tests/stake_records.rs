@@ -0,0 +1,166 @@
+mod common;
+
+use common::*;
+use farm::{
+    error::FarmError,
+    state::{find_stake_record_address, StakeRecord, UserState},
+};
+use solana_program::{
+    account_info::AccountInfo, program_error::ProgramError, program_pack::Pack, pubkey::Pubkey,
+    rent::Rent,
+};
+
+/// Sends lamports to an address the way anyone could before it is created.
+fn fund(info: &AccountInfo, lamports: u64) {
+    **info.lamports.borrow_mut() = lamports;
+}
+
+#[test]
+fn test_each_nft_gets_its_own_record() {
+    let farm = Farm::with(PlatformOptions::default());
+    let first = farm.staker();
+    let second = farm.another_nft(&first);
+    farm.stake(&first).unwrap();
+    advance_time(60);
+    farm.stake(&second).unwrap();
+
+    for (nft, stake_time) in [(&first, now() - 60), (&second, now())] {
+        let (address, bump) =
+            find_stake_record_address(&farm.program_id, farm.platform_state.key, nft.nft_mint.key);
+        assert_eq!(*nft.stake_record.key, address);
+        let record = stake_record(&nft.stake_record);
+        assert_eq!(record.platform, *farm.platform_state.key);
+        assert_eq!(record.staker, *first.user.key);
+        assert_eq!(record.mint, *nft.nft_mint.key);
+        assert_eq!(record.stake_time, stake_time);
+        assert_eq!(record.last_claim_time, stake_time);
+        assert_eq!(record.accrued_reward, 0);
+        assert_eq!(record.bump, bump);
+    }
+    assert_eq!(user_state(&first.user_state).total_staked_nft, 2);
+    assert_eq!(platform(&farm.platform_state).total_staked_nft, 2);
+
+    advance_time(2 * 86_400);
+    farm.unstake(&first).unwrap();
+    assert_eq!(first.stake_record.lamports(), 0);
+    assert_eq!(user_state(&second.user_state).total_staked_nft, 1);
+    assert_eq!(platform(&farm.platform_state).total_staked_nft, 1);
+    assert_eq!(
+        stake_record(&second.stake_record).mint,
+        *second.nft_mint.key
+    );
+}
+
+#[test]
+fn test_stake_record_must_be_the_mint_pda() {
+    let farm = Farm::with(PlatformOptions::default());
+    let staker = farm.staker();
+    let other = farm.staker();
+    for record in [
+        other.stake_record.clone(),
+        empty_account(Pubkey::new_unique(), StakeRecord::LEN),
+    ] {
+        let wrong_record = Staker {
+            stake_record: record,
+            ..farm.staker()
+        };
+        assert_eq!(
+            farm.stake(&wrong_record),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+    farm.stake(&staker).unwrap();
+}
+
+#[test]
+fn test_staked_mint_cannot_be_staked_again() {
+    let farm = Farm::with(PlatformOptions::default());
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+
+    // another wallet claiming to hold the same mint finds the record taken
+    let holder = farm.staker();
+    let copy = Staker {
+        nft_mint: staker.nft_mint.clone(),
+        nft_account: token_account(
+            Pubkey::new_unique(),
+            staker.nft_mint.key,
+            holder.user.key,
+            1,
+        ),
+        metadata: staker.metadata.clone(),
+        stake_record: staker.stake_record.clone(),
+        rarity: staker.rarity.clone(),
+        ..holder
+    };
+    assert_eq!(farm.stake(&copy), Err(FarmError::AlreadyInUse.into()));
+    assert_eq!(stake_record(&staker.stake_record).staker, *staker.user.key);
+}
+
+#[test]
+fn test_records_only_serve_their_staker() {
+    let farm = Farm::with(PlatformOptions::default());
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    advance_time(2 * 86_400);
+
+    let thief = farm.staker();
+    farm.init_user(&thief).unwrap();
+    let with_foreign_record = Staker {
+        stake_record: staker.stake_record.clone(),
+        ..thief
+    };
+    assert_eq!(
+        farm.harvest(&with_foreign_record),
+        Err(FarmError::InvalidAuthority.into())
+    );
+    assert_eq!(
+        farm.unstake(&with_foreign_record),
+        Err(FarmError::InvalidAuthority.into())
+    );
+    assert_eq!(token(&with_foreign_record.reward_account).amount, 0);
+}
+
+#[test]
+fn test_stake_succeeds_on_prefunded_addresses() {
+    let farm = Farm::with(PlatformOptions::default());
+    let record_rent = Rent::default().minimum_balance(StakeRecord::LEN);
+    let user_state_rent = Rent::default().minimum_balance(UserState::LEN);
+    for (record_lamports, user_state_lamports) in [(1, 1), (record_rent + 5, user_state_rent)] {
+        let staker = farm.staker();
+        fund(&staker.stake_record, record_lamports);
+        fund(&staker.user_state, user_state_lamports);
+        let wallet = staker.user.lamports();
+
+        farm.stake(&staker).unwrap();
+
+        assert_eq!(*staker.stake_record.owner, farm.program_id);
+        assert_eq!(*staker.user_state.owner, farm.program_id);
+        assert_eq!(
+            stake_record(&staker.stake_record).mint,
+            *staker.nft_mint.key
+        );
+        assert_eq!(user_state(&staker.user_state).total_staked_nft, 1);
+        assert_eq!(
+            staker.stake_record.lamports(),
+            record_rent.max(record_lamports)
+        );
+        assert_eq!(staker.user_state.lamports(), user_state_rent);
+        // the wallet only pays what the donations left short of rent exemption
+        assert_eq!(
+            wallet - staker.user.lamports(),
+            record_rent.saturating_sub(record_lamports)
+                + user_state_rent.saturating_sub(user_state_lamports)
+        );
+    }
+}
+
+#[test]
+fn test_initialize_user_succeeds_on_a_prefunded_address() {
+    let farm = Farm::with(PlatformOptions::default());
+    let staker = farm.staker();
+    fund(&staker.user_state, 1);
+    farm.init_user(&staker).unwrap();
+    assert_eq!(*staker.user_state.owner, farm.program_id);
+    assert_eq!(user_state(&staker.user_state).user, *staker.user.key);
+}
@@ -0,0 +1,91 @@
+//! PDA checks of stake, unstake and harvest against the bumps stored at creation.
+//!
+//! A handler that searched for a bump would find the right address whatever is stored, so
+//! a corrupted stored bump failing the check shows the handler derives from it instead.
+
+mod common;
+
+use common::*;
+use farm::state::{
+    find_rarity_address, find_stake_record_address, find_user_state_address, find_vault_address,
+    PlatForm, StakeRecord, UserState,
+};
+use solana_program::{account_info::AccountInfo, program_error::ProgramError, program_pack::Pack};
+
+fn edit<T: Pack>(info: &AccountInfo, f: impl FnOnce(&mut T)) {
+    let mut state = T::unpack_unchecked(&info.data.borrow()).unwrap();
+    f(&mut state);
+    state.pack_into_slice(&mut info.data.borrow_mut());
+}
+
+fn assert_bad_pda(result: Result<(), ProgramError>) {
+    assert!(
+        matches!(
+            result,
+            Err(ProgramError::InvalidAccountData) | Err(ProgramError::InvalidSeeds)
+        ),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+fn test_handlers_derive_from_stored_bumps() {
+    let farm = Farm::with(PlatformOptions::default());
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    let platform_data = platform(&farm.platform_state);
+    let (_, vault_bump) = find_vault_address(&farm.program_id, farm.platform_state.key);
+    assert_eq!(platform_data.vault_bump, vault_bump);
+    let (_, user_bump) =
+        find_user_state_address(&farm.program_id, farm.platform_state.key, staker.user.key);
+    assert_eq!(user_state(&staker.user_state).bump, user_bump);
+    let (_, record_bump) = find_stake_record_address(
+        &farm.program_id,
+        farm.platform_state.key,
+        staker.nft_mint.key,
+    );
+    assert_eq!(stake_record(&staker.stake_record).bump, record_bump);
+    let (_, rarity_bump) = find_rarity_address(
+        &farm.program_id,
+        farm.platform_state.key,
+        staker.nft_mint.key,
+    );
+    assert_eq!(stake_record(&staker.stake_record).rarity_bump, rarity_bump);
+
+    // a wrong stored bump no longer resolves to the accounts passed in
+    edit::<PlatForm>(&farm.platform_state, |p| {
+        p.vault_bump = vault_bump.wrapping_sub(1)
+    });
+    assert_bad_pda(farm.harvest(&staker));
+    assert_bad_pda(farm.unstake(&staker));
+    let other = farm.staker();
+    assert_bad_pda(farm.stake(&other));
+    edit::<PlatForm>(&farm.platform_state, |p| p.vault_bump = vault_bump);
+
+    edit(&staker.stake_record, |r: &mut StakeRecord| {
+        r.bump = record_bump.wrapping_sub(1)
+    });
+    assert_bad_pda(farm.harvest(&staker));
+    edit(&staker.stake_record, |r: &mut StakeRecord| {
+        r.bump = record_bump
+    });
+
+    // an unset rarity is looked up at its stored bump as well
+    edit(&staker.stake_record, |r: &mut StakeRecord| {
+        r.rarity_bump = rarity_bump.wrapping_sub(1)
+    });
+    assert_bad_pda(farm.harvest(&staker));
+    edit(&staker.stake_record, |r: &mut StakeRecord| {
+        r.rarity_bump = rarity_bump
+    });
+
+    edit(&staker.user_state, |u: &mut UserState| {
+        u.bump = user_bump.wrapping_sub(1)
+    });
+    assert_bad_pda(farm.unstake(&staker));
+    edit(&staker.user_state, |u: &mut UserState| u.bump = user_bump);
+
+    farm.harvest(&staker).unwrap();
+    farm.unstake(&staker).unwrap();
+}
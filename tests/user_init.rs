@@ -0,0 +1,101 @@
+mod common;
+
+use common::*;
+use farm::{
+    error::FarmError,
+    instruction::NftInstruction,
+    state::{find_user_state_address, UserState},
+};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+#[test]
+fn test_initialize_user_ahead_of_stake() {
+    let farm = Farm::with(PlatformOptions::default());
+    let staker = farm.staker();
+
+    farm.init_user(&staker).unwrap();
+    assert_eq!(*staker.user_state.owner, farm.program_id);
+    let (_, bump) =
+        find_user_state_address(&farm.program_id, farm.platform_state.key, staker.user.key);
+    assert_eq!(
+        user_state(&staker.user_state),
+        UserState {
+            is_initialized: true,
+            user: *staker.user.key,
+            total_staked_nft: 0,
+            bump,
+        }
+    );
+
+    farm.stake(&staker).unwrap();
+    assert_eq!(user_state(&staker.user_state).total_staked_nft, 1);
+}
+
+#[test]
+fn test_reinitializing_user_is_rejected() {
+    let farm = Farm::with(PlatformOptions::default());
+    let staker = farm.staker();
+    farm.init_user(&staker).unwrap();
+
+    assert_eq!(farm.init_user(&staker), Err(FarmError::AlreadyInUse.into()));
+
+    // also once the user state is in use
+    farm.stake(&staker).unwrap();
+    assert_eq!(farm.init_user(&staker), Err(FarmError::AlreadyInUse.into()));
+    assert_eq!(user_state(&staker.user_state).total_staked_nft, 1);
+}
+
+#[test]
+fn test_stake_still_initializes_user() {
+    let farm = Farm::with(PlatformOptions::default());
+    let staker = farm.staker();
+
+    farm.stake(&staker).unwrap();
+    assert_eq!(*staker.user_state.owner, farm.program_id);
+    assert_eq!(user_state(&staker.user_state).user, *staker.user.key);
+}
+
+#[test]
+fn test_initialize_user_checks_accounts() {
+    let farm = Farm::with(PlatformOptions::default());
+    let staker = farm.staker();
+    let other = farm.staker();
+
+    // someone else's user state address
+    let mut accounts = farm.init_user_accounts(&staker);
+    accounts[1] = other.user_state.clone();
+    assert_eq!(
+        process(
+            &farm.program_id,
+            &accounts,
+            &NftInstruction::InitializeUser.pack()
+        ),
+        Err(ProgramError::InvalidAccountData)
+    );
+
+    let mut accounts = farm.init_user_accounts(&staker);
+    accounts[0] = with_signer(&staker.user, false);
+    assert_eq!(
+        process(
+            &farm.program_id,
+            &accounts,
+            &NftInstruction::InitializeUser.pack()
+        ),
+        Err(ProgramError::MissingRequiredSignature)
+    );
+
+    let mut accounts = farm.init_user_accounts(&staker);
+    accounts[3] = program_account(Pubkey::new_unique());
+    assert_eq!(
+        process(
+            &farm.program_id,
+            &accounts,
+            &NftInstruction::InitializeUser.pack()
+        ),
+        Err(FarmError::InvalidOwner.into())
+    );
+    assert_eq!(
+        *staker.user_state.owner,
+        solana_program::system_program::id()
+    );
+}
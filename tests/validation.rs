@@ -0,0 +1,387 @@
+mod common;
+
+use common::*;
+use farm::{error::FarmError, instruction::NftInstruction};
+use solana_program::{
+    account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey, system_program,
+};
+
+/// Runs `data` with `accounts[index]` swapped for `replacement` and checks the error.
+fn assert_rejected(
+    farm: &Farm,
+    accounts: Vec<AccountInfo<'static>>,
+    index: usize,
+    replacement: AccountInfo<'static>,
+    data: &[u8],
+    expected: ProgramError,
+) {
+    let mut accounts = accounts;
+    accounts[index] = replacement;
+    assert_eq!(process(&farm.program_id, &accounts, data), Err(expected));
+}
+
+fn staked_farm() -> (Farm, Staker) {
+    let farm = Farm::new();
+    farm.initialize(REWARD_SUPPLY, 10);
+    let staker = farm.staker();
+    farm.stake(&staker).unwrap();
+    (farm, staker)
+}
+
+#[test]
+fn test_valid_accounts_pass() {
+    let (farm, staker) = staked_farm();
+    advance_time(2 * 86_400);
+    farm.harvest(&staker).unwrap();
+    farm.unstake(&staker).unwrap();
+    assert_eq!(token(&staker.nft_account).owner, *staker.user.key);
+    assert_eq!(token(&staker.reward_account).amount, 2 * 86_400 * 10);
+}
+
+#[test]
+fn test_initialize_platform_constraints() {
+    let farm = Farm::new();
+    let data = farm.init_data(REWARD_SUPPLY, 10);
+    let cases: Vec<(usize, AccountInfo<'static>, ProgramError)> = vec![
+        (
+            0,
+            with_signer(&farm.platform_state, false),
+            ProgramError::MissingRequiredSignature,
+        ),
+        (
+            0,
+            with_writable(&farm.platform_state, false),
+            FarmError::AccountNotWritable.into(),
+        ),
+        (
+            1,
+            with_signer(&farm.owner, false),
+            ProgramError::MissingRequiredSignature,
+        ),
+        (
+            1,
+            with_writable(&farm.owner, false),
+            FarmError::AccountNotWritable.into(),
+        ),
+        (
+            2,
+            with_owner(&farm.admin_reward, Pubkey::new_unique()),
+            FarmError::InvalidTokenAccountOwner.into(),
+        ),
+        (
+            3,
+            with_writable(&farm.vault_reward, false),
+            FarmError::AccountNotWritable.into(),
+        ),
+        (
+            3,
+            token_account(Pubkey::new_unique(), &farm.reward_mint, farm.owner.key, 0),
+            FarmError::InvalidTokenAccountOwner.into(),
+        ),
+        (
+            4,
+            account(
+                Pubkey::new_unique(),
+                system_program::id(),
+                0,
+                vec![],
+                false,
+                false,
+            ),
+            ProgramError::InvalidAccountData,
+        ),
+        (
+            5,
+            program_account(Pubkey::new_unique()),
+            ProgramError::IncorrectProgramId,
+        ),
+        (
+            6,
+            program_account(Pubkey::new_unique()),
+            ProgramError::IncorrectProgramId,
+        ),
+    ];
+    for (index, replacement, expected) in cases {
+        assert_rejected(
+            &farm,
+            farm.init_accounts(),
+            index,
+            replacement,
+            &data,
+            expected,
+        );
+    }
+    farm.initialize(REWARD_SUPPLY, 10);
+}
+
+#[test]
+fn test_stake_nft_constraints() {
+    let farm = Farm::new();
+    farm.initialize(REWARD_SUPPLY, 10);
+    let staker = farm.staker();
+    let data = NftInstruction::StakeNft {
+        lock_tier: 0,
+        rarity: None,
+    }
+    .pack();
+    let cases: Vec<(usize, AccountInfo<'static>, ProgramError)> = vec![
+        (
+            0,
+            with_signer(&staker.user, false),
+            ProgramError::MissingRequiredSignature,
+        ),
+        (
+            0,
+            with_writable(&staker.user, false),
+            FarmError::AccountNotWritable.into(),
+        ),
+        (
+            1,
+            with_writable(&staker.user_state, false),
+            FarmError::AccountNotWritable.into(),
+        ),
+        (
+            2,
+            with_writable(&staker.stake_record, false),
+            FarmError::AccountNotWritable.into(),
+        ),
+        (
+            3,
+            with_owner(&farm.platform_state, Pubkey::new_unique()),
+            FarmError::InvalidOwner.into(),
+        ),
+        (
+            3,
+            with_writable(&farm.platform_state, false),
+            FarmError::AccountNotWritable.into(),
+        ),
+        (
+            4,
+            with_writable(&staker.nft_account, false),
+            FarmError::AccountNotWritable.into(),
+        ),
+        (
+            4,
+            with_owner(&staker.nft_account, Pubkey::new_unique()),
+            FarmError::InvalidTokenAccountOwner.into(),
+        ),
+        (
+            5,
+            with_owner(&staker.nft_mint, Pubkey::new_unique()),
+            FarmError::InvalidMint.into(),
+        ),
+        (
+            8,
+            program_account(Pubkey::new_unique()),
+            ProgramError::IncorrectProgramId,
+        ),
+        (
+            9,
+            program_account(Pubkey::new_unique()),
+            ProgramError::IncorrectProgramId,
+        ),
+    ];
+    for (index, replacement, expected) in cases {
+        assert_rejected(
+            &farm,
+            farm.stake_accounts(&staker),
+            index,
+            replacement,
+            &data,
+            expected,
+        );
+    }
+    farm.stake(&staker).unwrap();
+}
+
+#[test]
+fn test_unstake_nft_constraints() {
+    let (farm, staker) = staked_farm();
+    let data = NftInstruction::UnStakeNft.pack();
+    let cases: Vec<(usize, AccountInfo<'static>, ProgramError)> = vec![
+        (
+            0,
+            with_signer(&staker.user, false),
+            ProgramError::MissingRequiredSignature,
+        ),
+        (
+            0,
+            with_writable(&staker.user, false),
+            FarmError::AccountNotWritable.into(),
+        ),
+        (
+            1,
+            with_owner(&staker.user_state, Pubkey::new_unique()),
+            FarmError::InvalidOwner.into(),
+        ),
+        (
+            1,
+            with_writable(&staker.user_state, false),
+            FarmError::AccountNotWritable.into(),
+        ),
+        (
+            2,
+            with_writable(&staker.stake_record, false),
+            FarmError::AccountNotWritable.into(),
+        ),
+        (
+            2,
+            with_owner(&staker.stake_record, Pubkey::new_unique()),
+            FarmError::InvalidOwner.into(),
+        ),
+        (
+            3,
+            with_owner(&farm.platform_state, Pubkey::new_unique()),
+            FarmError::InvalidOwner.into(),
+        ),
+        (
+            3,
+            with_writable(&farm.platform_state, false),
+            FarmError::AccountNotWritable.into(),
+        ),
+        (
+            4,
+            with_owner(&staker.nft_account, Pubkey::new_unique()),
+            FarmError::InvalidTokenAccountOwner.into(),
+        ),
+        (
+            4,
+            with_writable(&staker.nft_account, false),
+            FarmError::AccountNotWritable.into(),
+        ),
+        (
+            7,
+            program_account(Pubkey::new_unique()),
+            ProgramError::IncorrectProgramId,
+        ),
+        (
+            8,
+            program_account(Pubkey::new_unique()),
+            ProgramError::IncorrectProgramId,
+        ),
+        (
+            9,
+            with_owner(&staker.reward_account, Pubkey::new_unique()),
+            FarmError::InvalidTokenAccountOwner.into(),
+        ),
+        (
+            10,
+            with_writable(&farm.vault_reward, false),
+            FarmError::AccountNotWritable.into(),
+        ),
+    ];
+    for (index, replacement, expected) in cases {
+        assert_rejected(
+            &farm,
+            farm.unstake_accounts(&staker),
+            index,
+            replacement,
+            &data,
+            expected,
+        );
+    }
+    farm.unstake(&staker).unwrap();
+}
+
+#[test]
+fn test_harvest_constraints() {
+    let (farm, staker) = staked_farm();
+    let data = NftInstruction::Harvest.pack();
+    let other = farm.staker();
+    let cases: Vec<(usize, AccountInfo<'static>, ProgramError)> = vec![
+        (
+            0,
+            with_signer(&staker.user, false),
+            ProgramError::MissingRequiredSignature,
+        ),
+        (0, other.user.clone(), FarmError::InvalidAuthority.into()),
+        (
+            1,
+            with_writable(&staker.stake_record, false),
+            FarmError::AccountNotWritable.into(),
+        ),
+        (
+            1,
+            with_owner(&staker.stake_record, Pubkey::new_unique()),
+            FarmError::InvalidOwner.into(),
+        ),
+        (
+            2,
+            with_owner(&farm.platform_state, Pubkey::new_unique()),
+            FarmError::InvalidOwner.into(),
+        ),
+        (
+            2,
+            with_writable(&farm.platform_state, false),
+            FarmError::AccountNotWritable.into(),
+        ),
+        (
+            3,
+            with_owner(&staker.reward_account, Pubkey::new_unique()),
+            FarmError::InvalidTokenAccountOwner.into(),
+        ),
+        (
+            4,
+            with_writable(&farm.vault_reward, false),
+            FarmError::AccountNotWritable.into(),
+        ),
+        (
+            6,
+            program_account(Pubkey::new_unique()),
+            ProgramError::IncorrectProgramId,
+        ),
+    ];
+    for (index, replacement, expected) in cases {
+        assert_rejected(
+            &farm,
+            farm.harvest_accounts(&staker),
+            index,
+            replacement,
+            &data,
+            expected,
+        );
+    }
+    farm.harvest(&staker).unwrap();
+}
+
+#[test]
+fn test_update_reward_rate_constraints() {
+    let farm = Farm::new();
+    farm.initialize(REWARD_SUPPLY, 10);
+    let data = NftInstruction::UpdateRewardRate { reward_rate: 20 }.pack();
+    let accounts = vec![farm.owner.clone(), farm.platform_state.clone()];
+    let cases: Vec<(usize, AccountInfo<'static>, ProgramError)> = vec![
+        (
+            0,
+            with_signer(&farm.owner, false),
+            ProgramError::MissingRequiredSignature,
+        ),
+        (
+            0,
+            account(
+                Pubkey::new_unique(),
+                system_program::id(),
+                0,
+                vec![],
+                true,
+                false,
+            ),
+            FarmError::InvalidAuthority.into(),
+        ),
+        (
+            1,
+            with_owner(&farm.platform_state, Pubkey::new_unique()),
+            FarmError::InvalidOwner.into(),
+        ),
+        (
+            1,
+            with_writable(&farm.platform_state, false),
+            FarmError::AccountNotWritable.into(),
+        ),
+    ];
+    for (index, replacement, expected) in cases {
+        assert_rejected(&farm, accounts.clone(), index, replacement, &data, expected);
+    }
+    process(&farm.program_id, &accounts, &data).unwrap();
+    assert_eq!(platform(&farm.platform_state).reward_rate, 20);
+}